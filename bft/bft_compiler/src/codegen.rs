@@ -0,0 +1,243 @@
+//! Emits brainfuck for a list of [Statement]s.
+//!
+//! Every variable gets a cell of its own for the whole program, and
+//! expressions are computed in temporary cells that are handed back, empty,
+//! as soon as they are finished with. Keeping free cells at zero means a
+//! temporary never needs clearing before use.
+
+use crate::parse::{Expr, Kind, Statement};
+use crate::CompileError;
+use std::collections::HashMap;
+
+pub struct Generator {
+    code: String,
+    /// The line and column of the statement that emitted each instruction.
+    positions: Vec<(usize, usize)>,
+    position: (usize, usize),
+    pointer: usize,
+    /// Whether each cell is holding a variable or a temporary.
+    used: Vec<bool>,
+    variables: HashMap<String, usize>,
+    depth: usize,
+}
+
+impl Generator {
+    pub fn new() -> Generator {
+        Generator {
+            code: String::new(),
+            positions: vec![],
+            position: (0, 0),
+            pointer: 0,
+            used: vec![],
+            variables: HashMap::new(),
+            depth: 0,
+        }
+    }
+
+    /// Gets the generated source, the position of the statement behind each
+    /// instruction, and the cell of each variable.
+    pub fn finish(self) -> (String, Vec<(usize, usize)>, HashMap<String, usize>) {
+        (self.code, self.positions, self.variables)
+    }
+
+    fn emit(&mut self, instructions: &str) {
+        // start a new line of output for each statement, to keep it readable
+        if !instructions.is_empty() && !self.code.is_empty() && self.positions.last() != Some(&self.position) {
+            self.code.push('\n');
+        }
+        for c in instructions.chars() {
+            self.code.push(c);
+            self.positions.push(self.position);
+        }
+    }
+
+    /// Claims the lowest free cell, which is always zero.
+    fn alloc(&mut self) -> usize {
+        match self.used.iter().position(|used| !used) {
+            Some(cell) => { self.used[cell] = true; cell },
+            None => { self.used.push(true); self.used.len() - 1 },
+        }
+    }
+
+    /// Hands back a cell, which must already be zero.
+    fn free(&mut self, cell: usize) {
+        self.used[cell] = false;
+    }
+
+    fn move_to(&mut self, cell: usize) {
+        let moves = if cell > self.pointer { ">" } else { "<" };
+        self.emit(&moves.repeat(cell.abs_diff(self.pointer)));
+        self.pointer = cell;
+    }
+
+    /// Adds a constant to a cell, going whichever way round is shorter.
+    fn add(&mut self, cell: usize, amount: u8) {
+        self.move_to(cell);
+        if amount <= 128 {
+            self.emit(&"+".repeat(amount as usize));
+        } else {
+            self.emit(&"-".repeat(256 - amount as usize));
+        }
+    }
+
+    fn clear(&mut self, cell: usize) {
+        self.move_to(cell);
+        self.emit("[-]");
+    }
+
+    /// Empties `source` into each target, adding or subtracting as flagged.
+    fn drain(&mut self, source: usize, targets: &[(usize, bool)]) {
+        self.move_to(source);
+        self.emit("[-");
+        for &(target, add) in targets {
+            self.move_to(target);
+            self.emit(if add { "+" } else { "-" });
+        }
+        self.move_to(source);
+        self.emit("]");
+    }
+
+    /// Adds (or subtracts) `source` into `target`, leaving `source` as it was.
+    fn copy(&mut self, source: usize, target: usize, add: bool) {
+        let spare = self.alloc();
+        self.drain(source, &[(target, add), (spare, true)]);
+        self.drain(spare, &[(source, true)]);
+        self.free(spare);
+    }
+
+    fn variable(&self, name: &str, line: usize, column: usize) -> Result<usize, CompileError> {
+        self.variables.get(name).copied()
+            .ok_or_else(|| CompileError::UndefinedVariable { line, column, name: name.to_string() })
+    }
+
+    /// Adds (or subtracts) the value of an expression into `cell`.
+    fn eval_into(&mut self, expr: &Expr, cell: usize, add: bool) -> Result<(), CompileError> {
+        match expr {
+            Expr::Number(n) => self.add(cell, if add { *n } else { n.wrapping_neg() }),
+            Expr::Variable { name, line, column } => {
+                let source = self.variable(name, *line, *column)?;
+                self.copy(source, cell, add);
+            },
+            Expr::Add(left, right) => {
+                self.eval_into(left, cell, add)?;
+                self.eval_into(right, cell, add)?;
+            },
+            Expr::Sub(left, right) => {
+                self.eval_into(left, cell, add)?;
+                self.eval_into(right, cell, !add)?;
+            },
+        }
+        Ok(())
+    }
+
+    /// Computes an expression into a new temporary cell.
+    fn eval(&mut self, expr: &Expr) -> Result<usize, CompileError> {
+        let cell = self.alloc();
+        self.eval_into(expr, cell, true)?;
+        Ok(cell)
+    }
+
+    /// Sets a cell to the value of an expression, which may use the cell.
+    fn set(&mut self, cell: usize, value: &Expr) -> Result<(), CompileError> {
+        if let Expr::Number(n) = value {
+            self.clear(cell);
+            self.add(cell, *n);
+            return Ok(());
+        }
+
+        let temp = self.eval(value)?;
+        self.clear(cell);
+        self.drain(temp, &[(cell, true)]);
+        self.free(temp);
+        Ok(())
+    }
+
+    pub fn block(&mut self, statements: &[Statement]) -> Result<(), CompileError> {
+        statements.iter().try_for_each(|s| self.statement(s))
+    }
+
+    fn statement(&mut self, statement: &Statement) -> Result<(), CompileError> {
+        self.position = (statement.line, statement.column);
+        match &statement.kind {
+            Kind::Let { name, value } => {
+                if self.variables.contains_key(name) {
+                    return Err(CompileError::Redeclared { line: statement.line, column: statement.column, name: name.clone() });
+                }
+                // the value must not be able to see the variable it defines
+                let temp = self.eval(value)?;
+                let cell = self.alloc();
+                self.variables.insert(name.clone(), cell);
+                // top-level cells start empty, but a loop may run this again
+                if self.depth > 0 {
+                    self.clear(cell);
+                }
+                self.drain(temp, &[(cell, true)]);
+                self.free(temp);
+            },
+            Kind::Assign { name, value } => {
+                let cell = self.variable(name, statement.line, statement.column)?;
+                self.set(cell, value)?;
+            },
+            Kind::If { condition, body } => {
+                let flag = self.eval(condition)?;
+                self.move_to(flag);
+                self.emit("[");
+                self.nested(body)?;
+                self.position = (statement.line, statement.column);
+                self.clear(flag);
+                self.emit("]");
+                self.free(flag);
+            },
+            Kind::While { condition, body } => {
+                let flag = self.eval(condition)?;
+                self.move_to(flag);
+                self.emit("[");
+                self.nested(body)?;
+                self.position = (statement.line, statement.column);
+                self.clear(flag);
+                self.eval_into(condition, flag, true)?;
+                self.move_to(flag);
+                self.emit("]");
+                self.free(flag);
+            },
+            Kind::Print(Expr::Variable { name, line, column }) => {
+                let cell = self.variable(name, *line, *column)?;
+                self.move_to(cell);
+                self.emit(".");
+            },
+            Kind::Print(value) => {
+                let temp = self.eval(value)?;
+                self.move_to(temp);
+                self.emit(".");
+                self.clear(temp);
+                self.free(temp);
+            },
+            Kind::PrintText(text) => {
+                let temp = self.alloc();
+                let mut current = 0u8;
+                for byte in text {
+                    self.add(temp, byte.wrapping_sub(current));
+                    self.emit(".");
+                    current = *byte;
+                }
+                if current != 0 {
+                    self.clear(temp);
+                }
+                self.free(temp);
+            },
+            Kind::Read(name) => {
+                let cell = self.variable(name, statement.line, statement.column)?;
+                self.move_to(cell);
+                self.emit(",");
+            },
+        }
+        Ok(())
+    }
+
+    fn nested(&mut self, body: &[Statement]) -> Result<(), CompileError> {
+        self.depth += 1;
+        let result = self.block(body);
+        self.depth -= 1;
+        result
+    }
+}
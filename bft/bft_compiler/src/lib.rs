@@ -0,0 +1,211 @@
+//! Compiles a small structured language into a [BrainfuckProgram].
+//!
+//! Every value is a byte, and arithmetic wraps just like the cells it is
+//! computed in. A program is a list of statements:
+//!
+//! ```text
+//! # comments run to the end of the line
+//! let n = '5' - '0'     # declare a variable, from numbers, characters,
+//!                       # other variables, `+`, `-` and brackets
+//! n = n - 1             # assign to it
+//! read n                # read a byte of input into a variable
+//! print n               # print a byte
+//! print "text\n"        # print a string
+//! if n { ... }          # run the block if the value is not zero
+//! while n { ... }       # run the block until the value is zero
+//! ```
+//!
+//! Alongside the program, [Compiled] keeps a source map from each of its
+//! instructions back to the line and column of the statement that produced
+//! it.
+//!
+//! ```
+//! let compiled = bft_compiler::compile(&"count", &"
+//!     let n = 3
+//!     while n {
+//!         print '0' + n
+//!         n = n - 1
+//!     }
+//! ").unwrap();
+//! let program = compiled.program();
+//! assert_eq!(compiled.source_line(0), Some(2));
+//! assert_eq!(compiled.source_line(program.get_tokens().len() - 1), Some(3));
+//! assert_eq!(compiled.location(0).unwrap().to_string(), "count:2:5");
+//! ```
+//!
+//! For programs that only need to print something, [text::print_text]
+//! builds a much shorter one directly.
+
+mod codegen;
+mod parse;
+pub mod text;
+
+use bft_types::{BrainfuckProgram, Location};
+use std::collections::HashMap;
+use std::fmt;
+
+/// The ways in which compiling can fail. Each variant records the line
+/// and column of the offending source.
+#[derive(Debug, PartialEq)]
+pub enum CompileError {
+    /// The source is not a valid program.
+    Syntax { line: usize, column: usize, message: String },
+
+    /// A variable was used before it was declared with `let`.
+    UndefinedVariable { line: usize, column: usize, name: String },
+
+    /// A variable was declared with `let` more than once.
+    Redeclared { line: usize, column: usize, name: String },
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CompileError::Syntax { line, column, message } =>
+                write!(f, "{} at line {}, column {}", message, line, column),
+            CompileError::UndefinedVariable { line, column, name } =>
+                write!(f, "undefined variable `{}` at line {}, column {}", name, line, column),
+            CompileError::Redeclared { line, column, name } =>
+                write!(f, "variable `{}` declared again at line {}, column {}", name, line, column),
+        }
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+/// A compiled program, along with its source map.
+pub struct Compiled {
+    program: BrainfuckProgram,
+    positions: Vec<(usize, usize)>,
+    variables: HashMap<String, usize>,
+}
+
+impl Compiled {
+    /// Gets the generated program.
+    pub fn program(&self) -> &BrainfuckProgram {
+        &self.program
+    }
+
+    /// Gets the line of the statement that produced the instruction at
+    /// `index` in [BrainfuckProgram::get_tokens]. The instructions closing
+    /// an `if` or `while` map to the line the block was opened on.
+    pub fn source_line(&self, index: usize) -> Option<usize> {
+        self.positions.get(index).map(|(line, _)| *line)
+    }
+
+    /// Gets where the statement that produced the instruction at `index`
+    /// was written, in the file the program was compiled from, like
+    /// [Compiled::source_line] does for its line.
+    pub fn location(&self, index: usize) -> Option<Location<'_>> {
+        let (line, column) = *self.positions.get(index)?;
+        Some(Location { file: &self.program.name, line, column })
+    }
+
+    /// Gets the cell a variable is stored in.
+    pub fn cell(&self, variable: &str) -> Option<usize> {
+        self.variables.get(variable).copied()
+    }
+}
+
+/// Compiles a program, naming the result `name`.
+pub fn compile(name: &dyn AsRef<str>, source: &dyn AsRef<str>) -> Result<Compiled, CompileError> {
+    let statements = parse::parse(source.as_ref())?;
+    let mut generator = codegen::Generator::new();
+    generator.block(&statements)?;
+
+    let (code, positions, variables) = generator.finish();
+    Ok(Compiled { program: BrainfuckProgram::from_string(name, &code), positions, variables })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{compile, CompileError, Compiled};
+    use bft_interp::BrainfuckInterpreter;
+
+    fn run(compiled: &Compiled, mut input: &[u8]) -> (Vec<u8>, BrainfuckInterpreter<u8>) {
+        let mut interp = BrainfuckInterpreter::new(64, false);
+        let mut output = vec![];
+        interp.interpret(compiled.program(), &mut input, &mut output).unwrap();
+        (output, interp)
+    }
+
+    #[test]
+    fn counts_down() {
+        let compiled = compile(&"count", &"
+            let n = 5
+            while n {
+                print '0' + n
+                if n - 3 { print \",\" }
+                n = n - 1
+            }
+            print \"\\n\"
+        ").unwrap();
+        assert_eq!(run(&compiled, b"").0, b"5,4,32,1,\n");
+    }
+
+    #[test]
+    fn variables_keep_their_cells() {
+        let compiled = compile(&"vars", &"
+            let a = 200
+            let b = a + a - 1   # wraps around
+            a = b - (a - 10)
+            let c = 0
+            c = c - 1
+        ").unwrap();
+        let (_, interp) = run(&compiled, b"");
+        let value = |name| interp.tape()[compiled.cell(name).unwrap()];
+        assert_eq!((value("a"), value("b"), value("c")), (209, 143, 255));
+    }
+
+    #[test]
+    fn echoes_input() {
+        let compiled = compile(&"echo", &"
+            let c = 0
+            read c
+            while c {
+                let upper = c - 32
+                print upper
+                read c
+            }
+        ").unwrap();
+        assert_eq!(run(&compiled, b"shout").0, b"SHOUT");
+    }
+
+    #[test]
+    fn maps_instructions_to_lines() {
+        let compiled = compile(&"map", &"let x = 2\n\nwhile x {\n  x = x - 1\n}\nprint x").unwrap();
+        let lines: Vec<_> = (0..compiled.program().get_tokens().len())
+            .map(|i| compiled.source_line(i).unwrap())
+            .collect();
+        let mut distinct = lines.clone();
+        distinct.dedup();
+        assert_eq!(distinct, vec![1, 3, 4, 3, 6]);
+
+        // the generated source puts each statement on its own line
+        let first_of_print = lines.iter().position(|&l| l == 6).unwrap();
+        assert_eq!(compiled.program().get_tokens()[first_of_print].column(), 1);
+
+        // the closing instructions of a loop point back at its `while`
+        let last_of_loop = lines.iter().rposition(|&l| l == 3).unwrap();
+        assert_eq!(compiled.location(last_of_loop).unwrap().to_string(), "map:3:1");
+        let in_loop = lines.iter().position(|&l| l == 4).unwrap();
+        assert_eq!(compiled.location(in_loop).unwrap().to_string(), "map:4:3");
+        assert_eq!(compiled.location(lines.len()), None);
+    }
+
+    #[test]
+    fn reports_errors() {
+        assert_eq!(
+            compile(&"bad", &"let x = 1\nprint y").err(),
+            Some(CompileError::UndefinedVariable { line: 2, column: 7, name: "y".to_string() })
+        );
+        assert_eq!(
+            compile(&"bad", &"let x = 1\nlet x = 2").err(),
+            Some(CompileError::Redeclared { line: 2, column: 1, name: "x".to_string() })
+        );
+        assert_eq!(
+            compile(&"bad", &"let x = 256").map(|_| ()).unwrap_err().to_string(),
+            "numbers must be between 0 and 255 at line 1, column 9"
+        );
+    }
+}
@@ -0,0 +1,321 @@
+//! Turns source text into a list of [Statement]s.
+
+use crate::CompileError;
+
+/// A value computed into a single cell. Arithmetic wraps, like the cells.
+#[derive(Debug, PartialEq)]
+pub enum Expr {
+    Number(u8),
+    Variable { name: String, line: usize, column: usize },
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Kind {
+    Let { name: String, value: Expr },
+    Assign { name: String, value: Expr },
+    If { condition: Expr, body: Vec<Statement> },
+    While { condition: Expr, body: Vec<Statement> },
+    Print(Expr),
+    PrintText(Vec<u8>),
+    Read(String),
+}
+
+/// A statement along with where it starts.
+#[derive(Debug, PartialEq)]
+pub struct Statement {
+    pub kind: Kind,
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum Token {
+    Word(String),
+    Number(u32),
+    Text(Vec<u8>),
+    Symbol(char),
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Word(word) => format!("`{}`", word),
+            Token::Number(n) => format!("`{}`", n),
+            Token::Text(_) => "a string".to_string(),
+            Token::Symbol(c) => format!("`{}`", c),
+        }
+    }
+}
+
+struct Lexeme {
+    token: Token,
+    line: usize,
+    column: usize,
+}
+
+/// Parses a whole program.
+pub fn parse(source: &str) -> Result<Vec<Statement>, CompileError> {
+    let mut parser = Parser { lexemes: lex(source)?, next: 0, end: end_of(source) };
+    let statements = parser.block(false)?;
+    Ok(statements)
+}
+
+/// The position just past the last character, for errors at the end of input.
+fn end_of(source: &str) -> (usize, usize) {
+    let line = source.split('\n').count();
+    let column = source.split('\n').next_back().map_or(0, |l| l.chars().count()) + 1;
+    (line, column)
+}
+
+fn lex(source: &str) -> Result<Vec<Lexeme>, CompileError> {
+    let mut lexemes = vec![];
+    for (row, line) in source.lines().enumerate() {
+        let mut chars = line.chars().enumerate().peekable();
+        while let Some((col, c)) = chars.next() {
+            let (line, column) = (row + 1, col + 1);
+            let token = match c {
+                '#' => break,
+                c if c.is_whitespace() => continue,
+                c if c.is_ascii_alphabetic() || c == '_' => {
+                    let mut word = c.to_string();
+                    while let Some((_, c)) = chars.next_if(|(_, c)| c.is_ascii_alphanumeric() || *c == '_') {
+                        word.push(c);
+                    }
+                    Token::Word(word)
+                },
+                c if c.is_ascii_digit() => {
+                    let mut number = c.to_digit(10).unwrap();
+                    while let Some((_, c)) = chars.next_if(|(_, c)| c.is_ascii_digit()) {
+                        number = number.saturating_mul(10).saturating_add(c.to_digit(10).unwrap());
+                    }
+                    Token::Number(number)
+                },
+                '\'' => {
+                    let byte = match chars.next() {
+                        Some((_, '\\')) => escape(chars.next().map(|(_, c)| c), line, column)?,
+                        Some((_, c)) if c.is_ascii() && c != '\'' => c as u8,
+                        _ => return Err(syntax(line, column, "expected an ASCII character")),
+                    };
+                    match chars.next() {
+                        Some((_, '\'')) => Token::Number(byte as u32),
+                        _ => return Err(syntax(line, column, "unterminated character")),
+                    }
+                },
+                '"' => {
+                    let mut text = vec![];
+                    loop {
+                        match chars.next() {
+                            Some((_, '"')) => break,
+                            Some((_, '\\')) => text.push(escape(chars.next().map(|(_, c)| c), line, column)?),
+                            Some((_, c)) => {
+                                let mut buf = [0; 4];
+                                text.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                            },
+                            None => return Err(syntax(line, column, "unterminated string")),
+                        }
+                    }
+                    Token::Text(text)
+                },
+                '=' | '+' | '-' | '(' | ')' | '{' | '}' => Token::Symbol(c),
+                other => return Err(syntax(line, column, &format!("unexpected character `{}`", other))),
+            };
+            lexemes.push(Lexeme { token, line, column });
+        }
+    }
+    Ok(lexemes)
+}
+
+fn escape(c: Option<char>, line: usize, column: usize) -> Result<u8, CompileError> {
+    match c {
+        Some('n') => Ok(b'\n'),
+        Some('t') => Ok(b'\t'),
+        Some('0') => Ok(0),
+        Some(c @ ('\\' | '\'' | '"')) => Ok(c as u8),
+        _ => Err(syntax(line, column, "unknown escape")),
+    }
+}
+
+fn syntax(line: usize, column: usize, message: &str) -> CompileError {
+    CompileError::Syntax { line, column, message: message.to_string() }
+}
+
+struct Parser {
+    lexemes: Vec<Lexeme>,
+    next: usize,
+    end: (usize, usize),
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.lexemes.get(self.next).map(|l| &l.token)
+    }
+
+    /// The position of the next token, or the end of input.
+    fn position(&self) -> (usize, usize) {
+        self.lexemes.get(self.next).map_or(self.end, |l| (l.line, l.column))
+    }
+
+    fn unexpected(&self, expected: &str) -> CompileError {
+        let (line, column) = self.position();
+        let found = self.peek().map_or("the end of the program".to_string(), Token::describe);
+        syntax(line, column, &format!("expected {}, found {}", expected, found))
+    }
+
+    fn expect(&mut self, token: Token, expected: &str) -> Result<(), CompileError> {
+        if self.peek() == Some(&token) {
+            self.next += 1;
+            Ok(())
+        } else {
+            Err(self.unexpected(expected))
+        }
+    }
+
+    fn name(&mut self) -> Result<String, CompileError> {
+        match self.peek() {
+            Some(Token::Word(word)) if !is_keyword(word) => {
+                let word = word.clone();
+                self.next += 1;
+                Ok(word)
+            },
+            _ => Err(self.unexpected("a variable name")),
+        }
+    }
+
+    /// Parses statements up to the end of input, or a `}` if `nested`.
+    fn block(&mut self, nested: bool) -> Result<Vec<Statement>, CompileError> {
+        let mut statements = vec![];
+        loop {
+            match self.peek() {
+                None if !nested => return Ok(statements),
+                Some(Token::Symbol('}')) if nested => {
+                    self.next += 1;
+                    return Ok(statements);
+                },
+                _ => statements.push(self.statement()?),
+            }
+        }
+    }
+
+    fn statement(&mut self) -> Result<Statement, CompileError> {
+        let (line, column) = self.position();
+        let keyword = match self.peek() {
+            Some(Token::Word(word)) => word.clone(),
+            _ => return Err(self.unexpected("a statement")),
+        };
+
+        let kind = match keyword.as_str() {
+            "let" => {
+                self.next += 1;
+                let name = self.name()?;
+                self.expect(Token::Symbol('='), "`=`")?;
+                Kind::Let { name, value: self.expr()? }
+            },
+            "if" | "while" => {
+                self.next += 1;
+                let condition = self.expr()?;
+                self.expect(Token::Symbol('{'), "`{`")?;
+                let body = self.block(true)?;
+                if keyword == "if" { Kind::If { condition, body } } else { Kind::While { condition, body } }
+            },
+            "print" => {
+                self.next += 1;
+                match self.peek() {
+                    Some(Token::Text(text)) => {
+                        let text = text.clone();
+                        self.next += 1;
+                        Kind::PrintText(text)
+                    },
+                    _ => Kind::Print(self.expr()?),
+                }
+            },
+            "read" => {
+                self.next += 1;
+                Kind::Read(self.name()?)
+            },
+            _ => {
+                let name = self.name()?;
+                self.expect(Token::Symbol('='), "`=`")?;
+                Kind::Assign { name, value: self.expr()? }
+            },
+        };
+
+        Ok(Statement { kind, line, column })
+    }
+
+    fn expr(&mut self) -> Result<Expr, CompileError> {
+        let mut expr = self.atom()?;
+        loop {
+            match self.peek() {
+                Some(Token::Symbol('+')) => { self.next += 1; expr = Expr::Add(Box::new(expr), Box::new(self.atom()?)) },
+                Some(Token::Symbol('-')) => { self.next += 1; expr = Expr::Sub(Box::new(expr), Box::new(self.atom()?)) },
+                _ => return Ok(expr),
+            }
+        }
+    }
+
+    fn atom(&mut self) -> Result<Expr, CompileError> {
+        let (line, column) = self.position();
+        match self.peek().cloned() {
+            Some(Token::Number(n)) if n <= u8::MAX as u32 => { self.next += 1; Ok(Expr::Number(n as u8)) },
+            Some(Token::Number(_)) => Err(syntax(line, column, "numbers must be between 0 and 255")),
+            Some(Token::Symbol('(')) => {
+                self.next += 1;
+                let expr = self.expr()?;
+                self.expect(Token::Symbol(')'), "`)`")?;
+                Ok(expr)
+            },
+            Some(Token::Word(_)) => Ok(Expr::Variable { name: self.name()?, line, column }),
+            _ => Err(self.unexpected("a number, character or variable")),
+        }
+    }
+}
+
+fn is_keyword(word: &str) -> bool {
+    matches!(word, "let" | "if" | "while" | "print" | "read")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Expr, Kind};
+    use crate::CompileError;
+
+    #[test]
+    fn nested_blocks() {
+        let statements = parse("let x = 'a' + 2 - (1)\nwhile x {\n  if x { print \"hi\\n\" }\n  read x\n}").unwrap();
+        assert_eq!(statements.len(), 2);
+        assert_eq!(statements[0].kind, Kind::Let {
+            name: "x".to_string(),
+            value: Expr::Sub(
+                Box::new(Expr::Add(Box::new(Expr::Number(97)), Box::new(Expr::Number(2)))),
+                Box::new(Expr::Number(1)),
+            ),
+        });
+        match &statements[1].kind {
+            Kind::While { body, .. } => {
+                assert_eq!(body[0].kind, Kind::If {
+                    condition: Expr::Variable { name: "x".to_string(), line: 3, column: 6 },
+                    body: vec![super::Statement { kind: Kind::PrintText(b"hi\n".to_vec()), line: 3, column: 10 }],
+                });
+                assert_eq!((body[1].line, body[1].column), (4, 3));
+            },
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reports_position() {
+        match parse("let x = 1\nwhile x {\n  x = x -\n") {
+            Err(CompileError::Syntax { line, column, message }) => {
+                assert_eq!((line, column), (4, 1));
+                assert_eq!(message, "expected a number, character or variable, found the end of the program");
+            },
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse("let if = 2") {
+            Err(CompileError::Syntax { line: 1, column: 5, .. }) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
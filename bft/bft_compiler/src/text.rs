@@ -0,0 +1,159 @@
+//! Generates short programs that print a fixed string.
+//!
+//! Cell 0 is kept empty as a loop counter, and the bytes are built in a
+//! handful of cells to its right. An optional opening loop fills those
+//! cells with values near clusters of the bytes in the text, then each
+//! byte is printed from whichever cell is cheapest to reach and adjust,
+//! either by adding to it directly or by looping on the counter.
+
+use std::cmp::Ordering;
+
+/// The most cells used to hold bytes, not counting the loop counter.
+const CELLS: usize = 5;
+
+/// Generates a program that prints `text`, trying a range of layouts and
+/// keeping the shortest.
+///
+/// ```
+/// let program = bft_compiler::text::print_text(b"Hello, World!\n");
+/// assert!(program.len() < 150);
+/// ```
+pub fn print_text(text: &[u8]) -> String {
+    let mut best = Emitter::new(&[], 1).print(text);
+    for used in 1..=CELLS {
+        let centers = centers(text, used);
+        for factor in 2..=16 {
+            let candidate = Emitter::new(&centers, factor).print(text);
+            if candidate.len() < best.len() {
+                best = candidate;
+            }
+        }
+    }
+    best
+}
+
+/// Picks up to `count` values close to clusters of the distinct bytes in
+/// the text, starting from an even spread and then refining them a few
+/// times by moving each to the middle of the bytes nearest it.
+fn centers(text: &[u8], count: usize) -> Vec<u8> {
+    let mut distinct = text.to_vec();
+    distinct.sort_unstable();
+    distinct.dedup();
+    if distinct.is_empty() {
+        return vec![];
+    }
+
+    let chunk = distinct.len().div_ceil(count);
+    let mut centers: Vec<u8> = distinct.chunks(chunk).map(|c| c[c.len() / 2]).collect();
+    for _ in 0..8 {
+        let mut clusters = vec![vec![]; centers.len()];
+        for &byte in &distinct {
+            let nearest = (0..centers.len()).min_by_key(|&i| (byte as i32 - centers[i] as i32).abs()).unwrap();
+            clusters[nearest].push(byte);
+        }
+        centers = clusters.iter().filter(|c| !c.is_empty()).map(|c| c[c.len() / 2]).collect();
+    }
+    centers
+}
+
+struct Emitter {
+    code: String,
+    pointer: usize,
+    /// The values of cells 1 onwards.
+    cells: [u8; CELLS],
+}
+
+impl Emitter {
+    /// Starts a program whose opening loop brings the first cells close to
+    /// `centers`, counting down from `factor`.
+    fn new(centers: &[u8], factor: u8) -> Emitter {
+        let mut emitter = Emitter { code: String::new(), pointer: 0, cells: [0; CELLS] };
+        let multiples: Vec<u8> = centers.iter()
+            .map(|&c| ((c as u32 + factor as u32 / 2) / factor as u32).min(255 / factor as u32) as u8)
+            .collect();
+        if multiples.iter().all(|&m| m == 0) {
+            return emitter;
+        }
+
+        emitter.code.push_str(&"+".repeat(factor as usize));
+        emitter.code.push('[');
+        for (cell, &multiple) in multiples.iter().enumerate() {
+            emitter.code.push('>');
+            emitter.code.push_str(&"+".repeat(multiple as usize));
+            emitter.cells[cell] = multiple * factor;
+        }
+        emitter.code.push_str(&"<".repeat(multiples.len()));
+        emitter.code.push_str("-]");
+        emitter
+    }
+
+    fn print(mut self, text: &[u8]) -> String {
+        for &byte in text {
+            let (cell, snippet) = (1..=CELLS)
+                .map(|cell| (cell, self.change(cell, byte)))
+                .min_by(|(_, a), (_, b)| a.len().cmp(&b.len()))
+                .unwrap();
+            self.code.push_str(&snippet);
+            self.code.push('.');
+            self.pointer = cell;
+            self.cells[cell - 1] = byte;
+        }
+        self.code
+    }
+
+    /// The shortest code that sets `cell` to `target` and leaves the
+    /// pointer on it.
+    fn change(&self, cell: usize, target: u8) -> String {
+        let difference = target.wrapping_sub(self.cells[cell - 1]) as i8 as i32;
+        let direct = moves(self.pointer, cell) + &adjust(difference);
+
+        (2..=16)
+            .map(|counter| {
+                let times = difference / counter;
+                moves(self.pointer, 0) + &adjust(counter) + "["
+                    + &moves(0, cell) + &adjust(times) + &moves(cell, 0) + "-]"
+                    + &moves(0, cell) + &adjust(difference - counter * times)
+            })
+            .fold(direct, |best, looped| match looped.len().cmp(&best.len()) {
+                Ordering::Less => looped,
+                _ => best,
+            })
+    }
+}
+
+fn moves(from: usize, to: usize) -> String {
+    if to > from { ">".repeat(to - from) } else { "<".repeat(from - to) }
+}
+
+fn adjust(amount: i32) -> String {
+    if amount > 0 { "+".repeat(amount as usize) } else { "-".repeat(-amount as usize) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::print_text;
+    use bft_interp::BrainfuckInterpreter;
+    use bft_types::BrainfuckProgram;
+
+    fn output(code: &str) -> Vec<u8> {
+        let program = BrainfuckProgram::from_string(&"text", &code);
+        let mut output = vec![];
+        BrainfuckInterpreter::<u8>::new(8, false).interpret(&program, &mut &b""[..], &mut output).unwrap();
+        output
+    }
+
+    #[test]
+    fn prints_text() {
+        for text in [&b""[..], b"a", b"Hello, World!\n", b"\0\xff\x80\x7f", b"zzzzAAAA    ~~~~0000"] {
+            assert_eq!(output(&print_text(text)), text);
+        }
+    }
+
+    #[test]
+    fn reuses_cells() {
+        let program = print_text(b"Hello, World!\n");
+        assert!(program.len() < 150, "{} is too long", program);
+        // repeated bytes are printed again without any more work
+        assert!(print_text(b"aaaa").ends_with("...."));
+    }
+}
@@ -0,0 +1,60 @@
+// Runs a brainfuck program on stdin and stdout through the bft C API.
+//
+//     cargo build -p bft_ffi
+//     cc -Iinclude examples/run.c -L../target/debug -lbft_ffi -o run
+//     LD_LIBRARY_PATH=../target/debug ./run program.b < input
+
+#include <stdio.h>
+#include "bft.h"
+
+static ptrdiff_t read_stdin(void *user_data, uint8_t *buf, size_t len) {
+  size_t read = fread(buf, 1, len, (FILE *)user_data);
+  return ferror((FILE *)user_data) ? -1 : (ptrdiff_t)read;
+}
+
+static ptrdiff_t write_stdout(void *user_data, const uint8_t *buf, size_t len) {
+  size_t written = fwrite(buf, 1, len, (FILE *)user_data);
+  fflush((FILE *)user_data);
+  return written == len ? (ptrdiff_t)written : -1;
+}
+
+int main(int argc, char **argv) {
+  if (argc != 2) {
+    fprintf(stderr, "usage: %s PROGRAM\n", argv[0]);
+    return 2;
+  }
+
+  FILE *file = fopen(argv[1], "rb");
+  if (!file) {
+    perror(argv[1]);
+    return 1;
+  }
+  static uint8_t source[1 << 20];
+  size_t len = fread(source, 1, sizeof source, file);
+  fclose(file);
+
+  BftError *error = NULL;
+  BftProgram *program = bft_program_from_bytes(argv[1], source, len, &error);
+  if (!program) {
+    fprintf(stderr, "%s\n", bft_error_message(error));
+    bft_error_free(error);
+    return 1;
+  }
+
+  BftInterpreter *interp = bft_interpreter_new(0, true);
+  bft_interpreter_set_input(interp, read_stdin, stdin);
+  bft_interpreter_set_output(interp, write_stdout, stdout);
+  bft_interpreter_set_step_limit(interp, 100000000);
+
+  int status = 0;
+  if (!bft_interpreter_run(interp, program, &error)) {
+    fprintf(stderr, "%s:%zu:%zu: %s\n", argv[1], bft_error_line(error), bft_error_column(error),
+            bft_error_message(error));
+    bft_error_free(error);
+    status = 1;
+  }
+
+  bft_interpreter_free(interp);
+  bft_program_free(program);
+  return status;
+}
@@ -0,0 +1,173 @@
+#ifndef BFT_H
+#define BFT_H
+
+/* Generated by cbindgen from the bft_ffi crate. Do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// What went wrong in a [BftError].
+typedef enum BftErrorKind {
+  // A required pointer argument was null.
+  BFT_ERROR_KIND_NULL_ARGUMENT,
+  // The program source is not valid UTF-8.
+  BFT_ERROR_KIND_INVALID_UTF8,
+  // The program contains a loop bracket with no partner.
+  BFT_ERROR_KIND_UNMATCHED_BRACKET,
+  // The pointer was moved left of the first cell.
+  BFT_ERROR_KIND_POINTER_UNDERFLOW,
+  // The pointer was moved right of the last cell of a fixed tape.
+  BFT_ERROR_KIND_POINTER_OVERFLOW,
+  // An input or output callback failed.
+  BFT_ERROR_KIND_IO,
+  // The program was about to exceed its step limit.
+  BFT_ERROR_KIND_STEP_LIMIT,
+  // A cell went out of range under a policy that forbids it.
+  BFT_ERROR_KIND_CELL_OVERFLOW,
+  // The program was found to be stuck in a loop forever.
+  BFT_ERROR_KIND_INFINITE_LOOP,
+} BftErrorKind;
+
+// The details of a failed call.
+typedef struct BftError BftError;
+
+// A brainfuck interpreter along with the callbacks it reads and writes through.
+typedef struct BftInterpreter BftInterpreter;
+
+// A parsed brainfuck program.
+typedef struct BftProgram BftProgram;
+
+// Reads up to `len` bytes of input into `buf`, returning how many were
+// read, zero at the end of the input, or a negative number on failure.
+typedef ptrdiff_t (*BftReadFn)(void *user_data, uint8_t *buf, size_t len);
+
+// Writes up to `len` bytes of output from `buf`, returning how many were
+// written, or a negative number on failure.
+typedef ptrdiff_t (*BftWriteFn)(void *user_data, const uint8_t *buf, size_t len);
+
+// Parses a program from `len` bytes of UTF-8 source. `name` may be null.
+//
+// Returns null on failure, storing the details in `*error` if `error` is
+// not null. The program must be freed with [bft_program_free].
+//
+// # Safety
+//
+// `source` must be valid for reads of `len` bytes, and `name` must be
+// null or a nul-terminated string.
+struct BftProgram *bft_program_from_bytes(const char *name, const uint8_t *source, size_t len, struct BftError **error);
+
+// Frees a program.
+//
+// # Safety
+//
+// `program` must be null or have come from [bft_program_from_bytes], and
+// must not be used again.
+void bft_program_free(struct BftProgram *program);
+
+// Creates an interpreter with `tape_size` cells, or 30000 if zero. An
+// `expandable` tape grows when the pointer moves past its end.
+//
+// Until callbacks are set, the interpreter reads nothing and discards its
+// output. It must be freed with [bft_interpreter_free].
+struct BftInterpreter *bft_interpreter_new(size_t tape_size, bool expandable);
+
+// Frees an interpreter. Its callbacks' `user_data` is left alone.
+//
+// # Safety
+//
+// `interp` must be null or have come from [bft_interpreter_new], and must
+// not be used again.
+void bft_interpreter_free(struct BftInterpreter *interp);
+
+// Sets the callback that programs read input from, passing it `user_data`
+// on every call. A null callback gives programs no input.
+//
+// # Safety
+//
+// `interp` must be a live interpreter, and `read` must be safe to call
+// with `user_data` until the interpreter is freed or the callback replaced.
+void bft_interpreter_set_input(struct BftInterpreter *interp, BftReadFn read, void *user_data);
+
+// Sets the callback that programs write output to, passing it `user_data`
+// on every call. A null callback discards the output.
+//
+// # Safety
+//
+// `interp` must be a live interpreter, and `write` must be safe to call
+// with `user_data` until the interpreter is freed or the callback replaced.
+void bft_interpreter_set_output(struct BftInterpreter *interp, BftWriteFn write, void *user_data);
+
+// Limits how many instructions each run may execute, or removes the
+// limit if `limit` is zero.
+//
+// # Safety
+//
+// `interp` must be a live interpreter.
+void bft_interpreter_set_step_limit(struct BftInterpreter *interp, uint64_t limit);
+
+// Runs a program from its first instruction, on the tape as the last run
+// left it. Returns whether the program finished, storing the details of
+// any failure in `*error` if `error` is not null.
+//
+// # Safety
+//
+// `interp` and `program` must be live, and `error` must be null or valid
+// for writes.
+bool bft_interpreter_run(struct BftInterpreter *interp, const struct BftProgram *program, struct BftError **error);
+
+// Clears the tape and moves the pointer back to the first cell.
+//
+// # Safety
+//
+// `interp` must be a live interpreter.
+void bft_interpreter_reset(struct BftInterpreter *interp);
+
+// Gets the number of instructions executed by the last run.
+//
+// # Safety
+//
+// `interp` must be a live interpreter.
+uint64_t bft_interpreter_steps(const struct BftInterpreter *interp);
+
+// Gets what went wrong.
+//
+// # Safety
+//
+// `error` must be a live error.
+enum BftErrorKind bft_error_kind(const struct BftError *error);
+
+// Gets the line the error happened on, counting from one, or zero if it
+// has no location.
+//
+// # Safety
+//
+// `error` must be a live error.
+size_t bft_error_line(const struct BftError *error);
+
+// Gets the column the error happened in, counting from one, or zero if
+// it has no location.
+//
+// # Safety
+//
+// `error` must be a live error.
+size_t bft_error_column(const struct BftError *error);
+
+// Gets a description of the error, valid until the error is freed.
+//
+// # Safety
+//
+// `error` must be a live error.
+const char *bft_error_message(const struct BftError *error);
+
+// Frees an error.
+//
+// # Safety
+//
+// `error` must be null or have come from this library, and must not be
+// used again.
+void bft_error_free(struct BftError *error);
+
+#endif  /* BFT_H */
@@ -0,0 +1,474 @@
+//! A C API for loading and running brainfuck programs.
+//!
+//! The declarations live in `include/bft.h`, generated from this crate by
+//! [cbindgen](https://github.com/eqrion/cbindgen) with the settings in
+//! `cbindgen.toml`:
+//!
+//! ```text
+//! cbindgen --config cbindgen.toml --crate bft_ffi --output include/bft.h
+//! ```
+//!
+//! Building the crate produces both a shared and a static library to link
+//! against, and `examples/run.c` shows the API in use.
+//!
+//! Every object is created and freed by this library: a [BftProgram] with
+//! [bft_program_free], a [BftInterpreter] with [bft_interpreter_free] and a
+//! [BftError] with [bft_error_free]. Each free function accepts null.
+
+use bft_interp::{BrainfuckInterpreter, InterpError};
+use bft_types::{BrainfuckProgram, ParseError};
+use std::ffi::{c_void, CStr, CString};
+use std::io::{self, Read, Write};
+use std::os::raw::c_char;
+use std::ptr;
+use std::slice;
+
+/// Reads up to `len` bytes of input into `buf`, returning how many were
+/// read, zero at the end of the input, or a negative number on failure.
+pub type BftReadFn = Option<unsafe extern "C" fn(user_data: *mut c_void, buf: *mut u8, len: usize) -> isize>;
+
+/// Writes up to `len` bytes of output from `buf`, returning how many were
+/// written, or a negative number on failure.
+pub type BftWriteFn = Option<unsafe extern "C" fn(user_data: *mut c_void, buf: *const u8, len: usize) -> isize>;
+
+/// What went wrong in a [BftError].
+#[repr(C)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BftErrorKind {
+    /// A required pointer argument was null.
+    NullArgument,
+    /// The program source is not valid UTF-8.
+    InvalidUtf8,
+    /// The program contains a loop bracket with no partner.
+    UnmatchedBracket,
+    /// The pointer was moved left of the first cell.
+    PointerUnderflow,
+    /// The pointer was moved right of the last cell of a fixed tape.
+    PointerOverflow,
+    /// An input or output callback failed.
+    Io,
+    /// The program was about to exceed its step limit.
+    StepLimit,
+    /// A cell went out of range under a policy that forbids it.
+    CellOverflow,
+    /// The program was found to be stuck in a loop forever.
+    InfiniteLoop,
+}
+
+/// The details of a failed call.
+pub struct BftError {
+    kind: BftErrorKind,
+    line: usize,
+    column: usize,
+    message: CString,
+}
+
+impl BftError {
+    fn new(kind: BftErrorKind, line: usize, column: usize, message: String) -> BftError {
+        // none of our messages contain nul bytes, but never fail on one
+        let message = CString::new(message.replace('\0', "")).unwrap_or_default();
+        BftError { kind, line, column, message }
+    }
+
+    fn null_argument(name: &str) -> BftError {
+        BftError::new(BftErrorKind::NullArgument, 0, 0, format!("{} must not be null", name))
+    }
+}
+
+impl From<ParseError> for BftError {
+    fn from(error: ParseError) -> Self {
+        match error {
+            ParseError::InvalidUtf8 { line, column } =>
+                BftError::new(BftErrorKind::InvalidUtf8, line, column, error.to_string()),
+            other => BftError::new(BftErrorKind::Io, 0, 0, other.to_string()),
+        }
+    }
+}
+
+impl From<InterpError> for BftError {
+    fn from(error: InterpError) -> Self {
+        let kind = match error {
+            InterpError::UnmatchedBracket { .. } => BftErrorKind::UnmatchedBracket,
+            InterpError::PointerUnderflow { .. } => BftErrorKind::PointerUnderflow,
+            InterpError::PointerOverflow { .. } => BftErrorKind::PointerOverflow,
+            InterpError::Io { .. } => BftErrorKind::Io,
+            InterpError::StepLimit { .. } => BftErrorKind::StepLimit,
+            InterpError::CellOverflow { .. } => BftErrorKind::CellOverflow,
+            InterpError::InfiniteLoop { .. } => BftErrorKind::InfiniteLoop,
+        };
+        let (line, column) = error.location();
+        BftError::new(kind, line, column, error.to_string())
+    }
+}
+
+/// Hands an error to the caller through `error`, if they asked for it.
+unsafe fn report(error: *mut *mut BftError, details: BftError) {
+    if !error.is_null() {
+        *error = Box::into_raw(Box::new(details));
+    }
+}
+
+/// A parsed brainfuck program.
+pub struct BftProgram(BrainfuckProgram);
+
+/// A brainfuck interpreter along with the callbacks it reads and writes through.
+pub struct BftInterpreter {
+    interp: BrainfuckInterpreter<u8>,
+    input: CallbackReader,
+    output: CallbackWriter,
+}
+
+struct CallbackReader {
+    read: BftReadFn,
+    user_data: *mut c_void,
+}
+
+impl Read for CallbackReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = match self.read {
+            Some(read) => read,
+            None => return Ok(0),
+        };
+        match unsafe { read(self.user_data, buf.as_mut_ptr(), buf.len()) } {
+            n if n < 0 => Err(io::Error::other("input callback failed")),
+            n => Ok((n as usize).min(buf.len())),
+        }
+    }
+}
+
+struct CallbackWriter {
+    write: BftWriteFn,
+    user_data: *mut c_void,
+}
+
+impl Write for CallbackWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let write = match self.write {
+            Some(write) => write,
+            None => return Ok(buf.len()),
+        };
+        match unsafe { write(self.user_data, buf.as_ptr(), buf.len()) } {
+            n if n < 0 => Err(io::Error::other("output callback failed")),
+            n => Ok((n as usize).min(buf.len())),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Parses a program from `len` bytes of UTF-8 source. `name` may be null.
+///
+/// Returns null on failure, storing the details in `*error` if `error` is
+/// not null. The program must be freed with [bft_program_free].
+///
+/// # Safety
+///
+/// `source` must be valid for reads of `len` bytes, and `name` must be
+/// null or a nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn bft_program_from_bytes(name: *const c_char, source: *const u8, len: usize, error: *mut *mut BftError) -> *mut BftProgram {
+    if source.is_null() && len > 0 {
+        report(error, BftError::null_argument("source"));
+        return ptr::null_mut();
+    }
+
+    let name = if name.is_null() { "program".into() } else { CStr::from_ptr(name).to_string_lossy() };
+    let source = if len == 0 { &[] } else { slice::from_raw_parts(source, len) };
+    match BrainfuckProgram::from_bytes(&name, source) {
+        Ok(program) => Box::into_raw(Box::new(BftProgram(program))),
+        Err(e) => {
+            report(error, e.into());
+            ptr::null_mut()
+        },
+    }
+}
+
+/// Frees a program.
+///
+/// # Safety
+///
+/// `program` must be null or have come from [bft_program_from_bytes], and
+/// must not be used again.
+#[no_mangle]
+pub unsafe extern "C" fn bft_program_free(program: *mut BftProgram) {
+    if !program.is_null() {
+        drop(Box::from_raw(program));
+    }
+}
+
+/// Creates an interpreter with `tape_size` cells, or 30000 if zero. An
+/// `expandable` tape grows when the pointer moves past its end.
+///
+/// Until callbacks are set, the interpreter reads nothing and discards its
+/// output. It must be freed with [bft_interpreter_free].
+#[no_mangle]
+pub extern "C" fn bft_interpreter_new(tape_size: usize, expandable: bool) -> *mut BftInterpreter {
+    Box::into_raw(Box::new(BftInterpreter {
+        interp: BrainfuckInterpreter::new(tape_size, expandable),
+        input: CallbackReader { read: None, user_data: ptr::null_mut() },
+        output: CallbackWriter { write: None, user_data: ptr::null_mut() },
+    }))
+}
+
+/// Frees an interpreter. Its callbacks' `user_data` is left alone.
+///
+/// # Safety
+///
+/// `interp` must be null or have come from [bft_interpreter_new], and must
+/// not be used again.
+#[no_mangle]
+pub unsafe extern "C" fn bft_interpreter_free(interp: *mut BftInterpreter) {
+    if !interp.is_null() {
+        drop(Box::from_raw(interp));
+    }
+}
+
+/// Sets the callback that programs read input from, passing it `user_data`
+/// on every call. A null callback gives programs no input.
+///
+/// # Safety
+///
+/// `interp` must be a live interpreter, and `read` must be safe to call
+/// with `user_data` until the interpreter is freed or the callback replaced.
+#[no_mangle]
+pub unsafe extern "C" fn bft_interpreter_set_input(interp: *mut BftInterpreter, read: BftReadFn, user_data: *mut c_void) {
+    if let Some(interp) = interp.as_mut() {
+        interp.input = CallbackReader { read, user_data };
+    }
+}
+
+/// Sets the callback that programs write output to, passing it `user_data`
+/// on every call. A null callback discards the output.
+///
+/// # Safety
+///
+/// `interp` must be a live interpreter, and `write` must be safe to call
+/// with `user_data` until the interpreter is freed or the callback replaced.
+#[no_mangle]
+pub unsafe extern "C" fn bft_interpreter_set_output(interp: *mut BftInterpreter, write: BftWriteFn, user_data: *mut c_void) {
+    if let Some(interp) = interp.as_mut() {
+        interp.output = CallbackWriter { write, user_data };
+    }
+}
+
+/// Limits how many instructions each run may execute, or removes the
+/// limit if `limit` is zero.
+///
+/// # Safety
+///
+/// `interp` must be a live interpreter.
+#[no_mangle]
+pub unsafe extern "C" fn bft_interpreter_set_step_limit(interp: *mut BftInterpreter, limit: u64) {
+    if let Some(interp) = interp.as_mut() {
+        interp.interp.set_step_limit(if limit == 0 { None } else { Some(limit) });
+    }
+}
+
+/// Runs a program from its first instruction, on the tape as the last run
+/// left it. Returns whether the program finished, storing the details of
+/// any failure in `*error` if `error` is not null.
+///
+/// # Safety
+///
+/// `interp` and `program` must be live, and `error` must be null or valid
+/// for writes.
+#[no_mangle]
+pub unsafe extern "C" fn bft_interpreter_run(interp: *mut BftInterpreter, program: *const BftProgram, error: *mut *mut BftError) -> bool {
+    let (interp, program) = match (interp.as_mut(), program.as_ref()) {
+        (Some(interp), Some(program)) => (interp, program),
+        (None, _) => { report(error, BftError::null_argument("interp")); return false },
+        (_, None) => { report(error, BftError::null_argument("program")); return false },
+    };
+
+    match interp.interp.interpret(&program.0, &mut interp.input, &mut interp.output) {
+        Ok(()) => true,
+        Err(e) => {
+            report(error, e.into());
+            false
+        },
+    }
+}
+
+/// Clears the tape and moves the pointer back to the first cell.
+///
+/// # Safety
+///
+/// `interp` must be a live interpreter.
+#[no_mangle]
+pub unsafe extern "C" fn bft_interpreter_reset(interp: *mut BftInterpreter) {
+    if let Some(interp) = interp.as_mut() {
+        interp.interp.reset();
+    }
+}
+
+/// Gets the number of instructions executed by the last run.
+///
+/// # Safety
+///
+/// `interp` must be a live interpreter.
+#[no_mangle]
+pub unsafe extern "C" fn bft_interpreter_steps(interp: *const BftInterpreter) -> u64 {
+    interp.as_ref().map_or(0, |interp| interp.interp.steps())
+}
+
+/// Gets what went wrong.
+///
+/// # Safety
+///
+/// `error` must be a live error.
+#[no_mangle]
+pub unsafe extern "C" fn bft_error_kind(error: *const BftError) -> BftErrorKind {
+    (*error).kind
+}
+
+/// Gets the line the error happened on, counting from one, or zero if it
+/// has no location.
+///
+/// # Safety
+///
+/// `error` must be a live error.
+#[no_mangle]
+pub unsafe extern "C" fn bft_error_line(error: *const BftError) -> usize {
+    (*error).line
+}
+
+/// Gets the column the error happened in, counting from one, or zero if
+/// it has no location.
+///
+/// # Safety
+///
+/// `error` must be a live error.
+#[no_mangle]
+pub unsafe extern "C" fn bft_error_column(error: *const BftError) -> usize {
+    (*error).column
+}
+
+/// Gets a description of the error, valid until the error is freed.
+///
+/// # Safety
+///
+/// `error` must be a live error.
+#[no_mangle]
+pub unsafe extern "C" fn bft_error_message(error: *const BftError) -> *const c_char {
+    (*error).message.as_ptr()
+}
+
+/// Frees an error.
+///
+/// # Safety
+///
+/// `error` must be null or have come from this library, and must not be
+/// used again.
+#[no_mangle]
+pub unsafe extern "C" fn bft_error_free(error: *mut BftError) {
+    if !error.is_null() {
+        drop(Box::from_raw(error));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    unsafe extern "C" fn read_slice(user_data: *mut c_void, buf: *mut u8, len: usize) -> isize {
+        let input = &mut *(user_data as *mut &[u8]);
+        input.read(slice::from_raw_parts_mut(buf, len)).map_or(-1, |n| n as isize)
+    }
+
+    unsafe extern "C" fn write_vec(user_data: *mut c_void, buf: *const u8, len: usize) -> isize {
+        let output = &mut *(user_data as *mut Vec<u8>);
+        output.extend_from_slice(slice::from_raw_parts(buf, len));
+        len as isize
+    }
+
+    unsafe extern "C" fn fail(_: *mut c_void, _: *const u8, _: usize) -> isize {
+        -1
+    }
+
+    fn program(source: &str) -> *mut BftProgram {
+        unsafe { bft_program_from_bytes(ptr::null(), source.as_ptr(), source.len(), ptr::null_mut()) }
+    }
+
+    #[test]
+    fn runs_with_callbacks() {
+        unsafe {
+            let echo = program(",[.,]");
+            let interp = bft_interpreter_new(0, false);
+            let mut input: &[u8] = b"hello";
+            let mut output: Vec<u8> = vec![];
+            bft_interpreter_set_input(interp, Some(read_slice), &mut input as *mut &[u8] as *mut c_void);
+            bft_interpreter_set_output(interp, Some(write_vec), &mut output as *mut Vec<u8> as *mut c_void);
+
+            assert!(bft_interpreter_run(interp, echo, ptr::null_mut()));
+            assert_eq!(output, b"hello");
+            assert_eq!(bft_interpreter_steps(interp), 17);
+
+            bft_interpreter_free(interp);
+            bft_program_free(echo);
+        }
+    }
+
+    #[test]
+    fn reports_errors() {
+        unsafe {
+            let mut error = ptr::null_mut();
+            let invalid = bft_program_from_bytes(ptr::null(), b"+\xff".as_ptr(), 2, &mut error);
+            assert!(invalid.is_null());
+            assert_eq!(bft_error_kind(error), BftErrorKind::InvalidUtf8);
+            assert_eq!((bft_error_line(error), bft_error_column(error)), (1, 2));
+            bft_error_free(error);
+
+            let endless = program("+\n[]");
+            let interp = bft_interpreter_new(0, false);
+            bft_interpreter_set_step_limit(interp, 10);
+            assert!(!bft_interpreter_run(interp, endless, &mut error));
+            assert_eq!(bft_error_kind(error), BftErrorKind::StepLimit);
+            assert_eq!(
+                CStr::from_ptr(bft_error_message(error)).to_str(),
+                Ok("step limit exceeded at line 2, column 2")
+            );
+            bft_error_free(error);
+
+            let print = program(".");
+            bft_interpreter_set_output(interp, Some(fail), ptr::null_mut());
+            assert!(!bft_interpreter_run(interp, print, &mut error));
+            assert_eq!(bft_error_kind(error), BftErrorKind::Io);
+            bft_error_free(error);
+
+            assert!(!bft_interpreter_run(interp, ptr::null(), &mut error));
+            assert_eq!(bft_error_kind(error), BftErrorKind::NullArgument);
+            bft_error_free(error);
+
+            bft_interpreter_free(interp);
+            bft_program_free(endless);
+            bft_program_free(print);
+        }
+    }
+
+    #[test]
+    fn header_declares_every_function() {
+        let header = include_str!("../include/bft.h");
+        let exports = include_str!("lib.rs").split("#[cfg(test)]").next().unwrap();
+        let missing: Vec<_> = exports
+            .split("#[no_mangle]")
+            .skip(1)
+            .filter_map(|item| item.split("fn ").nth(1)?.split('(').next())
+            .filter(|name| !header.contains(&format!("{}(", name)))
+            .collect();
+        assert!(missing.is_empty(), "missing from bft.h: {:?}", missing);
+    }
+
+    #[test]
+    fn example_compiles_against_header() {
+        let dir = env!("CARGO_MANIFEST_DIR");
+        let status = Command::new("cc")
+            .args(["-fsyntax-only", "-Wall", "-Wextra", "-Werror", "-std=c99", "-Iinclude", "examples/run.c"])
+            .current_dir(dir)
+            .status()
+            .expect("a C compiler is needed to check the header");
+        assert!(status.success());
+    }
+}
@@ -0,0 +1,2 @@
+Clearing loops in both directions
++++++[-]+.[+].
@@ -0,0 +1,21 @@
+This is your input file
+
+Anything which is not one of the important characters will be ignored
+by the program
+
+The program is as follows:
+
+   +[-
+     [<<
+      [+
+       [--->]
+      -[<<<]
+     ]
+    ]>>>-
+   ]
+
+I'm guessing that you know where we're going with all this
+
+   >-.---.>..>.<<<<-.<+.>>>>>.>.<<.<-.
+
+But perhaps you're as lost as a little lamb?
\ No newline at end of file
@@ -0,0 +1,2 @@
+Reading past the end of input sets the cell to zero
++,.
@@ -0,0 +1,3 @@
+Multiplies three by four with nested loops
++++[>++++[>+<-]<-]>>
+++++++++++++++++++++++++++++++++++++++++++++++++.
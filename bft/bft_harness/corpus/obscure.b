@@ -0,0 +1,3 @@
+Tests for several obscure problems (Daniel B Cristofani)
+[]++++++++++[>>+>+>++++++[<<+<+++>>>-]<<<<-]
+"A*$";?@![#>>+<<]>[>>]<<<<[>++<[-]]>.>.
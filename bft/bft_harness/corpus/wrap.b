@@ -0,0 +1,2 @@
+Cells wrap around at both ends
+-.+.+.
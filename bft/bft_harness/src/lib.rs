@@ -0,0 +1,249 @@
+//! A differential testing harness for the brainfuck toolchain.
+//!
+//! Runs a corpus of programs, each with an expected input and output,
+//! through every way the workspace has of executing a program, and
+//! reports wherever a [Backend] does not produce the expected output.
+//!
+//! A corpus is a directory of `<name>.b` programs, each alongside a
+//! `<name>.out` file holding its expected output and, optionally, a
+//! `<name>.in` file holding the input to feed it. The standard
+//! conformance corpus lives in this crate's `corpus` directory.
+//!
+//! Alongside the corpus, the crate's tests generate random programs and
+//! check the optimised execution paths against naive interpretation.
+
+use bft_interp::debugger::Debugger;
+use bft_interp::ir::IrProgram;
+use bft_interp::{BrainfuckInterpreter, InterpError};
+use bft_types::BrainfuckProgram;
+use std::fmt;
+use std::fs;
+use std::io::{self, Result};
+use std::path::{Path, PathBuf};
+
+#[cfg(test)]
+mod properties;
+
+/// The number of cells on the tape given to every backend.
+const TAPE_SIZE: usize = 30_000;
+
+/// How many instructions a backend may run by default before a program
+/// is reported as never finishing.
+pub const STEP_LIMIT: u64 = 10_000_000;
+
+/// Creates the interpreter every backend runs on.
+fn interpreter(step_limit: u64) -> BrainfuckInterpreter<u8> {
+    let mut interpreter = BrainfuckInterpreter::new(TAPE_SIZE, false);
+    interpreter.set_step_limit(Some(step_limit));
+    interpreter
+}
+
+/// A program with the input it should be given and the output it should produce.
+pub struct Case {
+    pub program: BrainfuckProgram,
+    pub input: Vec<u8>,
+    pub expected: Vec<u8>,
+}
+
+/// Loads every case in a corpus directory, ordered by name.
+pub fn load_corpus(dir: &dyn AsRef<Path>) -> Result<Vec<Case>> {
+    let mut programs: Vec<PathBuf> = fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<_>>()?;
+    programs.retain(|path| path.extension().is_some_and(|e| e == "b"));
+    programs.sort();
+
+    programs.into_iter()
+        .map(|path| {
+            let input = match fs::read(path.with_extension("in")) {
+                Ok(input) => input,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+                Err(e) => return Err(e),
+            };
+            Ok(Case {
+                program: BrainfuckProgram::from_file(&path)?,
+                input,
+                expected: fs::read(path.with_extension("out"))?,
+            })
+        })
+        .collect()
+}
+
+/// Gets the path of the standard conformance corpus.
+pub fn standard_corpus() -> PathBuf {
+    [env!("CARGO_MANIFEST_DIR"), "corpus"].iter().collect()
+}
+
+/// A way of executing a [BrainfuckProgram].
+pub trait Backend {
+    /// A short name to identify the backend in reports.
+    fn name(&self) -> &'static str;
+
+    /// Runs the program to completion, returning everything it wrote, or
+    /// fails once it has run `step_limit` instructions.
+    fn run(&self, program: &BrainfuckProgram, input: &[u8], step_limit: u64) -> std::result::Result<Vec<u8>, InterpError>;
+}
+
+/// [BrainfuckInterpreter::interpret], the reference implementation.
+pub struct Interpreter;
+
+impl Backend for Interpreter {
+    fn name(&self) -> &'static str { "interpreter" }
+
+    fn run(&self, program: &BrainfuckProgram, mut input: &[u8], step_limit: u64) -> std::result::Result<Vec<u8>, InterpError> {
+        let mut output = vec![];
+        interpreter(step_limit).interpret(program, &mut input, &mut output)?;
+        Ok(output)
+    }
+}
+
+/// [BrainfuckInterpreter::trace], discarding the trace itself.
+pub struct Traced;
+
+impl Backend for Traced {
+    fn name(&self) -> &'static str { "traced" }
+
+    fn run(&self, program: &BrainfuckProgram, mut input: &[u8], step_limit: u64) -> std::result::Result<Vec<u8>, InterpError> {
+        let mut output = vec![];
+        interpreter(step_limit).trace(program, &mut input, &mut output, &mut io::sink())?;
+        Ok(output)
+    }
+}
+
+/// A [Debugger] stepping through the whole program.
+pub struct Stepped;
+
+impl Backend for Stepped {
+    fn name(&self) -> &'static str { "debugger" }
+
+    fn run(&self, program: &BrainfuckProgram, mut input: &[u8], step_limit: u64) -> std::result::Result<Vec<u8>, InterpError> {
+        let mut debugger = Debugger::new(interpreter(step_limit), program, &mut input);
+        while debugger.step()? {}
+        Ok(debugger.output().to_vec())
+    }
+}
+
+/// [BrainfuckInterpreter::interpret_ir] on the optimised [IrProgram].
+pub struct Optimised;
+
+impl Backend for Optimised {
+    fn name(&self) -> &'static str { "ir" }
+
+    fn run(&self, program: &BrainfuckProgram, mut input: &[u8], step_limit: u64) -> std::result::Result<Vec<u8>, InterpError> {
+        let ir = IrProgram::compile(program)?;
+        let mut output = vec![];
+        interpreter(step_limit).interpret_ir(&ir, &mut input, &mut output)?;
+        Ok(output)
+    }
+}
+
+/// Gets every backend in the workspace.
+pub fn backends() -> Vec<Box<dyn Backend>> {
+    vec![Box::new(Interpreter), Box::new(Traced), Box::new(Stepped), Box::new(Optimised)]
+}
+
+/// A backend that did not produce the expected output for a case.
+pub struct Mismatch {
+    pub case: String,
+    pub backend: &'static str,
+    pub expected: Vec<u8>,
+    pub actual: std::result::Result<Vec<u8>, InterpError>,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} on {}:", self.case, self.backend)?;
+        writeln!(f, "  expected {:?}", String::from_utf8_lossy(&self.expected))?;
+        match &self.actual {
+            Ok(actual) => write!(f, "       got {:?}", String::from_utf8_lossy(actual)),
+            Err(e) => write!(f, "    failed {}", e),
+        }
+    }
+}
+
+/// Runs every case through every backend, returning the mismatches.
+///
+/// A program that runs for more than `step_limit` instructions on
+/// [Interpreter] is reported there, and any other backend hitting the
+/// limit too agrees with it, rather than being reported again.
+pub fn check(cases: &[Case], backends: &[Box<dyn Backend>], step_limit: u64) -> Vec<Mismatch> {
+    cases.iter()
+        .flat_map(|case| {
+            let endless = matches!(Interpreter.run(&case.program, &case.input, step_limit), Err(InterpError::StepLimit { .. }));
+            backends.iter().map(move |backend| (case, backend, endless))
+        })
+        .filter_map(|(case, backend, endless)| {
+            let actual = backend.run(&case.program, &case.input, step_limit);
+            match &actual {
+                Ok(output) if *output == case.expected => None,
+                Err(InterpError::StepLimit { .. }) if endless && backend.name() != Interpreter.name() => None,
+                _ => Some(Mismatch {
+                    case: case.program.name.clone(),
+                    backend: backend.name(),
+                    expected: case.expected.clone(),
+                    actual,
+                }),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{backends, check, load_corpus, standard_corpus, Backend, Case, STEP_LIMIT};
+    use bft_interp::InterpError;
+    use bft_types::BrainfuckProgram;
+
+    #[test]
+    fn standard_corpus_conforms() {
+        let cases = load_corpus(&standard_corpus()).unwrap();
+        assert!(cases.len() >= 10);
+
+        let mismatches = check(&cases, &backends(), STEP_LIMIT);
+        let report: Vec<_> = mismatches.iter().map(ToString::to_string).collect();
+        assert!(mismatches.is_empty(), "\n{}", report.join("\n"));
+    }
+
+    /// Drops the last byte of output, like a backend that forgets to flush.
+    struct Truncating;
+
+    impl Backend for Truncating {
+        fn name(&self) -> &'static str { "truncating" }
+
+        fn run(&self, program: &BrainfuckProgram, input: &[u8], step_limit: u64) -> Result<Vec<u8>, InterpError> {
+            let mut output = crate::Interpreter.run(program, input, step_limit)?;
+            output.pop();
+            Ok(output)
+        }
+    }
+
+    #[test]
+    fn reports_endless_programs_once() {
+        let cases = vec![Case {
+            program: BrainfuckProgram::from_string(&"virtual", &"+[]"),
+            input: vec![],
+            expected: vec![],
+        }];
+
+        let mismatches = check(&cases, &backends(), 1000);
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].to_string(), "virtual on interpreter:\n  expected \"\"\n    failed step limit exceeded at line 1, column 3");
+    }
+
+    #[test]
+    fn reports_mismatch() {
+        let cases = vec![Case {
+            program: BrainfuckProgram::from_string(&"virtual", &",.,."),
+            input: b"ab".to_vec(),
+            expected: b"ab".to_vec(),
+        }];
+
+        let mismatches = check(&cases, &[Box::new(crate::Interpreter), Box::new(Truncating)], STEP_LIMIT);
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].backend, "truncating");
+        assert_eq!(
+            mismatches[0].to_string(),
+            "virtual on truncating:\n  expected \"ab\"\n       got \"a\""
+        );
+    }
+}
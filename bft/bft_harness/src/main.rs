@@ -0,0 +1,33 @@
+use bft_harness::{backends, check, load_corpus, standard_corpus, STEP_LIMIT};
+use std::env;
+use std::path::PathBuf;
+use std::process;
+
+/// Checks every corpus directory given on the command line, or the
+/// standard conformance corpus if none are, against every backend.
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut dirs: Vec<PathBuf> = env::args_os().skip(1).map(PathBuf::from).collect();
+    if dirs.is_empty() {
+        dirs.push(standard_corpus());
+    }
+
+    let backends = backends();
+    let mut cases = vec![];
+    for dir in &dirs {
+        cases.extend(load_corpus(dir)?);
+    }
+
+    let mismatches = check(&cases, &backends, STEP_LIMIT);
+    for mismatch in &mismatches {
+        println!("{}", mismatch);
+    }
+
+    println!(
+        "{} cases on {} backends, {} mismatches",
+        cases.len(), backends.len(), mismatches.len(),
+    );
+    if !mismatches.is_empty() {
+        process::exit(1);
+    }
+    Ok(())
+}
@@ -0,0 +1,89 @@
+//! Property tests that generate random programs and check that every
+//! execution path agrees with naive, one-instruction-at-a-time execution.
+
+use bft_interp::ir::IrProgram;
+use bft_interp::BrainfuckInterpreter;
+use bft_types::BrainfuckProgram;
+use proptest::prelude::*;
+
+/// How many instructions the naive interpreter may run before a
+/// generated program is assumed never to finish and is skipped.
+const STEP_LIMIT: u64 = 10_000;
+
+/// A small tape, so that generated programs regularly run off either end.
+const TAPE_SIZE: usize = 16;
+
+/// Generates programs with balanced, arbitrarily nested loops.
+fn program() -> impl Strategy<Value = String> {
+    let straight = prop::collection::vec(prop::sample::select(vec!['+', '-', '>', '<', '.', ',']), 0..8)
+        .prop_map(|instructions| instructions.into_iter().collect::<String>());
+
+    straight.prop_recursive(4, 64, 4, |inner| {
+        prop::collection::vec(
+            prop_oneof![inner.clone(), inner.prop_map(|body| format!("[{}]", body))],
+            1..4,
+        )
+        .prop_map(|parts| parts.concat())
+    })
+}
+
+/// Generates arbitrary source text, mostly instructions but with comments
+/// and line breaks mixed in.
+fn source() -> impl Strategy<Value = String> {
+    prop::collection::vec(
+        prop_oneof![
+            4 => prop::sample::select(vec!['+', '-', '>', '<', '.', ',', '[', ']']),
+            1 => prop::sample::select(vec!['a', ' ', '\n', '#', '\t', 'é']),
+        ],
+        0..64,
+    )
+    .prop_map(|chars| chars.into_iter().collect())
+}
+
+/// The observable result of a run: its output or error, and the final tape and pointer.
+type Outcome = (Result<Vec<u8>, String>, Vec<u8>, usize);
+
+fn outcome(interp: BrainfuckInterpreter<u8>, result: Result<Vec<u8>, String>) -> Outcome {
+    (result, interp.tape().to_vec(), interp.tape_index())
+}
+
+proptest! {
+    #[test]
+    fn optimised_matches_naive(
+        source in program(),
+        input in prop::collection::vec(any::<u8>(), 0..8),
+        expandable in any::<bool>(),
+    ) {
+        let program = BrainfuckProgram::from_string(&"generated", &source);
+
+        let mut naive: BrainfuckInterpreter<u8> = BrainfuckInterpreter::new(TAPE_SIZE, expandable);
+        naive.set_step_limit(Some(STEP_LIMIT));
+        let mut output = vec![];
+        let result = naive.interpret(&program, &mut &input[..], &mut output);
+        if naive.steps() >= STEP_LIMIT {
+            return Ok(());
+        }
+        let naive = outcome(naive, result.map(|_| output).map_err(|e| e.to_string()));
+
+        // every optimised operation stands for at least one raw instruction,
+        // so a program that finished naively finishes within the same limit
+        let ir = IrProgram::compile(&program).unwrap();
+        let mut optimised: BrainfuckInterpreter<u8> = BrainfuckInterpreter::new(TAPE_SIZE, expandable);
+        optimised.set_step_limit(Some(STEP_LIMIT));
+        let mut output = vec![];
+        let result = optimised.interpret_ir(&ir, &mut &input[..], &mut output);
+        let optimised = outcome(optimised, result.map(|_| output).map_err(|e| e.to_string()));
+
+        prop_assert_eq!(naive, optimised);
+    }
+
+    #[test]
+    fn printing_is_idempotent(source in source()) {
+        let printed = BrainfuckProgram::from_string(&"generated", &source).to_string();
+        let reprinted = BrainfuckProgram::from_string(&"generated", &printed).to_string();
+        prop_assert_eq!(&printed, &reprinted);
+
+        let instructions: String = source.chars().filter(|c| "+-<>.,[]".contains(*c)).collect();
+        prop_assert_eq!(printed, instructions);
+    }
+}
@@ -0,0 +1,281 @@
+//! Works out where the pointer can be, without running the program.
+//!
+//! Each loop is summarised by how far its body can move the pointer and
+//! where it can end up after any number of iterations: a loop whose body
+//! always comes back to where it started stays put, while one that drifts
+//! (like `[>]`) can end up arbitrarily far in that direction. Those
+//! summaries then give a range of offsets from the starting cell for every
+//! operation in an [IrProgram], and for the program as a whole.
+//!
+//! The ranges are conservative, so a program may never actually reach the
+//! ends of them, but it can never go outside them.
+
+use crate::ir::{IrProgram, Node, Op};
+use crate::InterpError;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// A range of pointer offsets, where [None] means the range is unbounded
+/// in that direction.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Offsets {
+    pub min: Option<isize>,
+    pub max: Option<isize>,
+}
+
+impl Offsets {
+    const ZERO: Offsets = Offsets { min: Some(0), max: Some(0) };
+
+    fn shift(self, by: isize) -> Offsets {
+        Offsets { min: self.min.map(|m| m.saturating_add(by)), max: self.max.map(|m| m.saturating_add(by)) }
+    }
+
+    fn plus(self, other: Offsets) -> Offsets {
+        Offsets {
+            min: self.min.zip(other.min).map(|(a, b)| a.saturating_add(b)),
+            max: self.max.zip(other.max).map(|(a, b)| a.saturating_add(b)),
+        }
+    }
+
+    fn join(self, other: Offsets) -> Offsets {
+        Offsets {
+            min: self.min.zip(other.min).map(|(a, b)| a.min(b)),
+            max: self.max.zip(other.max).map(|(a, b)| a.max(b)),
+        }
+    }
+
+    /// Whether starting at cell `start` of a tape with `cells` cells, every
+    /// offset in the range is on the tape.
+    ///
+    /// ```
+    /// # use bft_interp::bounds::Offsets;
+    /// let offsets = Offsets { min: Some(-2), max: Some(3) };
+    /// assert!(offsets.fits(2, 6));
+    /// assert!(!offsets.fits(1, 6));
+    /// assert!(!Offsets { min: Some(0), max: None }.fits(0, 30000));
+    /// ```
+    pub fn fits(&self, start: usize, cells: usize) -> bool {
+        match (self.min, self.max) {
+            (Some(min), Some(max)) => start as isize + min >= 0 && (start as isize + max) < cells as isize,
+            _ => false,
+        }
+    }
+}
+
+/// The result of analysing where the pointer can go in an [IrProgram].
+///
+/// ```
+/// # use bft_interp::bounds::Offsets;
+/// # use bft_interp::ir::IrProgram;
+/// # use bft_types::BrainfuckProgram;
+/// let program = BrainfuckProgram::from_string(&"sum", &">>+[<+>-]<<");
+/// let ir = IrProgram::compile(&program).unwrap();
+/// let bounds = ir.bounds();
+/// assert_eq!(bounds.program(), Offsets { min: Some(0), max: Some(2) });
+/// assert_eq!(bounds.loop_offsets(2), Some(Offsets { min: Some(-1), max: Some(0) }));
+/// ```
+#[derive(Debug, PartialEq)]
+pub struct Bounds {
+    /// For each loop, where its body can move the pointer in one iteration
+    /// and where it can be once the loop ends, relative to the loop's start.
+    loops: Vec<Option<(Offsets, Offsets)>>,
+    positions: Vec<Offsets>,
+    program: Offsets,
+}
+
+impl Bounds {
+    /// Analyses a list of operations, whose loops must match up.
+    pub(crate) fn analyse(nodes: &[Node]) -> Bounds {
+        let mut bounds = Bounds {
+            loops: vec![None; nodes.len()],
+            positions: vec![Offsets::ZERO; nodes.len()],
+            program: Offsets::ZERO,
+        };
+
+        // loops close in order from the innermost out, so every loop
+        // nested in a body is summarised before the body itself
+        for (end, node) in nodes.iter().enumerate() {
+            if let Op::LoopEnd(start) = node.op {
+                let (exit, visited) = bounds.walk(nodes, start + 1, end, Offsets::ZERO, false);
+                let down = exit.min.is_none_or(|m| m < 0);
+                let up = exit.max.is_none_or(|m| m > 0);
+                let ends = Offsets { min: if down { None } else { Some(0) }, max: if up { None } else { Some(0) } };
+                let inside = Offsets { min: visited.min.filter(|_| !down), max: visited.max.filter(|_| !up) };
+                bounds.loops[start] = Some((inside, ends));
+            }
+        }
+
+        bounds.program = bounds.walk(nodes, 0, nodes.len(), Offsets::ZERO, true).1;
+        bounds
+    }
+
+    /// Follows the pointer through `nodes[from..to]` from `entry`, giving
+    /// where it ends up and every offset it passes through. When `record`
+    /// is set, the position at each node is kept too.
+    fn walk(&mut self, nodes: &[Node], from: usize, to: usize, entry: Offsets, record: bool) -> (Offsets, Offsets) {
+        let mut position = entry;
+        let mut visited = entry;
+        let mut index = from;
+
+        while index < to {
+            match nodes[index].op {
+                Op::Right(distance) => position = position.shift(distance as isize),
+                Op::Left(distance) => position = position.shift(-(distance as isize)),
+                Op::LoopStart(end) => {
+                    let (inside, ends) = self.loops[index].expect("inner loops are summarised first");
+                    visited = visited.join(position.plus(inside));
+                    let head = position.plus(ends);
+                    if record {
+                        self.positions[index] = head;
+                        self.positions[end] = head;
+                        self.walk(nodes, index + 1, end, head, true);
+                    }
+                    position = head;
+                    index = end + 1;
+                    continue;
+                },
+                _ => (),
+            }
+
+            visited = visited.join(position);
+            if record {
+                self.positions[index] = position;
+            }
+            index += 1;
+        }
+
+        (position, visited)
+    }
+
+    /// Gets every offset from the starting cell that the pointer can reach.
+    pub fn program(&self) -> Offsets {
+        self.program
+    }
+
+    /// Gets the offsets the pointer can reach inside the loop starting at
+    /// the node at `start`, relative to where the loop is first reached.
+    pub fn loop_offsets(&self, start: usize) -> Option<Offsets> {
+        self.loops.get(start).copied().flatten().map(|(inside, _)| inside)
+    }
+
+    /// Gets the offsets from the starting cell that the pointer can be at
+    /// while the node at `index` runs, or once it has moved for a move.
+    pub fn position(&self, index: usize) -> Option<Offsets> {
+        self.positions.get(index).copied()
+    }
+}
+
+/// Finds the moves that are certain to step off a fixed tape of
+/// `tape_size` cells, when the program starts on the first cell. Each is
+/// reported as the error running the program would give, at the
+/// instruction by which the pointer is sure to have left the tape.
+///
+/// ```
+/// # use bft_interp::bounds::failures;
+/// # use bft_interp::ir::IrProgram;
+/// # use bft_types::BrainfuckProgram;
+/// let program = BrainfuckProgram::from_string(&"sum", &"+[>+<-]\n>><<<<");
+/// let ir = IrProgram::compile(&program).unwrap();
+/// let errors: Vec<_> = failures(&ir, 8).iter().map(ToString::to_string).collect();
+/// assert_eq!(errors, vec!["pointer moved left of the first cell at line 2, column 5"]);
+/// ```
+pub fn failures(ir: &IrProgram, tape_size: usize) -> Vec<InterpError> {
+    let tokens = ir.program().get_tokens();
+    let last = tape_size as isize - 1;
+
+    ir.nodes().iter().enumerate().filter_map(|(index, node)| {
+        let position = ir.bounds().position(index)?;
+        let step = match node.op {
+            Op::Left(distance) => match position.max {
+                Some(max) if max < 0 => (max + distance as isize).clamp(0, distance as isize - 1),
+                _ => return None,
+            },
+            Op::Right(distance) => match position.min {
+                Some(min) if min > last => (last - (min - distance as isize)).clamp(0, distance as isize - 1),
+                _ => return None,
+            },
+            _ => return None,
+        };
+
+        let index = node.token + step as usize;
+        let (line, column) = (tokens[index].line(), tokens[index].column());
+        Some(match node.op {
+            Op::Left(_) => InterpError::PointerUnderflow { line, column, token: index },
+            _ => InterpError::PointerOverflow { line, column, token: index },
+        })
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{failures, Offsets};
+    use crate::ir::IrProgram;
+    use crate::{BrainfuckInterpreter, InterpError};
+    use bft_types::BrainfuckProgram;
+
+    fn offsets(min: Option<isize>, max: Option<isize>) -> Offsets {
+        Offsets { min, max }
+    }
+
+    #[test]
+    fn summarises_loops() {
+        let program = BrainfuckProgram::from_string(&"test", &"+[>[-]<<]>>[[>]+]");
+        let ir = IrProgram::compile(&program).unwrap();
+        let bounds = ir.bounds();
+        // each iteration ends a cell further left
+        assert_eq!(bounds.loop_offsets(1), Some(offsets(None, Some(1))));
+        // `[>]` drifts right, and takes the loop around it with it
+        assert_eq!(bounds.loop_offsets(8), Some(offsets(Some(0), None)));
+        assert_eq!(bounds.loop_offsets(7), Some(offsets(Some(0), None)));
+        assert_eq!(bounds.program(), offsets(None, None));
+        assert_eq!(bounds.loop_offsets(0), None);
+    }
+
+    #[test]
+    fn balanced_loops_stay_put() {
+        let program = BrainfuckProgram::from_string(&"test", &">>++[->[->+<]<]>>>.");
+        let ir = IrProgram::compile(&program).unwrap();
+        assert_eq!(ir.bounds().program(), offsets(Some(0), Some(5)));
+        assert_eq!(ir.bounds().position(ir.nodes().len() - 1), Some(offsets(Some(5), Some(5))));
+    }
+
+    #[test]
+    fn finds_certain_failures() {
+        // matches the location the interpreter reports, for the same fixed tape
+        for source in [">>>\n<<<<<", "+[>+<-]>>\n>>>", "+[-]<", ">>>>+[<<<<<<]"] {
+            let program = BrainfuckProgram::from_string(&"test", &source);
+            let ir = IrProgram::compile(&program).unwrap();
+            let mut interp = BrainfuckInterpreter::<u8>::new(5, false);
+            let error = interp.interpret(&program, &mut std::io::empty(), &mut vec![]).unwrap_err();
+            assert_eq!(failures(&ir, 5).iter().map(ToString::to_string).collect::<Vec<_>>(), vec![error.to_string()]);
+        }
+
+        // a move in a loop fails whenever it runs, even if it never does
+        let program = BrainfuckProgram::from_string(&"test", &"+[<+>-]");
+        let found = failures(&IrProgram::compile(&program).unwrap(), 5);
+        assert!(matches!(found[..], [InterpError::PointerUnderflow { line: 1, column: 3, .. }]));
+
+        // these may or may not fail, depending on the input
+        for source in ["+[>+]", ">>,[<,]", ">>>>[<<<]"] {
+            let program = BrainfuckProgram::from_string(&"test", &source);
+            assert!(failures(&IrProgram::compile(&program).unwrap(), 5).is_empty(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn runs_unchecked_when_in_bounds() {
+        let program = BrainfuckProgram::from_string(&"test", &"++[>+++[>+<-]<-]>>.");
+        let ir = IrProgram::compile(&program).unwrap();
+        let mut interp = BrainfuckInterpreter::<u8>::new(3, false);
+        let mut output = vec![];
+        interp.interpret_ir(&ir, &mut std::io::empty(), &mut output).unwrap();
+        assert_eq!(output, vec![6]);
+
+        // a tape that's too short still fails as it should
+        let mut interp = BrainfuckInterpreter::<u8>::new(2, false);
+        match interp.interpret_ir(&ir, &mut std::io::empty(), &mut vec![]) {
+            Err(InterpError::PointerOverflow { line: 1, column: 9, .. }) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,134 @@
+//! Spots a program going round and round without getting anywhere.
+//!
+//! Without input, a program is entirely determined by its program counter,
+//! pointer and tape, so if those ever come back exactly as they were, the
+//! program will repeat the same steps forever. Comparing every state
+//! against every earlier one would be far too slow, so states are only
+//! compared each time a loop jumps back to its start, and only ever against
+//! one saved state, which is replaced after twice as many jumps each time
+//! (Brent's algorithm). That still finds every such cycle, within a few
+//! times its length.
+//!
+//! Rather than keep a copy of the whole tape, the saved state only records
+//! the cells that have changed since, along with their values back then.
+
+use alloc::collections::BTreeMap;
+
+/// Where the program was when the saved state was taken.
+struct Saved {
+    program_counter: usize,
+    tape_index: usize,
+}
+
+pub(crate) struct CycleDetector<T> {
+    saved: Option<Saved>,
+    /// The values cells had when the state was saved, for those changed since.
+    changed: BTreeMap<usize, T>,
+    /// The number of jumps to allow before saving a new state.
+    power: u64,
+    jumps: u64,
+    /// The range of instructions run since the state was saved.
+    lowest: usize,
+    highest: usize,
+}
+
+impl<T> CycleDetector<T>
+where T: Clone, T: PartialEq {
+    pub(crate) fn new() -> CycleDetector<T> {
+        CycleDetector { saved: None, changed: BTreeMap::new(), power: 1, jumps: 0, lowest: 0, highest: 0 }
+    }
+
+    /// Drops the saved state, since the program has done something that
+    /// can't be repeated, like reading input, or has been moved elsewhere.
+    pub(crate) fn forget(&mut self) {
+        self.saved = None;
+        self.changed.clear();
+        self.power = 1;
+        self.jumps = 0;
+    }
+
+    /// Notes that an instruction is about to run.
+    pub(crate) fn visit(&mut self, program_counter: usize) {
+        self.lowest = self.lowest.min(program_counter);
+        self.highest = self.highest.max(program_counter);
+    }
+
+    /// Notes that a cell is about to change from `old`.
+    pub(crate) fn write(&mut self, index: usize, old: &T) {
+        if self.saved.is_some() {
+            self.changed.entry(index).or_insert_with(|| old.clone());
+        }
+    }
+
+    /// Notes that the loop ending at `program_counter` is jumping back to
+    /// its start. If the program has been here before in the same state,
+    /// gives the range of instructions it has run in between.
+    pub(crate) fn jump(&mut self, program_counter: usize, tape_index: usize, tape: &[T]) -> Option<(usize, usize)> {
+        if let Some(saved) = &self.saved {
+            let same = saved.program_counter == program_counter
+                && saved.tape_index == tape_index
+                && self.changed.iter().all(|(&index, old)| tape[index] == *old);
+            if same {
+                return Some((self.lowest, self.highest));
+            }
+
+            self.jumps += 1;
+            if self.jumps < self.power {
+                return None;
+            }
+            self.power *= 2;
+        }
+
+        self.saved = Some(Saved { program_counter, tape_index });
+        self.changed.clear();
+        self.jumps = 0;
+        self.lowest = program_counter;
+        self.highest = program_counter;
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ir::IrProgram;
+    use crate::BrainfuckInterpreter;
+    use bft_types::BrainfuckProgram;
+
+    /// Runs a program both directly and as an [IrProgram], checking they agree.
+    fn run(source: &str, input: &[u8], expandable: bool) -> Result<(), String> {
+        let program = BrainfuckProgram::from_string(&"./virtual", &source);
+        let results: Vec<_> = [false, true].iter().map(|&ir| {
+            let mut interp = BrainfuckInterpreter::<u8>::new(8, expandable);
+            interp.set_cycle_detection(true);
+            interp.set_step_limit(Some(100_000));
+            let result = if ir {
+                interp.interpret_ir(&IrProgram::compile(&program).unwrap(), &mut &input[..], &mut vec![])
+            } else {
+                interp.interpret(&program, &mut &input[..], &mut vec![])
+            };
+            result.map_err(|e| e.to_string())
+        }).collect();
+        assert_eq!(results[0], results[1], "{}", source);
+        results[0].clone()
+    }
+
+    #[test]
+    fn blames_the_loop_going_round() {
+        let stuck = |line, column| Err(format!("stuck in an endless loop at line {}, column {}", line, column));
+        assert_eq!(run("+[]", b"", false), stuck(1, 2));
+        // only the outer loop repeats, clearing its inner loop each time
+        assert_eq!(run("+[>+[-]<]", b"", false), stuck(1, 2));
+        assert_eq!(run(">+[<\n+[-+]]", b"", false), stuck(2, 2));
+        // cells wrapping round come back to where they were, eventually
+        assert_eq!(run("+[>++++++<]", b"", false), stuck(1, 2));
+    }
+
+    #[test]
+    fn leaves_other_programs_alone() {
+        assert_eq!(run("-[-]>++++[>++++<-]>[<+>[-]]", b"", false), Ok(()));
+        // each time round reads something new, even if it happens to be the same
+        assert!(run("+[,+]", b"ab", false).unwrap_err().starts_with("step limit exceeded"));
+        // wandering off along a growing tape never repeats
+        assert!(run("+[>+]", b"", true).unwrap_err().starts_with("step limit exceeded"));
+    }
+}
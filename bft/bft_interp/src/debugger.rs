@@ -0,0 +1,413 @@
+//! A single-stepping debugger that can also run programs backwards.
+
+use crate::io::{self, Read};
+use crate::{BrainfuckInterpreter, ByteCell, IncDec, InterpError};
+use alloc::collections::{BTreeSet, VecDeque};
+use alloc::vec;
+use alloc::vec::Vec;
+use bft_types::{BrainfuckProgram, RawInstruction};
+
+/// The number of steps remembered by default, to bound the memory used by
+/// a program left running for a long time.
+pub const HISTORY_LIMIT: usize = 1 << 20;
+
+/// Everything needed to undo a single executed instruction.
+struct Undo<T> {
+    program_counter: usize,
+    /// The interpreter's step count, so the step limit applies the same
+    /// way when the instruction is run again.
+    steps: u64,
+    tape_index: usize,
+    tape_len: usize,
+    cell: T,
+    /// The byte consumed by an input instruction.
+    input: Option<u8>,
+    /// Whether an output instruction wrote a byte.
+    output: bool,
+}
+
+/// Why the debugger stopped running.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Stop {
+    /// The next instruction to execute has a breakpoint on it.
+    Breakpoint(usize),
+    /// The program ran to completion.
+    Finished,
+    /// Running backwards reached the start of the program, or the oldest
+    /// step still remembered.
+    Start,
+}
+
+/// Drives a [BrainfuckInterpreter] through a [BrainfuckProgram] one
+/// instruction at a time, recording enough to undo every step.
+///
+/// The program's output is collected by the debugger rather than written
+/// anywhere, so that stepping backwards over an output instruction can
+/// take the byte back again. Bytes consumed from the input are likewise
+/// remembered and fed back in when those steps are replayed. Only the
+/// most recent steps can be undone, up to [HISTORY_LIMIT] unless changed
+/// with [Debugger::set_history_limit].
+///
+/// ```
+/// # use bft_interp::{BrainfuckInterpreter, debugger::Debugger};
+/// # use bft_types::BrainfuckProgram;
+/// let program = BrainfuckProgram::from_string(&"virtual", &"+++.>+");
+/// let mut input = std::io::empty();
+/// let mut debugger = Debugger::new(BrainfuckInterpreter::<u8>::new(0, false), &program, &mut input);
+/// debugger.run().unwrap();
+/// assert_eq!(debugger.interpreter().tape()[..2], [3, 1]);
+///
+/// debugger.reverse_step();
+/// debugger.reverse_step();
+/// debugger.reverse_step();
+/// assert_eq!(debugger.interpreter().tape()[..2], [3, 0]);
+/// assert_eq!(debugger.output(), b"");
+/// ```
+pub struct Debugger<'a, T> {
+    interp: BrainfuckInterpreter<T>,
+    program: &'a BrainfuckProgram,
+    input: &'a mut dyn Read,
+    /// Bytes given back by stepping backwards, to be read again first.
+    replay: Vec<u8>,
+    /// Every byte taken from `input`, to feed in again after a restart.
+    read: Vec<u8>,
+    input_read: usize,
+    /// The tape and pointer as they were before the first step.
+    start: (Vec<T>, usize),
+    output: Vec<u8>,
+    history: VecDeque<Undo<T>>,
+    history_limit: usize,
+    steps: usize,
+    breakpoints: BTreeSet<usize>,
+}
+
+impl<'a, T> Debugger<'a, T>
+where T: IncDec, T: ByteCell, T: Default, T: Clone, T: PartialEq {
+
+    /// Creates a debugger positioned at the first instruction of the program.
+    pub fn new(mut interp: BrainfuckInterpreter<T>, program: &'a BrainfuckProgram, input: &'a mut dyn Read) -> Debugger<'a, T> {
+        interp.program_counter = 0;
+        interp.steps = 0;
+        interp.forget_cycles();
+        let start = (interp.tape.clone(), interp.tape_index);
+        Debugger {
+            interp,
+            program,
+            input,
+            replay: vec![],
+            read: vec![],
+            input_read: 0,
+            start,
+            output: vec![],
+            history: VecDeque::new(),
+            history_limit: HISTORY_LIMIT,
+            steps: 0,
+            breakpoints: BTreeSet::new(),
+        }
+    }
+
+    /// Sets the number of steps remembered to step back over, forgetting
+    /// the oldest ones beyond it.
+    pub fn set_history_limit(&mut self, limit: usize) {
+        self.history_limit = limit;
+        while self.history.len() > limit {
+            self.history.pop_front();
+        }
+    }
+
+    /// Executes the next instruction, returning `false` if the program
+    /// had already finished.
+    pub fn step(&mut self) -> Result<bool, InterpError> {
+        let token = match self.program.get_tokens().get(self.interp.program_counter) {
+            Some(token) => token,
+            None => return Ok(false),
+        };
+
+        let mut undo = Undo {
+            program_counter: self.interp.program_counter,
+            steps: self.interp.steps,
+            tape_index: self.interp.tape_index,
+            tape_len: self.interp.tape.len(),
+            cell: self.interp.tape[self.interp.tape_index].clone(),
+            input: None,
+            output: false,
+        };
+
+        let result = self.execute(token.instruction, &mut undo);
+        if result.is_err() {
+            // the instruction never ran, so it shouldn't count towards the step limit
+            self.interp.steps = undo.steps;
+        }
+        result?;
+
+        if self.history.len() == self.history_limit {
+            self.history.pop_front();
+        }
+        if self.history_limit > 0 {
+            self.history.push_back(undo);
+        }
+        self.steps += 1;
+        Ok(true)
+    }
+
+    /// Executes an instruction on the interpreter, noting in `undo` what
+    /// it reads or writes.
+    fn execute(&mut self, instruction: RawInstruction, undo: &mut Undo<T>) -> Result<(), InterpError> {
+        match instruction {
+            RawInstruction::Input => {
+                let byte = match self.replay.pop() {
+                    Some(byte) => Some(byte),
+                    None => {
+                        let mut byte = [0];
+                        let token = &self.program.get_tokens()[undo.program_counter];
+                        let read = self.input.read(&mut byte)
+                            .map_err(|source| InterpError::Io { line: token.line(), column: token.column(), token: undo.program_counter, source })?;
+                        if read == 1 {
+                            self.read.push(byte[0]);
+                            Some(byte[0])
+                        } else {
+                            None
+                        }
+                    },
+                };
+                if let Err(error) = self.interp.step(self.program, &mut byte.as_slice(), &mut io::sink()) {
+                    self.replay.extend(byte);
+                    return Err(error);
+                }
+                self.input_read += usize::from(byte.is_some());
+                undo.input = byte;
+            },
+            RawInstruction::Output => {
+                self.interp.step(self.program, &mut io::empty(), &mut self.output)?;
+                undo.output = true;
+            },
+            _ => {
+                self.interp.step(self.program, &mut io::empty(), &mut io::sink())?;
+            },
+        }
+        Ok(())
+    }
+
+    /// Undoes the most recently executed instruction, returning `false`
+    /// if the debugger is already at the start of the program or of the
+    /// steps it remembers.
+    pub fn reverse_step(&mut self) -> bool {
+        let undo = match self.history.pop_back() {
+            Some(undo) => undo,
+            None => return false,
+        };
+        self.steps -= 1;
+
+        self.interp.program_counter = undo.program_counter;
+        self.interp.steps = undo.steps;
+        self.interp.tape_index = undo.tape_index;
+        self.interp.tape.truncate(undo.tape_len);
+        self.interp.tape[undo.tape_index] = undo.cell;
+        self.interp.forget_cycles();
+        if let Some(byte) = undo.input {
+            self.replay.push(byte);
+            self.input_read -= 1;
+        }
+        if undo.output {
+            self.output.pop();
+        }
+        true
+    }
+
+    /// Goes back to before the first step, however many steps are
+    /// remembered, so the program runs again on the same input.
+    pub fn restart(&mut self) {
+        self.interp.tape = self.start.0.clone();
+        self.interp.tape_index = self.start.1;
+        self.interp.program_counter = 0;
+        self.interp.steps = 0;
+        self.interp.forget_cycles();
+        self.replay = self.read.iter().rev().copied().collect();
+        self.input_read = 0;
+        self.output.clear();
+        self.history.clear();
+        self.steps = 0;
+    }
+
+    /// Runs forwards until the program finishes or the next instruction
+    /// has a breakpoint. At least one instruction is always executed.
+    pub fn run(&mut self) -> Result<Stop, InterpError> {
+        if !self.step()? {
+            return Ok(Stop::Finished);
+        }
+        loop {
+            if self.breakpoints.contains(&self.interp.program_counter) {
+                return Ok(Stop::Breakpoint(self.interp.program_counter));
+            }
+            if !self.step()? {
+                return Ok(Stop::Finished);
+            }
+        }
+    }
+
+    /// Runs backwards until the next instruction has a breakpoint or the
+    /// start of the program is reached. At least one instruction is
+    /// always undone.
+    pub fn reverse_run(&mut self) -> Stop {
+        if !self.reverse_step() {
+            return Stop::Start;
+        }
+        loop {
+            if self.breakpoints.contains(&self.interp.program_counter) {
+                return Stop::Breakpoint(self.interp.program_counter);
+            }
+            if !self.reverse_step() {
+                return Stop::Start;
+            }
+        }
+    }
+
+    /// Toggles a breakpoint on the token at `index`, returning whether
+    /// the breakpoint is now set.
+    pub fn toggle_breakpoint(&mut self, index: usize) -> bool {
+        if self.breakpoints.remove(&index) {
+            false
+        } else {
+            self.breakpoints.insert(index)
+        }
+    }
+
+    /// Gets the indices of the tokens that have breakpoints.
+    pub fn breakpoints(&self) -> impl Iterator<Item = usize> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    /// Gets the number of instructions executed so far.
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// Gets the number of bytes the program has read from its input so far.
+    pub fn input_read(&self) -> usize {
+        self.input_read
+    }
+
+    /// Gets everything the program has written so far.
+    pub fn output(&self) -> &[u8] {
+        self.output.as_slice()
+    }
+
+    /// Gets the program being debugged.
+    pub fn program(&self) -> &'a BrainfuckProgram {
+        self.program
+    }
+
+    /// Gets the interpreter, to inspect the tape and program counter.
+    pub fn interpreter(&self) -> &BrainfuckInterpreter<T> {
+        &self.interp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Debugger, Stop};
+    use crate::{BrainfuckInterpreter, InterpError};
+    use bft_types::BrainfuckProgram;
+
+    #[test]
+    fn reverse_restores_input() {
+        let program = BrainfuckProgram::from_string(&"./virtual", &",.,.");
+        let mut input = &b"ab"[..];
+        let mut debugger = Debugger::new(BrainfuckInterpreter::<u8>::new(0, false), &program, &mut input);
+
+        assert_eq!(debugger.run().unwrap(), Stop::Finished);
+        assert_eq!((debugger.output(), debugger.input_read()), (&b"ab"[..], 2));
+
+        assert_eq!(debugger.reverse_run(), Stop::Start);
+        assert_eq!((debugger.output(), debugger.input_read()), (&b""[..], 0));
+        assert_eq!(debugger.interpreter().tape()[0], 0);
+
+        debugger.run().unwrap();
+        assert_eq!(debugger.output(), b"ab");
+    }
+
+    #[test]
+    fn reverse_gives_back_steps() {
+        let program = BrainfuckProgram::from_string(&"./virtual", &"+++++\n+++++");
+        let mut input = std::io::empty();
+        let mut interp = BrainfuckInterpreter::<u8>::new(0, false);
+        interp.set_step_limit(Some(7));
+        let mut debugger = Debugger::new(interp, &program, &mut input);
+
+        let limit = |debugger: &mut Debugger<u8>| match debugger.run() {
+            Err(InterpError::StepLimit { line, column, .. }) => (line, column, debugger.interpreter().steps()),
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!(limit(&mut debugger), (2, 3, 7));
+        for _ in 0..3 {
+            debugger.reverse_step();
+        }
+        assert_eq!((debugger.steps(), debugger.interpreter().steps()), (4, 4));
+        assert_eq!(limit(&mut debugger), (2, 3, 7));
+    }
+
+    #[test]
+    fn forgets_old_steps() {
+        let program = BrainfuckProgram::from_string(&"./virtual", &"+++++");
+        let mut input = std::io::empty();
+        let mut debugger = Debugger::new(BrainfuckInterpreter::<u8>::new(0, false), &program, &mut input);
+        debugger.set_history_limit(2);
+
+        assert_eq!(debugger.run().unwrap(), Stop::Finished);
+        assert_eq!(debugger.reverse_run(), Stop::Start);
+        assert_eq!((debugger.steps(), debugger.interpreter().tape()[0]), (3, 3));
+        assert!(!debugger.reverse_step());
+
+        debugger.run().unwrap();
+        assert_eq!((debugger.steps(), debugger.interpreter().tape()[0]), (5, 5));
+    }
+
+    #[test]
+    fn restarts_past_the_history() {
+        let program = BrainfuckProgram::from_string(&"./virtual", &",>,.<.");
+        let mut input = &b"ab"[..];
+        let mut debugger = Debugger::new(BrainfuckInterpreter::<u8>::new(2, false), &program, &mut input);
+        debugger.set_history_limit(1);
+
+        debugger.run().unwrap();
+        debugger.restart();
+        assert_eq!((debugger.steps(), debugger.output(), debugger.interpreter().tape()), (0, &b""[..], &[0, 0][..]));
+        debugger.run().unwrap();
+        assert_eq!(debugger.output(), b"ba");
+    }
+
+    #[test]
+    fn reverse_shrinks_tape() {
+        let program = BrainfuckProgram::from_string(&"./virtual", &">>+");
+        let mut input = std::io::empty();
+        let mut debugger = Debugger::new(BrainfuckInterpreter::<u8>::new(1, true), &program, &mut input);
+
+        debugger.run().unwrap();
+        assert_eq!(debugger.interpreter().tape(), &[0, 0, 1]);
+        debugger.reverse_run();
+        assert_eq!(debugger.interpreter().tape(), &[0]);
+    }
+
+    #[test]
+    fn breakpoints_both_ways() {
+        // find the step that corrupts cell 1 by breaking on the `-`
+        let program = BrainfuckProgram::from_string(&"./virtual", &"+++[>++<-]>-");
+        let mut input = std::io::empty();
+        let mut debugger = Debugger::new(BrainfuckInterpreter::<u8>::new(0, false), &program, &mut input);
+        assert!(debugger.toggle_breakpoint(4));
+
+        assert_eq!(debugger.run().unwrap(), Stop::Breakpoint(4));
+        assert_eq!(debugger.run().unwrap(), Stop::Breakpoint(4));
+        assert_eq!(debugger.interpreter().tape()[1], 2);
+
+        assert_eq!(debugger.run().unwrap(), Stop::Breakpoint(4));
+        assert_eq!(debugger.run().unwrap(), Stop::Finished);
+        assert_eq!(debugger.interpreter().tape()[1], 5);
+
+        assert_eq!(debugger.reverse_run(), Stop::Breakpoint(4));
+        assert_eq!(debugger.interpreter().tape()[1], 4);
+        assert!(!debugger.toggle_breakpoint(4));
+        assert_eq!(debugger.reverse_run(), Stop::Start);
+        assert_eq!(debugger.steps(), 0);
+    }
+}
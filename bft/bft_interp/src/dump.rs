@@ -0,0 +1,89 @@
+//! Human-readable dumps of the tape around the pointer.
+
+use crate::{BrainfuckInterpreter, ByteCell, IncDec};
+use std::fmt::{Display, UpperHex};
+use std::io::{Result, Write};
+use std::mem;
+
+impl<T> BrainfuckInterpreter<T>
+where T: IncDec, T: ByteCell, T: Default, T: Clone, T: PartialEq, T: Display, T: UpperHex {
+
+    /// Writes the `radius` cells either side of the pointer, one per line,
+    /// in hex, decimal and ASCII. The cell under the pointer is marked
+    /// with a `>`, and unprintable bytes are shown as `.`.
+    ///
+    /// ```
+    /// # use bft_interp::BrainfuckInterpreter;
+    /// # use bft_types::BrainfuckProgram;
+    /// let program = BrainfuckProgram::from_string(&"virtual", &"+>++++++[-<+++++++++++>]<");
+    /// let mut interp: BrainfuckInterpreter<u8> = BrainfuckInterpreter::new(4, false);
+    /// interp.interpret(&program, &mut std::io::empty(), &mut vec![]).unwrap();
+    ///
+    /// let mut dump = vec![];
+    /// interp.dump(&mut dump, 1).unwrap();
+    /// assert_eq!(String::from_utf8(dump).unwrap(), "\
+    /// pointer at cell 0 of 4, next instruction 25
+    ///   cell  hex  dec  ascii
+    /// >    0   43   67  C
+    ///      1   00    0  .
+    /// ");
+    /// ```
+    pub fn dump(&self, writer: &mut dyn Write, radius: usize) -> Result<()> {
+        let start = self.tape_index.saturating_sub(radius);
+        let end = (self.tape_index + radius + 1).min(self.tape.len());
+        let index_width = end.saturating_sub(1).to_string().len().max(4);
+        let hex_width = mem::size_of::<T>() * 2;
+        let dec_width = T::default().decrement().to_string().len().max(3);
+
+        writeln!(
+            writer,
+            "pointer at cell {} of {}, next instruction {}",
+            self.tape_index, self.tape.len(), self.program_counter,
+        )?;
+        writeln!(
+            writer,
+            "  {:>iw$}  {:>hw$}  {:>dw$}  ascii",
+            "cell", "hex", "dec",
+            iw = index_width, hw = hex_width.max(3), dw = dec_width,
+        )?;
+
+        for (index, cell) in self.tape[start..end].iter().enumerate().map(|(i, c)| (start + i, c)) {
+            let byte = cell.to_byte();
+            let ascii = if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' };
+            writeln!(
+                writer,
+                "{} {:>iw$}  {:>hw$}  {:>dw$}  {}",
+                if index == self.tape_index { '>' } else { ' ' },
+                index,
+                format!("{:0w$X}", cell, w = hex_width),
+                cell,
+                ascii,
+                iw = index_width, hw = hex_width.max(3), dw = dec_width,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::BrainfuckInterpreter;
+    use bft_types::BrainfuckProgram;
+
+    #[test]
+    fn dump_clamps_to_tape() {
+        let program = BrainfuckProgram::from_string(&"./virtual", &"++++++++++>>-");
+        let mut interp: BrainfuckInterpreter<u8> = BrainfuckInterpreter::new(3, false);
+        interp.interpret(&program, &mut std::io::empty(), &mut vec![]).unwrap();
+
+        let mut dump = vec![];
+        interp.dump(&mut dump, 8).unwrap();
+        let dump = String::from_utf8(dump).unwrap();
+        let lines: Vec<_> = dump.lines().collect();
+
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[2], "     0   0A   10  .");
+        assert_eq!(lines[4], ">    2   FF  255  .");
+    }
+}
@@ -0,0 +1,97 @@
+//! The input and output traits the interpreter reads and writes through.
+//!
+//! With the `std` feature these are simply [std::io::Read] and
+//! [std::io::Write]. Without it, they are minimal stand-ins with the same
+//! methods, implemented for byte slices and vectors, so that programs can
+//! be run anywhere with an allocator.
+
+#[cfg(feature = "std")]
+pub use std::io::{empty, sink, Error, Read, Write};
+
+#[cfg(not(feature = "std"))]
+pub use self::bare::{empty, sink, Empty, Error, Read, Sink, Write};
+
+#[cfg(not(feature = "std"))]
+mod bare {
+    use alloc::vec::Vec;
+    use core::fmt;
+
+    /// A failure to read or write.
+    #[derive(Debug)]
+    pub struct Error;
+
+    impl fmt::Display for Error {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "i/o error")
+        }
+    }
+
+    /// A source of bytes, read by [bft_types::RawInstruction::Input].
+    pub trait Read {
+        /// Reads some bytes into `buf`, returning how many were read.
+        /// Reading none means the input has ended.
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error>;
+    }
+
+    /// A sink for bytes, written by [bft_types::RawInstruction::Output].
+    pub trait Write {
+        /// Writes every byte in `buf`.
+        fn write_all(&mut self, buf: &[u8]) -> Result<(), Error>;
+
+        /// Delivers anything written so far.
+        fn flush(&mut self) -> Result<(), Error>;
+    }
+
+    impl Read for &[u8] {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+            let count = buf.len().min(self.len());
+            let (read, rest) = self.split_at(count);
+            buf[..count].copy_from_slice(read);
+            *self = rest;
+            Ok(count)
+        }
+    }
+
+    impl Write for Vec<u8> {
+        fn write_all(&mut self, buf: &[u8]) -> Result<(), Error> {
+            self.extend_from_slice(buf);
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    /// A reader that is always at the end of its input.
+    pub struct Empty;
+
+    /// Creates an [Empty] reader.
+    pub fn empty() -> Empty {
+        Empty
+    }
+
+    impl Read for Empty {
+        fn read(&mut self, _: &mut [u8]) -> Result<usize, Error> {
+            Ok(0)
+        }
+    }
+
+    /// A writer that throws away everything written to it.
+    pub struct Sink;
+
+    /// Creates a [Sink] writer.
+    pub fn sink() -> Sink {
+        Sink
+    }
+
+    impl Write for Sink {
+        fn write_all(&mut self, _: &[u8]) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+}
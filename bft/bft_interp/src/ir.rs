@@ -0,0 +1,314 @@
+//! An optimised intermediate representation of a [BrainfuckProgram].
+//!
+//! Runs of `+`/`-` and of `>` or `<` are folded into single operations,
+//! `[-]` and `[+]` become a single [Op::Clear], and every loop knows where
+//! its partner is up front. Executing an [IrProgram] behaves exactly like
+//! interpreting the original program, including where errors are reported,
+//! and every [Node] records the span of tokens it was made from.
+//!
+//! The [Bounds] of the pointer are worked out while compiling, and when
+//! they show that it can't leave the tape, moves skip their checks.
+
+use crate::bounds::Bounds;
+use crate::io::{Read, Write};
+use crate::{check_brackets, BrainfuckInterpreter, ByteCell, IncDec, InterpError, Overflow};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
+use bft_types::{BrainfuckProgram, RawInstruction};
+
+/// A single optimised operation.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Op {
+    /// Adds to the current cell, wrapping.
+    Add(i32),
+
+    /// Moves the pointer right by a number of cells.
+    Right(usize),
+
+    /// Moves the pointer left by a number of cells.
+    Left(usize),
+
+    /// Sets the current cell to zero.
+    Clear,
+
+    /// Reads a byte into the current cell.
+    Input,
+
+    /// Writes the current cell as a byte.
+    Output,
+
+    /// Jumps past the [Op::LoopEnd] at the given index if the cell is zero.
+    LoopStart(usize),
+
+    /// Jumps back past the [Op::LoopStart] at the given index if the cell is not zero.
+    LoopEnd(usize),
+}
+
+/// An [Op] along with the span of [bft_types::Token]s it was made from.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Node {
+    pub op: Op,
+    /// The index of the first token.
+    pub token: usize,
+    /// The index just past the last token.
+    pub end: usize,
+}
+
+impl Node {
+    /// Gets the indices of the tokens the node was made from.
+    ///
+    /// ```
+    /// # use bft_interp::ir::IrProgram;
+    /// # use bft_types::BrainfuckProgram;
+    /// let program = BrainfuckProgram::from_string(&"virtual", &"+ +\n>[-]");
+    /// let ir = IrProgram::compile(&program).unwrap();
+    /// let spans: Vec<_> = ir.nodes().iter().map(|n| n.tokens()).collect();
+    /// assert_eq!(spans, vec![0..2, 2..3, 3..6]);
+    /// ```
+    pub fn tokens(&self) -> Range<usize> {
+        self.token..self.end
+    }
+}
+
+/// A [BrainfuckProgram] compiled to a list of [Op]s.
+///
+/// ```
+/// # use bft_interp::ir::{IrProgram, Op};
+/// # use bft_types::BrainfuckProgram;
+/// let program = BrainfuckProgram::from_string(&"virtual", &"+++>>[-]<.");
+/// let ir = IrProgram::compile(&program).unwrap();
+/// let ops: Vec<_> = ir.nodes().iter().map(|n| n.op).collect();
+/// assert_eq!(ops, vec![Op::Add(3), Op::Right(2), Op::Clear, Op::Left(1), Op::Output]);
+/// ```
+pub struct IrProgram<'a> {
+    program: &'a BrainfuckProgram,
+    nodes: Vec<Node>,
+    bounds: Bounds,
+}
+
+impl<'a> IrProgram<'a> {
+    /// Compiles a program, failing if its loop brackets do not match.
+    pub fn compile(program: &'a BrainfuckProgram) -> Result<IrProgram<'a>, InterpError> {
+        check_brackets(program)?;
+
+        let tokens = program.get_tokens();
+        let instruction = |i: usize| tokens.get(i).map(|t| t.instruction);
+        let mut nodes = vec![];
+        let mut open = vec![];
+        let mut index = 0;
+
+        while index < tokens.len() {
+            let start = index;
+            let op = match tokens[index].instruction {
+                RawInstruction::Increment | RawInstruction::Decrement => {
+                    let mut amount = 0i32;
+                    while let Some(i @ (RawInstruction::Increment | RawInstruction::Decrement)) = instruction(index) {
+                        amount = amount.wrapping_add(if i == RawInstruction::Increment { 1 } else { -1 });
+                        index += 1;
+                    }
+                    Op::Add(amount)
+                },
+                direction @ (RawInstruction::IncrementPointer | RawInstruction::DecrementPointer) => {
+                    while instruction(index) == Some(direction) {
+                        index += 1;
+                    }
+                    match direction {
+                        RawInstruction::IncrementPointer => Op::Right(index - start),
+                        _ => Op::Left(index - start),
+                    }
+                },
+                RawInstruction::StartLoop => {
+                    let is_clear = matches!(instruction(index + 1), Some(RawInstruction::Increment | RawInstruction::Decrement))
+                        && instruction(index + 2) == Some(RawInstruction::EndLoop);
+                    if is_clear {
+                        index += 3;
+                        Op::Clear
+                    } else {
+                        index += 1;
+                        open.push(nodes.len());
+                        Op::LoopStart(0)
+                    }
+                },
+                RawInstruction::EndLoop => {
+                    index += 1;
+                    let start = open.pop().expect("brackets are checked before compiling");
+                    nodes[start] = Node { op: Op::LoopStart(nodes.len()), ..nodes[start] };
+                    Op::LoopEnd(start)
+                },
+                RawInstruction::Input => { index += 1; Op::Input },
+                RawInstruction::Output => { index += 1; Op::Output },
+            };
+
+            nodes.push(Node { op, token: start, end: index });
+        }
+
+        let bounds = Bounds::analyse(&nodes);
+        Ok(IrProgram { program, nodes, bounds })
+    }
+
+    /// Gets the compiled operations.
+    pub fn nodes(&self) -> &[Node] {
+        self.nodes.as_slice()
+    }
+
+    /// Gets the program this was compiled from.
+    pub fn program(&self) -> &'a BrainfuckProgram {
+        self.program
+    }
+
+    /// Gets where the pointer can go, relative to where it starts.
+    pub fn bounds(&self) -> &Bounds {
+        &self.bounds
+    }
+}
+
+impl<T> BrainfuckInterpreter<T>
+where T: IncDec, T: ByteCell, T: Default, T: Clone, T: PartialEq {
+
+    /// Executes a compiled [IrProgram] from its start. The result is the same
+    /// as calling [BrainfuckInterpreter::interpret] with the original program.
+    pub fn interpret_ir(&mut self, ir: &IrProgram, input: &mut dyn Read, output: &mut dyn Write) -> Result<(), InterpError> {
+        let tokens = ir.program.get_tokens();
+        let mut pc = 0;
+        self.steps = 0;
+        self.forget_cycles();
+        let in_bounds = ir.bounds.program().fits(self.tape_index, self.tape.len());
+
+        while let Some(node) = ir.nodes.get(pc) {
+            let token = &tokens[node.token];
+            self.count_step(node.token, token)?;
+            if let Some(cycles) = &mut self.cycles {
+                cycles.visit(node.token);
+            }
+            match node.op {
+                Op::Add(amount) if self.overflow == Overflow::Wrap => {
+                    self.touch_cell();
+                    self.tape[self.tape_index] = self.tape[self.tape_index].add(amount);
+                },
+                Op::Add(_) => {
+                    // go through the run one at a time, to find where it overflowed
+                    for index in node.tokens() {
+                        let token = &tokens[index];
+                        self.add_to_cell(index, token, if token.instruction == RawInstruction::Increment { 1 } else { -1 })?;
+                    }
+                },
+                Op::Right(distance) if in_bounds => self.tape_index += distance,
+                Op::Left(distance) if in_bounds => self.tape_index -= distance,
+                Op::Right(distance) => {
+                    let target = self.tape_index + distance;
+                    if target >= self.tape.len() {
+                        if !self.expandable {
+                            // stop at the end, blaming the `>` that would have stepped off it
+                            let index = node.token + self.tape.len() - 1 - self.tape_index;
+                            let token = &tokens[index];
+                            self.tape_index = self.tape.len() - 1;
+                            return Err(InterpError::PointerOverflow { line: token.line(), column: token.column(), token: index });
+                        }
+                        self.tape.resize(target + 1, T::default());
+                    }
+                    self.tape_index = target;
+                },
+                Op::Left(distance) => {
+                    if distance > self.tape_index {
+                        let index = node.token + self.tape_index;
+                        let token = &tokens[index];
+                        self.tape_index = 0;
+                        return Err(InterpError::PointerUnderflow { line: token.line(), column: token.column(), token: index });
+                    }
+                    self.tape_index -= distance;
+                },
+                Op::Clear => {
+                    // `[+]` counts up through the largest value, which is an error
+                    let up = &tokens[node.token + 1];
+                    if self.overflow == Overflow::Error && up.instruction == RawInstruction::Increment && self.tape[self.tape_index] != T::default() {
+                        self.tape[self.tape_index] = T::default().decrement();
+                        return Err(InterpError::CellOverflow { line: up.line(), column: up.column(), token: node.token + 1 });
+                    }
+                    self.touch_cell();
+                    self.tape[self.tape_index] = T::default();
+                },
+                Op::Input => self.read_cell(node.token, token, input)?,
+                Op::Output => self.write_cell(node.token, token, output)?,
+                Op::LoopStart(end) => if self.tape[self.tape_index] == T::default() {
+                    pc = end;
+                },
+                Op::LoopEnd(start) => if self.tape[self.tape_index] != T::default() {
+                    self.check_cycle(ir.program, node.token)?;
+                    pc = start;
+                },
+            }
+            pc += 1;
+        }
+
+        self.program_counter = tokens.len();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IrProgram, Op};
+    use crate::{BrainfuckInterpreter, InterpError};
+    use bft_types::BrainfuckProgram;
+
+    #[test]
+    fn loops_link_up() {
+        let program = BrainfuckProgram::from_string(&"./virtual", &"+[>[-]+[<]]");
+        let ir = IrProgram::compile(&program).unwrap();
+        let ops: Vec<_> = ir.nodes().iter().map(|n| n.op).collect();
+        assert_eq!(ops, vec![
+            Op::Add(1), Op::LoopStart(8), Op::Right(1), Op::Clear, Op::Add(1),
+            Op::LoopStart(7), Op::Left(1), Op::LoopEnd(5), Op::LoopEnd(1),
+        ]);
+    }
+
+    #[test]
+    fn cancelling_adds() {
+        let program = BrainfuckProgram::from_string(&"./virtual", &"++-+--");
+        let ir = IrProgram::compile(&program).unwrap();
+        assert_eq!(ir.nodes()[0].op, Op::Add(0));
+    }
+
+    #[test]
+    fn cell_overflow_matches_raw() {
+        for source in ["+++\n-----", "+[+]", "-[-]", "+[-]++\n[[-]-]"] {
+            let program = BrainfuckProgram::from_string(&"./virtual", &source);
+            let run = |ir: bool| {
+                let mut interp: BrainfuckInterpreter<u8> = BrainfuckInterpreter::new(1, false);
+                interp.set_overflow(crate::Overflow::Error);
+                let result = if ir {
+                    interp.interpret_ir(&IrProgram::compile(&program).unwrap(), &mut std::io::empty(), &mut vec![])
+                } else {
+                    interp.interpret(&program, &mut std::io::empty(), &mut vec![])
+                };
+                (result.map_err(|e| e.to_string()), interp.tape().to_vec())
+            };
+            assert_eq!(run(true), run(false), "{}", source);
+        }
+    }
+
+    #[test]
+    fn overflow_location_matches_raw() {
+        let program = BrainfuckProgram::from_string(&"./virtual", &">\n>>>>");
+        let ir = IrProgram::compile(&program).unwrap();
+        let mut interp: BrainfuckInterpreter<u8> = BrainfuckInterpreter::new(3, false);
+        match interp.interpret_ir(&ir, &mut std::io::empty(), &mut vec![]) {
+            Err(InterpError::PointerOverflow { line: 2, column: 2, .. }) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(interp.tape_index(), 2);
+    }
+
+    #[test]
+    fn underflow_location_matches_raw() {
+        let program = BrainfuckProgram::from_string(&"./virtual", &">\n<<<");
+        let ir = IrProgram::compile(&program).unwrap();
+        let mut interp: BrainfuckInterpreter<u8> = BrainfuckInterpreter::new(0, false);
+        match interp.interpret_ir(&ir, &mut std::io::empty(), &mut vec![]) {
+            Err(InterpError::PointerUnderflow { line: 2, column: 2, .. }) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(interp.tape_index(), 0);
+    }
+}
@@ -1,85 +1,641 @@
 //! Contains the logic for the brainfuck interpreter.
+//!
+//! Without the default `std` feature the crate only needs `alloc`, so it
+//! can be embedded anywhere with an allocator, such as WebAssembly. The
+//! interpreter then reads and writes through the stand-ins in [io], and
+//! the modules that need files, threads or JSON are left out.
 
-use bft_types::{BrainfuckProgram, RawInstruction};
-use std::fmt::Display;
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use bft_types::{BrainfuckProgram, RawInstruction, Token};
+use cycle::CycleDetector;
+use core::convert::TryFrom;
+use core::fmt;
+use io::{Read, Write};
+#[cfg(feature = "std")]
+use serde::{Deserialize, Serialize};
+
+pub mod bounds;
+mod cycle;
+pub mod debugger;
+#[cfg(feature = "std")]
+mod dump;
+pub mod io;
+pub mod ir;
+pub mod minify;
+#[cfg(feature = "std")]
+pub mod pipeline;
+#[cfg(feature = "std")]
+mod snapshot;
+#[cfg(feature = "std")]
+pub mod trace;
+
+#[cfg(feature = "std")]
+pub use snapshot::Snapshot;
 
 /// Encapsulates the local state of a Brainfuck interpreter.
 /// Once created, the interpreter accepts [BrainfuckProgram].
 pub struct BrainfuckInterpreter<T> {
     tape: Vec<T>,
     tape_index: usize,
+    program_counter: usize,
     expandable: bool,
+    steps: u64,
+    step_limit: Option<u64>,
+    eof: Eof,
+    overflow: Overflow,
+    cycles: Option<CycleDetector<T>>,
 }
 
+/// Arithmetic on a single cell of the tape. Both operations wrap
+/// around at the bounds of the type, as is conventional for brainfuck.
 pub trait IncDec {
     fn increment(&self) -> Self;
     fn decrement(&self) -> Self;
+
+    /// Adds a (possibly negative) amount in one go, as if by
+    /// repeated calls to [IncDec::increment] or [IncDec::decrement].
+    fn add(&self, amount: i32) -> Self;
+
+    /// Adds a (possibly negative) amount, or gives [None] if the result
+    /// would not fit in the cell.
+    fn checked_add(&self, amount: i32) -> Option<Self> where Self: Sized;
+}
+
+/// Conversion between a cell and the bytes read by [RawInstruction::Input]
+/// and written by [RawInstruction::Output]. Wider cells are written out
+/// as their lowest byte.
+pub trait ByteCell {
+    fn from_byte(byte: u8) -> Self;
+    fn to_byte(&self) -> u8;
+}
+
+macro_rules! unsigned_cell {
+    ($($cell:ty),*) => {$(
+        impl IncDec for $cell {
+            fn increment(&self) -> Self { self.wrapping_add(1) }
+            fn decrement(&self) -> Self { self.wrapping_sub(1) }
+            fn add(&self, amount: i32) -> Self { self.wrapping_add(amount as $cell) }
+            fn checked_add(&self, amount: i32) -> Option<Self> { <$cell>::try_from(*self as i64 + amount as i64).ok() }
+        }
+
+        impl ByteCell for $cell {
+            fn from_byte(byte: u8) -> Self { byte as $cell }
+            fn to_byte(&self) -> u8 { *self as u8 }
+        }
+    )*};
+}
+
+unsigned_cell!(u8, u16, u32);
+
+/// What [RawInstruction::Input] leaves in the cell once the input has
+/// run out.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize), serde(rename_all = "kebab-case"))]
+pub enum Eof {
+    /// Sets the cell to zero.
+    #[default]
+    Zero,
+
+    /// Leaves the cell as it was.
+    Unchanged,
+
+    /// Sets the cell to minus one, which wraps round to its largest value.
+    MinusOne,
+}
+
+/// What happens when [RawInstruction::Increment] or [RawInstruction::Decrement]
+/// take a cell past the largest or smallest value it can hold.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize), serde(rename_all = "kebab-case"))]
+pub enum Overflow {
+    /// Wraps round to the other end.
+    #[default]
+    Wrap,
+
+    /// Fails with [InterpError::CellOverflow].
+    Error,
 }
 
-impl IncDec for u8 {
-    fn increment(&self) -> Self { self + 1 }
-    fn decrement(&self) -> Self { self + 1 }
+/// The ways in which interpreting a [BrainfuckProgram] can fail. Each
+/// variant records the line and column of the offending instruction, and
+/// its index into [BrainfuckProgram::get_tokens].
+#[derive(Debug)]
+pub enum InterpError {
+    /// The program contains a loop bracket with no partner.
+    UnmatchedBracket { line: usize, column: usize, token: usize },
+
+    /// The pointer was moved left of the first cell.
+    PointerUnderflow { line: usize, column: usize, token: usize },
+
+    /// The pointer was moved right of the last cell on a tape
+    /// that is not allowed to grow.
+    PointerOverflow { line: usize, column: usize, token: usize },
+
+    /// Reading input or writing output failed.
+    Io { line: usize, column: usize, token: usize, source: io::Error },
+
+    /// The program was about to execute more instructions than the
+    /// limit set by [BrainfuckInterpreter::set_step_limit].
+    StepLimit { line: usize, column: usize, token: usize },
+
+    /// A cell went out of range while overflow was set to [Overflow::Error].
+    CellOverflow { line: usize, column: usize, token: usize },
+
+    /// The program came back to the same state in the loop starting here,
+    /// so would have run forever. Only detected once enabled with
+    /// [BrainfuckInterpreter::set_cycle_detection].
+    InfiniteLoop { line: usize, column: usize, token: usize },
 }
 
+impl fmt::Display for InterpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InterpError::UnmatchedBracket { line, column, .. } =>
+                write!(f, "unmatched loop bracket at line {}, column {}", line, column),
+            InterpError::PointerUnderflow { line, column, .. } =>
+                write!(f, "pointer moved left of the first cell at line {}, column {}", line, column),
+            InterpError::PointerOverflow { line, column, .. } =>
+                write!(f, "pointer moved right of the last cell at line {}, column {}", line, column),
+            InterpError::Io { line, column, source, .. } =>
+                write!(f, "i/o failed at line {}, column {}: {}", line, column, source),
+            InterpError::StepLimit { line, column, .. } =>
+                write!(f, "step limit exceeded at line {}, column {}", line, column),
+            InterpError::CellOverflow { line, column, .. } =>
+                write!(f, "cell overflowed at line {}, column {}", line, column),
+            InterpError::InfiniteLoop { line, column, .. } =>
+                write!(f, "stuck in an endless loop at line {}, column {}", line, column),
+        }
+    }
+}
+
+impl InterpError {
+    /// Gets the line and column of the instruction that failed.
+    pub fn location(&self) -> (usize, usize) {
+        match self {
+            InterpError::UnmatchedBracket { line, column, .. }
+            | InterpError::PointerUnderflow { line, column, .. }
+            | InterpError::PointerOverflow { line, column, .. }
+            | InterpError::Io { line, column, .. }
+            | InterpError::StepLimit { line, column, .. }
+            | InterpError::CellOverflow { line, column, .. }
+            | InterpError::InfiniteLoop { line, column, .. } => (*line, *column),
+        }
+    }
+
+    /// Gets the index of the instruction that failed, which can be used to
+    /// find the file it came from with [BrainfuckProgram::location].
+    pub fn token(&self) -> usize {
+        match self {
+            InterpError::UnmatchedBracket { token, .. }
+            | InterpError::PointerUnderflow { token, .. }
+            | InterpError::PointerOverflow { token, .. }
+            | InterpError::Io { token, .. }
+            | InterpError::StepLimit { token, .. }
+            | InterpError::CellOverflow { token, .. }
+            | InterpError::InfiniteLoop { token, .. } => *token,
+        }
+    }
+
+    /// Describes the error as it happened in `program`, naming the file the
+    /// failing instruction was written in when the program was put together
+    /// from several.
+    ///
+    /// ```
+    /// # use bft_interp::BrainfuckInterpreter;
+    /// # use bft_types::BrainfuckProgram;
+    /// let program = BrainfuckProgram::from_string(&"virtual", &"+\n<");
+    /// let error = BrainfuckInterpreter::<u8>::new(8, false)
+    ///     .interpret(&program, &mut &b""[..], &mut vec![])
+    ///     .unwrap_err();
+    /// assert_eq!(error.token(), 1);
+    /// assert_eq!(error.describe(&program), "pointer moved left of the first cell at line 2, column 1");
+    /// ```
+    pub fn describe(&self, program: &BrainfuckProgram) -> String {
+        match program.source_map() {
+            Some(sources) => format!("{} in {}", self, sources.file(self.token())),
+            None => self.to_string(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for InterpError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            InterpError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
 
 impl<T> BrainfuckInterpreter<T>
-where T: std::ops::AddAssign, T: std::ops::SubAssign, T: Default, T: IncDec {
+where T: IncDec, T: ByteCell, T: Default, T: Clone, T: PartialEq {
 
     /// Creates a new [BrainfuckInterpreter] with an initial tape size.
     /// By setting `expandable` to `true`, the interpreter will automatically
     /// expand the tape size as needed if the pointer overflows.
-    /// 
+    ///
     /// **note:** Setting `tape_size` to 0 results in the interpreter using
     ///           the default size of 30,000.
-    /// 
+    ///
     /// # Example Usage
-    /// 
+    ///
     /// ```
-    /// let interp = BrainfuckInterpreter<u8>::new(50, false);
+    /// # use bft_interp::BrainfuckInterpreter;
+    /// let interp: BrainfuckInterpreter<u8> = BrainfuckInterpreter::new(50, false);
     /// ```
     pub fn new(tape_size: usize, expandable: bool) -> BrainfuckInterpreter<T> {
         let tape_size = if tape_size == 0 {30_000} else {tape_size};
         BrainfuckInterpreter {
-            tape: Vec::with_capacity(tape_size),
+            tape: vec![T::default(); tape_size],
             tape_index: 0,
+            program_counter: 0,
             expandable,
+            steps: 0,
+            step_limit: None,
+            eof: Eof::default(),
+            overflow: Overflow::default(),
+            cycles: None,
         }
     }
 
-    /// Interprets the given [BrainfuckProgram] on the [BrainfuckInterpreter].
-    /// 
-    /// **note:** This does not reset the tape state. If you need fresh state, 
+    /// Limits how many instructions a single run may execute, so that
+    /// programs which never finish can be stopped. A run is a call to
+    /// [BrainfuckInterpreter::interpret] or [BrainfuckInterpreter::interpret_ir]
+    /// along with any following calls that continue it, such as
+    /// [BrainfuckInterpreter::step]. When running an [ir::IrProgram], each
+    /// optimised operation counts as a single instruction.
+    ///
+    /// ```
+    /// # use bft_interp::{BrainfuckInterpreter, InterpError};
+    /// # use bft_types::BrainfuckProgram;
+    /// let program = BrainfuckProgram::from_string(&"virtual", &"+[]");
+    /// let mut interp: BrainfuckInterpreter<u8> = BrainfuckInterpreter::new(0, false);
+    /// interp.set_step_limit(Some(100));
+    /// match interp.interpret(&program, &mut std::io::empty(), &mut vec![]) {
+    ///     Err(InterpError::StepLimit { line: 1, column: 3, .. }) => (),
+    ///     other => panic!("unexpected {:?}", other),
+    /// }
+    /// ```
+    pub fn set_step_limit(&mut self, limit: Option<u64>) {
+        self.step_limit = limit;
+    }
+
+    /// Chooses what [RawInstruction::Input] does once the input has run
+    /// out. By default it sets the cell to zero.
+    ///
+    /// ```
+    /// # use bft_interp::{BrainfuckInterpreter, Eof};
+    /// # use bft_types::BrainfuckProgram;
+    /// let program = BrainfuckProgram::from_string(&"virtual", &"+,");
+    /// let mut interp: BrainfuckInterpreter<u16> = BrainfuckInterpreter::new(1, false);
+    /// interp.set_eof(Eof::MinusOne);
+    /// interp.interpret(&program, &mut std::io::empty(), &mut vec![]).unwrap();
+    /// assert_eq!(interp.tape(), &[u16::MAX]);
+    /// ```
+    pub fn set_eof(&mut self, eof: Eof) {
+        self.eof = eof;
+    }
+
+    /// Chooses what happens when a cell goes out of range. By default it
+    /// wraps round.
+    ///
+    /// ```
+    /// # use bft_interp::{BrainfuckInterpreter, InterpError, Overflow};
+    /// # use bft_types::BrainfuckProgram;
+    /// let program = BrainfuckProgram::from_string(&"virtual", &"+-\n-");
+    /// let mut interp: BrainfuckInterpreter<u8> = BrainfuckInterpreter::new(1, false);
+    /// interp.set_overflow(Overflow::Error);
+    /// match interp.interpret(&program, &mut std::io::empty(), &mut vec![]) {
+    ///     Err(InterpError::CellOverflow { line: 2, column: 1, .. }) => (),
+    ///     other => panic!("unexpected {:?}", other),
+    /// }
+    /// ```
+    pub fn set_overflow(&mut self, overflow: Overflow) {
+        self.overflow = overflow;
+    }
+
+    /// Stops a run with [InterpError::InfiniteLoop] if it comes back to a
+    /// loop with exactly the same pointer and tape as an earlier time round,
+    /// and nothing read in between, since it would then never finish.
+    /// Programs that never finish in other ways, like moving right forever
+    /// along a tape that grows, are not caught. The check makes loops
+    /// slower, so it is off by default.
+    ///
+    /// ```
+    /// # use bft_interp::{BrainfuckInterpreter, InterpError};
+    /// # use bft_types::BrainfuckProgram;
+    /// let program = BrainfuckProgram::from_string(&"virtual", &"+[>+\n[>++<-]<]");
+    /// let mut interp: BrainfuckInterpreter<u8> = BrainfuckInterpreter::new(0, false);
+    /// interp.set_cycle_detection(true);
+    /// match interp.interpret(&program, &mut std::io::empty(), &mut vec![]) {
+    ///     Err(InterpError::InfiniteLoop { line: 1, column: 2, .. }) => (),
+    ///     other => panic!("unexpected {:?}", other),
+    /// }
+    /// ```
+    pub fn set_cycle_detection(&mut self, detect: bool) {
+        self.cycles = if detect { Some(CycleDetector::new()) } else { None };
+    }
+
+    /// Forgets the states seen so far, after the interpreter is moved to
+    /// somewhere the program didn't take it by itself.
+    fn forget_cycles(&mut self) {
+        if let Some(cycles) = &mut self.cycles {
+            cycles.forget();
+        }
+    }
+
+    /// Gets the number of instructions executed by the current run.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Counts an instruction, failing if that would exceed the step limit.
+    fn count_step(&mut self, index: usize, token: &Token) -> Result<(), InterpError> {
+        if self.step_limit.is_some_and(|limit| self.steps >= limit) {
+            return Err(InterpError::StepLimit { line: token.line(), column: token.column(), token: index });
+        }
+        self.steps += 1;
+        Ok(())
+    }
+
+    /// Interprets the given [BrainfuckProgram] on the [BrainfuckInterpreter]
+    /// from its first instruction, reading from `input` and writing to `output`.
+    ///
+    /// **note:** This does not reset the tape state. If you need fresh state,
     ///           use [BrainfuckInterpreter::reset]. This is so that multiple
-    ///           programs could be chained together.
-    pub fn interpret(&mut self, program: &BrainfuckProgram) {
-        println!("Running program {}\n\n", program.name);
-        println!("{}", program.get_tokens().iter().map(|f| format!("{}", f)).collect::<Vec<_>>().join(""))
-    
-        let instruction_counter = 0;
+    ///           programs can be chained together, see [pipeline::Pipeline].
+    ///
+    /// ```
+    /// # use bft_interp::BrainfuckInterpreter;
+    /// # use bft_types::BrainfuckProgram;
+    /// let program = BrainfuckProgram::from_string(&"virtual", &",[.,]");
+    /// let mut interp: BrainfuckInterpreter<u8> = BrainfuckInterpreter::new(0, false);
+    /// let mut output = vec![];
+    /// interp.interpret(&program, &mut "echo".as_bytes(), &mut output).unwrap();
+    /// assert_eq!(output, b"echo");
+    /// ```
+    pub fn interpret(&mut self, program: &BrainfuckProgram, input: &mut dyn Read, output: &mut dyn Write) -> Result<(), InterpError> {
+        check_brackets(program)?;
+        self.program_counter = 0;
+        self.steps = 0;
+        self.forget_cycles();
+        self.resume(program, input, output)
+    }
+
+    /// Continues interpreting the given [BrainfuckProgram] from the current
+    /// program counter until it finishes, such as after restoring a [Snapshot].
+    pub fn resume(&mut self, program: &BrainfuckProgram, input: &mut dyn Read, output: &mut dyn Write) -> Result<(), InterpError> {
+        while self.step(program, input, output)? {}
+        Ok(())
+    }
+
+    /// Executes the single instruction at the program counter, returning
+    /// `false` if the program had already finished.
+    pub fn step(&mut self, program: &BrainfuckProgram, input: &mut dyn Read, output: &mut dyn Write) -> Result<bool, InterpError> {
+        let token = match program.get_tokens().get(self.program_counter) {
+            Some(token) => token,
+            None => return Ok(false),
+        };
+
+        let index = self.program_counter;
+        self.count_step(index, token)?;
+        if let Some(cycles) = &mut self.cycles {
+            cycles.visit(index);
+        }
+        match token.instruction {
+            RawInstruction::IncrementPointer => {
+                if self.tape_index + 1 == self.tape.len() {
+                    if !self.expandable {
+                        return Err(InterpError::PointerOverflow { line: token.line(), column: token.column(), token: index });
+                    }
+                    self.tape.push(T::default());
+                }
+                self.tape_index += 1;
+            },
+            RawInstruction::DecrementPointer => {
+                self.tape_index = self.tape_index.checked_sub(1)
+                    .ok_or(InterpError::PointerUnderflow { line: token.line(), column: token.column(), token: index })?;
+            },
+            RawInstruction::Increment => self.add_to_cell(index, token, 1)?,
+            RawInstruction::Decrement => self.add_to_cell(index, token, -1)?,
+            RawInstruction::Input => self.read_cell(index, token, input)?,
+            RawInstruction::Output => self.write_cell(index, token, output)?,
+            RawInstruction::StartLoop | RawInstruction::EndLoop => {
+                let partner = program.matching_bracket(index)
+                    .ok_or(InterpError::UnmatchedBracket { line: token.line(), column: token.column(), token: index })?;
+                let is_zero = self.tape[self.tape_index] == T::default();
+                let jump = match token.instruction {
+                    RawInstruction::StartLoop => is_zero,
+                    _ => !is_zero,
+                };
+                if jump {
+                    if token.instruction == RawInstruction::EndLoop {
+                        self.check_cycle(program, index)?;
+                    }
+                    self.program_counter = partner;
+                }
+            },
+        }
+
+        self.program_counter += 1;
+        Ok(true)
+    }
+
+    /// Records the current cell before it changes, so that cycles can be found.
+    fn touch_cell(&mut self) {
+        if let Some(cycles) = &mut self.cycles {
+            cycles.write(self.tape_index, &self.tape[self.tape_index]);
+        }
+    }
+
+    /// Fails if the loop ending at `end` is about to go round again in a
+    /// state it has been in before, blaming the innermost loop holding
+    /// everything run since then.
+    fn check_cycle(&mut self, program: &BrainfuckProgram, end: usize) -> Result<(), InterpError> {
+        let cycles = match &mut self.cycles {
+            Some(cycles) => cycles,
+            None => return Ok(()),
+        };
+        let (lowest, highest) = match cycles.jump(end, self.tape_index, &self.tape) {
+            Some(range) => range,
+            None => return Ok(()),
+        };
+
         let tokens = program.get_tokens();
+        let start = (0..=lowest).rev()
+            .find(|&index| tokens[index].instruction == RawInstruction::StartLoop
+                && program.matching_bracket(index).is_some_and(|partner| partner >= highest))
+            .or_else(|| program.matching_bracket(end))
+            .unwrap_or(end);
+        let token = &tokens[start];
+        Err(InterpError::InfiniteLoop { line: token.line(), column: token.column(), token: start })
+    }
 
-        while instruction_counter < tokens.len() {
-            match tokens[instruction_counter].instruction {
-                RawInstruction::IncrementPointer => self.tape_index += 1,
-                RawInstruction::DecrementPointer => self.tape_index -= 1,
-                RawInstruction::Increment => {self.tape[self.tape_index].increment();},
-                RawInstruction::Decrement => {self.tape[self.tape_index].decrement();},
-                RawInstruction::Input => (),
-                RawInstruction::Output => (),
-                RawInstruction::StartLoop => (),
-                RawInstruction::EndLoop => (),
-            }
+    /// Adds to the current cell, as the overflow policy allows.
+    fn add_to_cell(&mut self, index: usize, token: &Token, amount: i32) -> Result<(), InterpError> {
+        self.touch_cell();
+        let cell = &mut self.tape[self.tape_index];
+        *cell = match self.overflow {
+            Overflow::Wrap => cell.add(amount),
+            Overflow::Error => cell.checked_add(amount)
+                .ok_or(InterpError::CellOverflow { line: token.line(), column: token.column(), token: index })?,
+        };
+        Ok(())
+    }
+
+    /// Reads a byte from `input` into the current cell.
+    fn read_cell(&mut self, index: usize, token: &Token, input: &mut dyn Read) -> Result<(), InterpError> {
+        let mut byte = [0];
+        let read = input.read(&mut byte).map_err(|e| io_error(index, token, e))?;
+        // input is never read the same way twice
+        self.forget_cycles();
+        let cell = &mut self.tape[self.tape_index];
+        match (read, self.eof) {
+            (0, Eof::Zero) => *cell = T::default(),
+            (0, Eof::Unchanged) => (),
+            (0, Eof::MinusOne) => *cell = T::default().decrement(),
+            _ => *cell = T::from_byte(byte[0]),
         }
+        Ok(())
+    }
+
+    /// Writes the current cell to `output` as a byte.
+    fn write_cell(&self, index: usize, token: &Token, output: &mut dyn Write) -> Result<(), InterpError> {
+        output.write_all(&[self.tape[self.tape_index].to_byte()])
+            .and_then(|_| output.flush())
+            .map_err(|e| io_error(index, token, e))
     }
 
     /// Resets the state (memory) of the interpreter.
     pub fn reset(&mut self) {
         self.tape_index = 0;
-        self.tape = Vec::with_capacity(self.tape.capacity())
+        self.program_counter = 0;
+        self.steps = 0;
+        self.tape = vec![T::default(); self.tape.len()];
+        self.forget_cycles();
+    }
+
+    /// Gets the contents of the tape.
+    pub fn tape(&self) -> &[T] {
+        self.tape.as_slice()
+    }
+
+    /// Gets the index of the cell the pointer is at.
+    pub fn tape_index(&self) -> usize {
+        self.tape_index
+    }
+
+    /// Gets the index of the next [Token] to be executed.
+    pub fn program_counter(&self) -> usize {
+        self.program_counter
+    }
+}
+
+/// Fails with the location of the first unmatched loop bracket, if any.
+fn check_brackets(program: &BrainfuckProgram) -> Result<(), InterpError> {
+    match program.bracket_pairs().1.first() {
+        Some(bracket) => {
+            let token = &program.get_tokens()[bracket.index()];
+            Err(InterpError::UnmatchedBracket { line: token.line(), column: token.column(), token: bracket.index() })
+        },
+        None => Ok(()),
     }
 }
 
+/// Attaches the location of the [Token] at `index` to an i/o error.
+fn io_error(index: usize, token: &Token, source: io::Error) -> InterpError {
+    InterpError::Io { line: token.line(), column: token.column(), token: index, source }
+}
+
 #[cfg(test)]
 mod tests {
+    use crate::{BrainfuckInterpreter, InterpError};
+    use bft_types::BrainfuckProgram;
+
+    fn run(source: &str, input: &[u8], tape_size: usize, expandable: bool) -> (BrainfuckInterpreter<u8>, Result<Vec<u8>, InterpError>) {
+        let program = BrainfuckProgram::from_string(&"./virtual", &source);
+        let mut interp = BrainfuckInterpreter::new(tape_size, expandable);
+        let mut output = vec![];
+        let result = interp.interpret(&program, &mut &input[..], &mut output).map(|_| output);
+        (interp, result)
+    }
+
+    #[test]
+    fn hello_world() {
+        let source = include_str!("../../brainfuck.b");
+        assert_eq!(run(source, b"", 0, false).1.unwrap(), b"hello world");
+    }
+
+    #[test]
+    fn cells_wrap() {
+        let (interp, _) = run("->>+", b"", 3, false);
+        assert_eq!(interp.tape(), &[255, 0, 1]);
+    }
+
+    #[test]
+    fn input_at_end_is_zero() {
+        let (interp, result) = run("+++,>+++,", b"a", 0, false);
+        result.unwrap();
+        assert_eq!(&interp.tape()[..2], b"a\0");
+    }
 
+    #[test]
+    fn wider_cells() {
+        let program = BrainfuckProgram::from_string(&"./virtual", &"-.>,");
+        let mut interp: BrainfuckInterpreter<u32> = BrainfuckInterpreter::new(2, false);
+        interp.set_eof(crate::Eof::Unchanged);
+        let mut output = vec![];
+        interp.interpret(&program, &mut &b""[..], &mut output).unwrap();
+        assert_eq!((interp.tape(), &output[..]), (&[u32::MAX, 0][..], &[255][..]));
+    }
+
+    #[test]
+    fn overflow_is_an_error() {
+        let program = BrainfuckProgram::from_string(&"./virtual", &"+-\n>-");
+        let mut interp: BrainfuckInterpreter<u16> = BrainfuckInterpreter::new(2, false);
+        interp.set_overflow(crate::Overflow::Error);
+        match interp.interpret(&program, &mut std::io::empty(), &mut vec![]) {
+            Err(InterpError::CellOverflow { line: 2, column: 2, .. }) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn pointer_underflow() {
+        match run("+\n<", b"", 0, false).1 {
+            Err(InterpError::PointerUnderflow { line: 2, column: 1, .. }) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn pointer_overflow() {
+        match run(">>", b"", 2, false).1 {
+            Err(InterpError::PointerOverflow { line: 1, column: 2, .. }) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn tape_expands() {
+        let (interp, result) = run(">>+", b"", 2, true);
+        result.unwrap();
+        assert_eq!(interp.tape(), &[0, 0, 1]);
+    }
+
+    #[test]
+    fn unmatched_bracket() {
+        match run("+[", b"", 0, false).1 {
+            Err(InterpError::UnmatchedBracket { line: 1, column: 2, .. }) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
 }
@@ -0,0 +1,144 @@
+//! Shrinks a [BrainfuckProgram] without changing what it does.
+//!
+//! Comments go, instructions that undo each other (`+-`, `-+`, `><` and
+//! `<>`) are cancelled, and loops that can never be entered are dropped. A
+//! loop can't be entered straight after another loop closes, or after
+//! only output since, because the cell is known to be zero there. The
+//! same goes for a loop at the very start of the program, on the empty
+//! tape it starts with. Cancelling goes first, since it can leave a loop
+//! right up against the end of another.
+//!
+//! Cancelled moves never leave the tape in the first place, so a program
+//! that only failed by stepping off the end of the tape and straight back
+//! may run without error once minified. The same goes for a cell taken
+//! out of range and straight back under [Overflow::Error](crate::Overflow::Error).
+//! Otherwise, the result reads the same input, writes the same output and
+//! leaves the same tape.
+
+use crate::{check_brackets, InterpError};
+use alloc::vec::Vec;
+use bft_types::{BrainfuckProgram, RawInstruction, Token};
+
+/// Minifies a program, failing if its loop brackets do not match. The
+/// tokens of the result keep their lines and columns in the original, and
+/// it prints as the minified source.
+///
+/// ```
+/// # use bft_types::BrainfuckProgram;
+/// let program = BrainfuckProgram::from_string(&"hi", &"[a comment] +++++ +-- [> +<-]\n[dead] > <.");
+/// let minified = bft_interp::minify::minify(&program).unwrap();
+/// assert_eq!(minified.to_string(), "++++[>+<-].");
+/// assert_eq!(minified.get_tokens()[10].line(), 2);
+/// ```
+pub fn minify(program: &BrainfuckProgram) -> Result<BrainfuckProgram, InterpError> {
+    check_brackets(program)?;
+
+    let tokens = remove_dead_loops(cancel_pairs(program.get_tokens().to_vec()));
+    Ok(BrainfuckProgram::new(&program.name, tokens))
+}
+
+fn inverse(instruction: RawInstruction) -> Option<RawInstruction> {
+    match instruction {
+        RawInstruction::Increment => Some(RawInstruction::Decrement),
+        RawInstruction::Decrement => Some(RawInstruction::Increment),
+        RawInstruction::IncrementPointer => Some(RawInstruction::DecrementPointer),
+        RawInstruction::DecrementPointer => Some(RawInstruction::IncrementPointer),
+        _ => None,
+    }
+}
+
+/// Drops each instruction that immediately undoes the one before it,
+/// which also folds longer runs like `++-+--` down to what's left.
+fn cancel_pairs(tokens: Vec<Token>) -> Vec<Token> {
+    let mut kept: Vec<Token> = Vec::with_capacity(tokens.len());
+    for token in tokens {
+        let cancels = kept.last().is_some_and(|last| inverse(last.instruction) == Some(token.instruction));
+        if cancels {
+            kept.pop();
+        } else {
+            kept.push(token);
+        }
+    }
+    kept
+}
+
+/// Drops every loop that starts while the current cell is known to be zero.
+fn remove_dead_loops(tokens: Vec<Token>) -> Vec<Token> {
+    let mut kept = Vec::with_capacity(tokens.len());
+    let mut zero = true;
+    let mut skipping = 0;
+
+    for token in tokens {
+        if skipping > 0 {
+            match token.instruction {
+                RawInstruction::StartLoop => skipping += 1,
+                RawInstruction::EndLoop => skipping -= 1,
+                _ => (),
+            }
+            continue;
+        }
+
+        match token.instruction {
+            RawInstruction::StartLoop if zero => {
+                skipping = 1;
+                continue;
+            },
+            RawInstruction::EndLoop => zero = true,
+            RawInstruction::Output => (),
+            _ => zero = false,
+        }
+        kept.push(token);
+    }
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use super::minify;
+    use crate::{BrainfuckInterpreter, InterpError};
+    use bft_types::BrainfuckProgram;
+
+    fn minified(source: &str) -> alloc::string::String {
+        minify(&BrainfuckProgram::from_string(&"test", &source)).unwrap().to_string()
+    }
+
+    #[test]
+    fn cancels_pairs() {
+        assert_eq!(minified("+ + - > < < > . + -"), "+.");
+        assert_eq!(minified(">+<<>>-<"), "");
+        assert_eq!(minified(">+[->++-<]"), ">+[->+<]");
+    }
+
+    #[test]
+    fn removes_dead_loops() {
+        assert_eq!(minified("[comment [nested]]+[->+<][-][.]."), "+[->+<].");
+        assert_eq!(minified("+[-].[never]>[-]"), "+[-].>[-]");
+        // cancelling brings a dead loop to light
+        assert_eq!(minified("+[-]>+-<[>]>>."), "+[-]>>.");
+    }
+
+    #[test]
+    fn rejects_unmatched_brackets() {
+        let program = BrainfuckProgram::from_string(&"test", &"+[\n]]");
+        assert!(matches!(minify(&program), Err(InterpError::UnmatchedBracket { line: 2, column: 2, .. })));
+    }
+
+    #[test]
+    fn behaves_the_same() {
+        let source = "[ print HELLO and the input backwards ]
+            ++++++++[>+++++++++<-]>.---.+++++++..+++.[-]<+-
+            >,[>,]<[.<][dead]";
+        let program = BrainfuckProgram::from_string(&"test", &source);
+        let small = minify(&program).unwrap();
+        assert!(small.get_tokens().len() < program.get_tokens().len() - 5);
+
+        let run = |program: &BrainfuckProgram| {
+            let mut interp = BrainfuckInterpreter::<u8>::new(32, false);
+            let mut output = vec![];
+            interp.interpret(program, &mut &b"abc"[..], &mut output).unwrap();
+            (output, interp.tape().to_vec(), interp.tape_index())
+        };
+        assert_eq!(run(&small), run(&program));
+        assert_eq!(run(&small).0, b"HELLOcba");
+    }
+}
@@ -0,0 +1,343 @@
+//! Runs several programs together, either one after another on a single
+//! tape with a [Pipeline], or all at once like a Unix pipe with [pipe].
+
+use crate::{BrainfuckInterpreter, ByteCell, IncDec, InterpError};
+use bft_types::BrainfuckProgram;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread;
+
+/// A single program in a [Pipeline], along with how it should be run.
+pub struct Stage<'a> {
+    program: &'a BrainfuckProgram,
+    reset: bool,
+    input: Option<&'a mut dyn Read>,
+    output: Option<&'a mut dyn Write>,
+}
+
+impl<'a> Stage<'a> {
+    /// Creates a stage that runs on the tape exactly as the previous stage
+    /// left it, using the input and output given to [Pipeline::run].
+    pub fn new(program: &'a BrainfuckProgram) -> Stage<'a> {
+        Stage { program, reset: false, input: None, output: None }
+    }
+
+    /// Resets the interpreter with [BrainfuckInterpreter::reset] before this stage runs.
+    pub fn reset(mut self) -> Stage<'a> {
+        self.reset = true;
+        self
+    }
+
+    /// Reads this stage's input from `input` rather than the pipeline's.
+    pub fn input(mut self, input: &'a mut dyn Read) -> Stage<'a> {
+        self.input = Some(input);
+        self
+    }
+
+    /// Writes this stage's output to `output` rather than the pipeline's.
+    pub fn output(mut self, output: &'a mut dyn Write) -> Stage<'a> {
+        self.output = Some(output);
+        self
+    }
+}
+
+/// An ordered list of [Stage]s sharing one [BrainfuckInterpreter].
+///
+/// Each program starts from its first instruction with the tape and
+/// pointer left behind by the one before it, so a stage can pick up
+/// where the last left off.
+///
+/// ```
+/// # use bft_interp::BrainfuckInterpreter;
+/// # use bft_interp::pipeline::{Pipeline, Stage};
+/// # use bft_types::BrainfuckProgram;
+/// let set = BrainfuckProgram::from_string(&"set", &"++++++++[>++++++++<-]>+");
+/// let print = BrainfuckProgram::from_string(&"print", &".+.");
+/// let mut interp: BrainfuckInterpreter<u8> = BrainfuckInterpreter::new(0, false);
+/// let mut output = vec![];
+/// Pipeline::new()
+///     .stage(Stage::new(&set))
+///     .stage(Stage::new(&print))
+///     .run(&mut interp, &mut std::io::empty(), &mut output)
+///     .unwrap();
+/// assert_eq!(output, b"AB");
+/// ```
+#[derive(Default)]
+pub struct Pipeline<'a> {
+    stages: Vec<Stage<'a>>,
+}
+
+impl<'a> Pipeline<'a> {
+    /// Creates an empty pipeline.
+    pub fn new() -> Pipeline<'a> {
+        Pipeline { stages: vec![] }
+    }
+
+    /// Adds a stage to the end of the pipeline.
+    pub fn stage(mut self, stage: Stage<'a>) -> Pipeline<'a> {
+        self.stages.push(stage);
+        self
+    }
+
+    /// Runs every stage in order, stopping at the first that fails. Stages
+    /// without their own input or output use `input` and `output`.
+    pub fn run<T>(&mut self, interp: &mut BrainfuckInterpreter<T>, input: &mut dyn Read, output: &mut dyn Write) -> Result<(), StageError>
+    where T: IncDec, T: ByteCell, T: Default, T: Clone, T: PartialEq {
+        for (index, stage) in self.stages.iter_mut().enumerate() {
+            if stage.reset {
+                interp.reset();
+            }
+
+            let input: &mut dyn Read = match &mut stage.input {
+                Some(input) => &mut **input,
+                None => &mut *input,
+            };
+            let output: &mut dyn Write = match &mut stage.output {
+                Some(output) => &mut **output,
+                None => &mut *output,
+            };
+
+            interp.interpret(stage.program, input, output)
+                .map_err(|source| StageError::new(index, stage.program, source))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Runs every program at once, each on its own interpreter and thread,
+/// with the output of each program streamed into the input of the next.
+///
+/// The first program reads from `input` and the last writes to `output`.
+/// Between them, up to `capacity` bytes may be waiting to be read before
+/// a writing program blocks. A program that finishes closes its end of
+/// the pipe: the next program sees the end of its input, and the one
+/// before it stops quietly the next time it writes.
+///
+/// ```
+/// # use bft_interp::BrainfuckInterpreter;
+/// # use bft_interp::pipeline::pipe;
+/// # use bft_types::BrainfuckProgram;
+/// let echo = BrainfuckProgram::from_string(&"echo", &",[.,]");
+/// let increment = BrainfuckProgram::from_string(&"increment", &",[+.,]");
+/// let mut stages = vec![
+///     (&echo, BrainfuckInterpreter::<u8>::new(0, false)),
+///     (&increment, BrainfuckInterpreter::<u8>::new(0, false)),
+///     (&increment, BrainfuckInterpreter::<u8>::new(0, false)),
+/// ];
+/// let mut output = vec![];
+/// pipe(&mut stages, &mut "HAL".as_bytes(), &mut output, 16).unwrap();
+/// assert_eq!(output, b"JCN");
+/// ```
+pub fn pipe<T>(
+    stages: &mut [(&BrainfuckProgram, BrainfuckInterpreter<T>)],
+    input: &mut (dyn Read + Send),
+    output: &mut (dyn Write + Send),
+    capacity: usize,
+) -> Result<(), StageError>
+where T: IncDec, T: ByteCell, T: Default, T: Clone, T: PartialEq, T: Send {
+    let count = stages.len();
+    let results: Vec<_> = thread::scope(|scope| {
+        let mut input = Some(input);
+        let mut output = Some(output);
+        let mut upstream: Option<Receiver<u8>> = None;
+
+        let handles: Vec<_> = stages.iter_mut().enumerate().map(|(index, (program, interp))| {
+            let mut reader: Box<dyn Read + Send + '_> = match upstream.take() {
+                Some(receiver) => Box::new(PipeReader(receiver)),
+                None => Box::new(input.take().expect("only the first stage reads the input")),
+            };
+            let mut writer: Box<dyn Write + Send + '_> = if index + 1 == count {
+                Box::new(output.take().expect("only the last stage writes the output"))
+            } else {
+                let (sender, receiver) = mpsc::sync_channel(capacity);
+                upstream = Some(receiver);
+                Box::new(PipeWriter(sender))
+            };
+
+            let program: &BrainfuckProgram = program;
+            scope.spawn(move || interp.interpret(program, &mut reader, &mut writer))
+        }).collect();
+
+        handles.into_iter().map(|h| h.join().expect("pipeline stage panicked")).collect()
+    });
+
+    for (index, result) in results.into_iter().enumerate() {
+        match result {
+            // the next program finished first, so this one stops like a Unix pipe
+            Err(InterpError::Io { ref source, .. }) if index + 1 < count && source.kind() == io::ErrorKind::BrokenPipe => (),
+            Err(source) => return Err(StageError::new(index, stages[index].0, source)),
+            Ok(()) => (),
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads the bytes written to the other end of a [PipeWriter], reaching
+/// the end of input once it is dropped.
+struct PipeReader(Receiver<u8>);
+
+impl Read for PipeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        match self.0.recv() {
+            Ok(byte) => { buf[0] = byte; Ok(1) },
+            Err(_) => Ok(0),
+        }
+    }
+}
+
+/// Sends bytes to a [PipeReader], blocking while its buffer is full.
+struct PipeWriter(SyncSender<u8>);
+
+impl Write for PipeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for byte in buf {
+            self.0.send(*byte).map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The failure of one stage of a [Pipeline] or [pipe].
+#[derive(Debug)]
+pub struct StageError {
+    /// The index of the stage that failed, counting from zero.
+    pub stage: usize,
+
+    /// The name of the failing stage's program.
+    pub program: String,
+
+    /// The file the failing instruction was written in, which is only
+    /// different from the program when it was put together from several.
+    pub file: String,
+
+    pub source: InterpError,
+}
+
+impl StageError {
+    fn new(stage: usize, program: &BrainfuckProgram, source: InterpError) -> StageError {
+        let file = program.location(source.token()).file.to_string();
+        StageError { stage, program: program.name.clone(), file, source }
+    }
+}
+
+impl fmt::Display for StageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (stage {}): {}", self.program, self.stage + 1, self.source)?;
+        if self.file != self.program {
+            write!(f, " in {}", self.file)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for StageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pipe, Pipeline, Stage};
+    use crate::{BrainfuckInterpreter, InterpError};
+    use bft_types::preprocess::preprocess_with;
+    use bft_types::BrainfuckProgram;
+
+    #[test]
+    fn reset_between_stages() {
+        let add = BrainfuckProgram::from_string(&"add", &"+++>++");
+        let print = BrainfuckProgram::from_string(&"print", &".");
+        let mut interp: BrainfuckInterpreter<u8> = BrainfuckInterpreter::new(0, false);
+        let mut output = vec![];
+        Pipeline::new()
+            .stage(Stage::new(&add))
+            .stage(Stage::new(&print))
+            .stage(Stage::new(&print).reset())
+            .run(&mut interp, &mut std::io::empty(), &mut output)
+            .unwrap();
+        assert_eq!(output, [2, 0]);
+    }
+
+    #[test]
+    fn per_stage_io() {
+        let echo = BrainfuckProgram::from_string(&"echo", &",[.,]");
+        let mut first_in = "ab".as_bytes();
+        let mut first_out = vec![];
+        let mut shared_out = vec![];
+        Pipeline::new()
+            .stage(Stage::new(&echo).input(&mut first_in).output(&mut first_out))
+            .stage(Stage::new(&echo))
+            .run(&mut BrainfuckInterpreter::<u8>::new(0, false), &mut "cd".as_bytes(), &mut shared_out)
+            .unwrap();
+        assert_eq!(first_out, b"ab");
+        assert_eq!(shared_out, b"cd");
+    }
+
+    #[test]
+    fn reports_failing_stage() {
+        let fine = BrainfuckProgram::from_string(&"fine", &"+");
+        let broken = BrainfuckProgram::from_string(&"broken", &"\n <");
+        let error = Pipeline::new()
+            .stage(Stage::new(&fine))
+            .stage(Stage::new(&broken))
+            .run(&mut BrainfuckInterpreter::<u8>::new(0, false), &mut std::io::empty(), &mut vec![])
+            .unwrap_err();
+        assert_eq!(error.stage, 1);
+        assert!(matches!(error.source, InterpError::PointerUnderflow { line: 2, column: 2, .. }));
+        assert_eq!(error.to_string(), "broken (stage 2): pointer moved left of the first cell at line 2, column 2");
+
+        // an instruction brought in from elsewhere names the file it came from
+        let mut read = |path: &std::path::Path| Ok(match path.to_str() {
+            Some("main.bfm") => b"#include \"lib.bfm\"\n+@LEFT".to_vec(),
+            _ => b"#define LEFT <".to_vec(),
+        });
+        let included = preprocess_with(&"main.bfm", &mut read).unwrap().program;
+        let error = Pipeline::new()
+            .stage(Stage::new(&included))
+            .run(&mut BrainfuckInterpreter::<u8>::new(0, false), &mut std::io::empty(), &mut vec![])
+            .unwrap_err();
+        assert_eq!((error.source.token(), error.file.as_str()), (1, "lib.bfm"));
+        assert_eq!(error.to_string(), "main.bfm (stage 1): pointer moved left of the first cell at line 1, column 14 in lib.bfm");
+    }
+
+    #[test]
+    fn pipe_stops_upstream_quietly() {
+        // an endless stream of 'A's, cut off after the first two bytes
+        let endless = BrainfuckProgram::from_string(&"endless", &"++++++++[>++++++++<-]>+[.]");
+        let head = BrainfuckProgram::from_string(&"head", &",.,.");
+        let mut stages = vec![
+            (&endless, BrainfuckInterpreter::<u8>::new(0, false)),
+            (&head, BrainfuckInterpreter::<u8>::new(0, false)),
+        ];
+        let mut output = vec![];
+        pipe(&mut stages, &mut std::io::empty(), &mut output, 1).unwrap();
+        assert_eq!(output, b"AA");
+    }
+
+    #[test]
+    fn pipe_reports_failing_stage() {
+        let echo = BrainfuckProgram::from_string(&"echo", &",[.,]");
+        let broken = BrainfuckProgram::from_string(&"broken", &",[.,]<");
+        let mut stages = vec![
+            (&echo, BrainfuckInterpreter::<u8>::new(0, false)),
+            (&broken, BrainfuckInterpreter::<u8>::new(0, false)),
+            (&echo, BrainfuckInterpreter::<u8>::new(0, false)),
+        ];
+        let mut output = vec![];
+        let error = pipe(&mut stages, &mut "hi".as_bytes(), &mut output, 4).unwrap_err();
+        assert_eq!(error.stage, 1);
+        assert!(matches!(error.source, InterpError::PointerUnderflow { line: 1, column: 6, .. }));
+        assert_eq!(output, b"hi");
+        assert_eq!(stages[2].1.program_counter(), 5);
+    }
+}
@@ -0,0 +1,173 @@
+//! Serialisable checkpoints of a [BrainfuckInterpreter]'s state.
+
+use crate::{BrainfuckInterpreter, ByteCell, IncDec};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Result, Write};
+use std::path::Path;
+
+/// The state of a [BrainfuckInterpreter] at a point in time: the tape,
+/// the pointer, and the program counter.
+///
+/// Restoring a snapshot and calling [BrainfuckInterpreter::resume] with
+/// the same program carries on exactly where the snapshot was taken.
+///
+/// ```
+/// # use bft_interp::BrainfuckInterpreter;
+/// # use bft_types::BrainfuckProgram;
+/// let program = BrainfuckProgram::from_string(&"virtual", &"+++>++.");
+/// let mut interp: BrainfuckInterpreter<u8> = BrainfuckInterpreter::new(0, false);
+/// let mut output = vec![];
+/// for _ in 0..4 {
+///     interp.step(&program, &mut std::io::empty(), &mut output).unwrap();
+/// }
+///
+/// let snapshot = interp.snapshot();
+/// let mut resumed: BrainfuckInterpreter<u8> = BrainfuckInterpreter::new(0, false);
+/// resumed.restore(&snapshot);
+/// resumed.resume(&program, &mut std::io::empty(), &mut output).unwrap();
+/// assert_eq!(output, [2]);
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot<T> {
+    /// The tape up to its last non-zero cell.
+    tape: Vec<T>,
+    /// The full length of the tape, including trailing zero cells.
+    tape_size: usize,
+    tape_index: usize,
+    program_counter: usize,
+}
+
+impl<T> Snapshot<T>
+where T: Serialize, T: DeserializeOwned {
+
+    /// Writes the snapshot to a file at the given path as JSON.
+    pub fn save(&self, path: &dyn AsRef<Path>) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(&mut writer, self)?;
+        writer.flush()
+    }
+
+    /// Reads a snapshot previously written by [Snapshot::save].
+    pub fn load(path: &dyn AsRef<Path>) -> Result<Snapshot<T>> {
+        let snapshot: Snapshot<T> = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        if snapshot.tape.len() > snapshot.tape_size || snapshot.tape_index >= snapshot.tape_size {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Snapshot \"{}\" has a pointer or tape outside its tape size", path.as_ref().display()),
+            ));
+        }
+        Ok(snapshot)
+    }
+}
+
+impl<T> Snapshot<T> {
+    /// Gets the saved tape, up to its last non-zero cell.
+    pub fn tape(&self) -> &[T] {
+        self.tape.as_slice()
+    }
+
+    /// Gets the full length of the saved tape.
+    pub fn tape_size(&self) -> usize {
+        self.tape_size
+    }
+
+    /// Gets the saved index of the cell the pointer is at.
+    pub fn tape_index(&self) -> usize {
+        self.tape_index
+    }
+
+    /// Gets the saved index of the next instruction to be executed.
+    pub fn program_counter(&self) -> usize {
+        self.program_counter
+    }
+}
+
+impl<T> BrainfuckInterpreter<T>
+where T: IncDec, T: ByteCell, T: Default, T: Clone, T: PartialEq {
+
+    /// Takes a [Snapshot] of the current state of the interpreter.
+    pub fn snapshot(&self) -> Snapshot<T> {
+        let used = self.tape.iter()
+            .rposition(|cell| *cell != T::default())
+            .map_or(0, |last| last + 1);
+
+        Snapshot {
+            tape: self.tape[..used].to_vec(),
+            tape_size: self.tape.len(),
+            tape_index: self.tape_index,
+            program_counter: self.program_counter,
+        }
+    }
+
+    /// Replaces the state of the interpreter with that of a [Snapshot].
+    /// Whether the tape may grow is kept from the interpreter itself.
+    pub fn restore(&mut self, snapshot: &Snapshot<T>) {
+        let tape_size = snapshot.tape_size
+            .max(snapshot.tape.len())
+            .max(snapshot.tape_index + 1);
+
+        self.tape = snapshot.tape.clone();
+        self.tape.resize(tape_size, T::default());
+        self.tape_index = snapshot.tape_index;
+        self.program_counter = snapshot.program_counter;
+        self.forget_cycles();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{BrainfuckInterpreter, Snapshot};
+    use bft_types::BrainfuckProgram;
+    use std::env;
+    use std::path::PathBuf;
+
+    /// Runs a program to completion and compares the resulting state with
+    /// the golden snapshot of the same name. Set `BFT_BLESS` to rewrite it.
+    fn assert_golden(name: &str, source: &str, input: &[u8]) {
+        let program = BrainfuckProgram::from_string(&name, &source);
+        let mut interp: BrainfuckInterpreter<u8> = BrainfuckInterpreter::new(0, false);
+        interp.interpret(&program, &mut &input[..], &mut vec![]).unwrap();
+        let snapshot = interp.snapshot();
+
+        let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "golden", &format!("{}.json", name)].iter().collect();
+        if env::var_os("BFT_BLESS").is_some() {
+            snapshot.save(&path).unwrap();
+        }
+        assert_eq!(snapshot, Snapshot::load(&path).unwrap());
+    }
+
+    #[test]
+    fn golden_hello_world() {
+        assert_golden("hello_world", include_str!("../../brainfuck.b"), b"");
+    }
+
+    #[test]
+    fn golden_reverse() {
+        assert_golden("reverse", ">,[>,]<[.<]", b"stressed");
+    }
+
+    #[test]
+    fn save_and_load() {
+        let program = BrainfuckProgram::from_string(&"./virtual", &"++>+++[-]");
+        let mut interp: BrainfuckInterpreter<u8> = BrainfuckInterpreter::new(10, false);
+        for _ in 0..5 {
+            interp.step(&program, &mut std::io::empty(), &mut vec![]).unwrap();
+        }
+
+        let path = env::temp_dir().join(format!("bft-snapshot-{}.json", std::process::id()));
+        interp.snapshot().save(&path).unwrap();
+        let loaded: Snapshot<u8> = Snapshot::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.tape(), &[2, 2]);
+        assert_eq!((loaded.tape_size(), loaded.tape_index(), loaded.program_counter()), (10, 1, 5));
+
+        let mut resumed: BrainfuckInterpreter<u8> = BrainfuckInterpreter::new(0, false);
+        resumed.restore(&loaded);
+        resumed.resume(&program, &mut std::io::empty(), &mut vec![]).unwrap();
+        assert_eq!(resumed.tape().len(), 10);
+        assert_eq!(resumed.tape()[..2], [2, 0]);
+    }
+}
@@ -0,0 +1,266 @@
+//! Recording, verification and comparison of execution traces.
+//!
+//! A trace is a log of every instruction executed by a
+//! [BrainfuckInterpreter], stored as one JSON [TraceEntry] per line.
+
+use crate::{BrainfuckInterpreter, ByteCell, IncDec, InterpError};
+use bft_types::{BrainfuckProgram, RawInstruction};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{self, BufRead, Read, Write};
+
+/// A single executed instruction, and its effect on the tape.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TraceEntry<T> {
+    /// The number of instructions executed before this one.
+    pub step: u64,
+    pub line: usize,
+    pub column: usize,
+    pub instruction: char,
+    /// The index of the cell the pointer was at when the instruction ran.
+    pub pointer: usize,
+    /// The value of the cell under the pointer before the instruction.
+    pub before: T,
+    /// The value of the same cell after the instruction.
+    pub after: T,
+    /// The byte read or written, if the instruction was an input or
+    /// output. Reading past the end of input records [None].
+    pub io: Option<u8>,
+}
+
+impl<T: fmt::Display> fmt::Display for TraceEntry<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "step {} `{}` at line {}, column {}: cell {} {} -> {}",
+            self.step, self.instruction, self.line, self.column, self.pointer, self.before, self.after,
+        )?;
+        match self.io {
+            Some(byte) => write!(f, " (io {})", byte),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The first point at which two traces disagree. Either side is [None]
+/// if that trace ended before the other.
+#[derive(Debug, PartialEq)]
+pub struct Divergence<T> {
+    pub step: u64,
+    pub expected: Option<TraceEntry<T>>,
+    pub actual: Option<TraceEntry<T>>,
+}
+
+impl<T: fmt::Display> fmt::Display for Divergence<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let describe = |entry: &Option<TraceEntry<T>>| match entry {
+            Some(entry) => entry.to_string(),
+            None => "end of trace".to_string(),
+        };
+        write!(
+            f,
+            "traces diverge at step {}\n  expected: {}\n    actual: {}",
+            self.step, describe(&self.expected), describe(&self.actual),
+        )
+    }
+}
+
+/// Wraps an input, remembering the last byte read through it.
+struct RecordingReader<'a> {
+    inner: &'a mut dyn Read,
+    last: Option<u8>,
+}
+
+impl Read for RecordingReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.last = if read > 0 { Some(buf[0]) } else { None };
+        Ok(read)
+    }
+}
+
+impl<T> BrainfuckInterpreter<T>
+where T: IncDec, T: ByteCell, T: Default, T: Clone, T: PartialEq {
+
+    /// Executes the single instruction at the program counter like
+    /// [BrainfuckInterpreter::step], describing what it did. Returns
+    /// [None] if the program had already finished.
+    pub fn step_traced(&mut self, program: &BrainfuckProgram, step: u64, input: &mut dyn Read, output: &mut dyn Write) -> Result<Option<TraceEntry<T>>, InterpError> {
+        let token = match program.get_tokens().get(self.program_counter) {
+            Some(token) => token,
+            None => return Ok(None),
+        };
+
+        let pointer = self.tape_index;
+        let before = self.tape[pointer].clone();
+        let mut input = RecordingReader { inner: input, last: None };
+        self.step(program, &mut input, output)?;
+
+        let io = match token.instruction {
+            RawInstruction::Input => input.last,
+            RawInstruction::Output => Some(before.to_byte()),
+            _ => None,
+        };
+
+        Ok(Some(TraceEntry {
+            step,
+            line: token.line(),
+            column: token.column(),
+            instruction: token.instruction.to_char(),
+            pointer,
+            before,
+            after: self.tape[pointer].clone(),
+            io,
+        }))
+    }
+
+    /// Interprets the program like [BrainfuckInterpreter::interpret], writing
+    /// a [TraceEntry] for every executed instruction to `trace` as a line of JSON.
+    /// Flushing `trace` is left to the caller.
+    pub fn trace(&mut self, program: &BrainfuckProgram, input: &mut dyn Read, output: &mut dyn Write, trace: &mut dyn Write) -> Result<(), InterpError>
+    where T: Serialize {
+        crate::check_brackets(program)?;
+        self.program_counter = 0;
+        self.steps = 0;
+        self.forget_cycles();
+        let mut step = 0;
+        loop {
+            let index = self.program_counter;
+            let entry = match self.step_traced(program, step, input, output)? {
+                Some(entry) => entry,
+                None => break,
+            };
+            serde_json::to_writer(&mut *trace, &entry)
+                .map_err(io::Error::from)
+                .and_then(|_| trace.write_all(b"\n"))
+                .map_err(|source| InterpError::Io { line: entry.line, column: entry.column, token: index, source })?;
+            step += 1;
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads a trace written by [BrainfuckInterpreter::trace].
+pub fn read_trace<T: DeserializeOwned>(reader: &mut dyn BufRead) -> io::Result<Vec<TraceEntry<T>>> {
+    reader.lines()
+        .filter(|line| line.as_ref().map_or(true, |l| !l.trim().is_empty()))
+        .map(|line| Ok(serde_json::from_str(&line?)?))
+        .collect()
+}
+
+/// Finds the first entry at which two traces differ, if any.
+pub fn diff<T: Clone + PartialEq>(expected: &[TraceEntry<T>], actual: &[TraceEntry<T>]) -> Option<Divergence<T>> {
+    let length = expected.len().max(actual.len());
+    (0..length)
+        .find(|&i| expected.get(i) != actual.get(i))
+        .map(|i| Divergence {
+            step: i as u64,
+            expected: expected.get(i).cloned(),
+            actual: actual.get(i).cloned(),
+        })
+}
+
+/// Replays a recorded trace against the program on the given interpreter,
+/// feeding it the input bytes the trace recorded, and reports the first
+/// step at which the interpreter's behaviour differs from the recording.
+/// A replay that fails before the recording ends has diverged there.
+///
+/// A trace doesn't record how its run ended, so once every recorded step
+/// matches, the replay only has to stop where the recording did: whether
+/// it then finishes cleanly or fails isn't checked.
+pub fn verify<T>(interp: &mut BrainfuckInterpreter<T>, program: &BrainfuckProgram, expected: &[TraceEntry<T>]) -> Option<Divergence<T>>
+where T: IncDec, T: ByteCell, T: Default, T: Clone, T: PartialEq {
+    let input: Vec<u8> = expected.iter()
+        .filter(|entry| entry.instruction == ',')
+        .filter_map(|entry| entry.io)
+        .collect();
+    let mut input = &input[..];
+
+    interp.program_counter = 0;
+    interp.steps = 0;
+    interp.forget_cycles();
+    for (step, recorded) in expected.iter().enumerate() {
+        let actual = interp.step_traced(program, step as u64, &mut input, &mut io::sink()).unwrap_or(None);
+        if actual.as_ref() != Some(recorded) {
+            return Some(Divergence { step: step as u64, expected: Some(recorded.clone()), actual });
+        }
+    }
+
+    let step = expected.len() as u64;
+    match interp.step_traced(program, step, &mut input, &mut io::sink()) {
+        Ok(Some(actual)) => Some(Divergence { step, expected: None, actual: Some(actual) }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff, read_trace, verify, TraceEntry};
+    use crate::BrainfuckInterpreter;
+    use bft_types::BrainfuckProgram;
+
+    fn record(source: &str, input: &[u8], tape_size: usize) -> Vec<TraceEntry<u8>> {
+        let program = BrainfuckProgram::from_string(&"./virtual", &source);
+        let mut interp: BrainfuckInterpreter<u8> = BrainfuckInterpreter::new(tape_size, false);
+        let mut trace = vec![];
+        let _ = interp.trace(&program, &mut &input[..], &mut vec![], &mut trace);
+        read_trace(&mut &trace[..]).unwrap()
+    }
+
+    #[test]
+    fn records_io_and_cells() {
+        let trace = record(",+.", b"a", 0);
+        assert_eq!(trace.len(), 3);
+        assert_eq!((trace[0].before, trace[0].after, trace[0].io), (0, b'a', Some(b'a')));
+        assert_eq!((trace[2].instruction, trace[2].io), ('.', Some(b'b')));
+        assert_eq!((trace[1].line, trace[1].column, trace[1].step), (1, 2, 1));
+    }
+
+    #[test]
+    fn traces_again_after_cycles_were_seen() {
+        let program = BrainfuckProgram::from_string(&"./virtual", &"++[-]");
+        let mut interp: BrainfuckInterpreter<u8> = BrainfuckInterpreter::new(1, false);
+        interp.set_cycle_detection(true);
+        for _ in 0..2 {
+            interp.trace(&program, &mut &b""[..], &mut vec![], &mut vec![]).unwrap();
+        }
+    }
+
+    #[test]
+    fn verify_round_trip() {
+        let source = ",[.,]";
+        let trace = record(source, b"abc", 0);
+        let program = BrainfuckProgram::from_string(&"./virtual", &source);
+        let mut interp: BrainfuckInterpreter<u8> = BrainfuckInterpreter::new(0, false);
+        assert_eq!(verify(&mut interp, &program, &trace), None);
+    }
+
+    #[test]
+    fn verify_detects_tampering() {
+        let source = "+++.";
+        let mut trace = record(source, b"", 0);
+        trace[1].after = 7;
+        let program = BrainfuckProgram::from_string(&"./virtual", &source);
+        let mut interp: BrainfuckInterpreter<u8> = BrainfuckInterpreter::new(0, false);
+        assert_eq!(verify(&mut interp, &program, &trace).unwrap().step, 1);
+
+        // a replay failing early has diverged, rather than failed to verify
+        let trace = record("+>+", b"", 2);
+        let mut small: BrainfuckInterpreter<u8> = BrainfuckInterpreter::new(1, false);
+        let divergence = verify(&mut small, &BrainfuckProgram::from_string(&"./virtual", &"+>+"), &trace).unwrap();
+        assert_eq!((divergence.step, divergence.actual), (1, None));
+    }
+
+    #[test]
+    fn diff_configurations() {
+        // a smaller tape stops the second run early
+        let large = record("+>>+", b"", 10);
+        let small = record("+>>+", b"", 2);
+        let divergence = diff(&large, &small).unwrap();
+        assert_eq!(divergence.step, 2);
+        assert_eq!(divergence.actual, None);
+        assert_eq!(diff(&large, &large), None);
+    }
+}
@@ -0,0 +1,113 @@
+//! Static analyses over a [BrainfuckProgram] used to answer editor queries.
+
+use bft_interp::bounds::Offsets;
+use bft_interp::ir::{IrProgram, Op};
+use bft_types::BrainfuckProgram;
+
+/// Determines, for every token in the program, the offsets from its
+/// starting cell that the data pointer can be at when that token executes,
+/// as worked out by [bft_interp::bounds]. Gives nothing for a program whose
+/// loop brackets do not match.
+pub fn pointer_offsets(program: &BrainfuckProgram) -> Option<Vec<Offsets>> {
+    let ir = IrProgram::compile(program).ok()?;
+    let mut offsets = Vec::with_capacity(program.get_tokens().len());
+
+    for (index, node) in ir.nodes().iter().enumerate() {
+        let position = ir.bounds().position(index)?;
+        // a move is only recorded once it has moved, so count each of its
+        // tokens back from there
+        let shift = |by: isize| Offsets { min: position.min.map(|m| m + by), max: position.max.map(|m| m + by) };
+        for (step, _) in node.tokens().enumerate() {
+            offsets.push(match node.op {
+                Op::Right(distance) => shift(step as isize - distance as isize),
+                Op::Left(distance) => shift(distance as isize - step as isize),
+                _ => position,
+            });
+        }
+    }
+
+    Some(offsets)
+}
+
+/// Re-indents each line of the source by the loop depth it starts at,
+/// using `indent` once per level. Lines that open with closing brackets
+/// are dedented to line up with their partners, and trailing whitespace
+/// is removed. Comments are otherwise left untouched.
+pub fn format(text: &str, indent: &str) -> String {
+    let mut depth = 0usize;
+    let mut lines = vec![];
+
+    for line in text.lines() {
+        let trimmed = line.trim();
+        let closing = trimmed.chars().take_while(|c| *c == ']').count();
+        if trimmed.is_empty() {
+            lines.push(String::new());
+        } else {
+            lines.push(indent.repeat(depth.saturating_sub(closing)) + trimmed);
+        }
+
+        for c in trimmed.chars() {
+            match c {
+                '[' => depth += 1,
+                ']' => depth = depth.saturating_sub(1),
+                _ => (),
+            }
+        }
+    }
+
+    let mut formatted = lines.join("\n");
+    if text.ends_with('\n') {
+        formatted.push('\n');
+    }
+    formatted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format, pointer_offsets};
+    use bft_interp::bounds::Offsets;
+    use bft_types::BrainfuckProgram;
+
+    fn offsets(offsets: &[(Option<isize>, Option<isize>)]) -> Option<Vec<Offsets>> {
+        Some(offsets.iter().map(|&(min, max)| Offsets { min, max }).collect())
+    }
+
+    #[test]
+    fn offsets_through_balanced_loop() {
+        let program = BrainfuckProgram::from_string(&"virtual", &">[>+<-]<");
+        let known = |offset| (Some(offset), Some(offset));
+        assert_eq!(
+            pointer_offsets(&program),
+            offsets(&[known(0), known(1), known(1), known(2), known(2), known(1), known(1), known(1)])
+        );
+    }
+
+    #[test]
+    fn offsets_after_drifting_loop() {
+        let program = BrainfuckProgram::from_string(&"virtual", &"+[>]>>");
+        assert_eq!(
+            pointer_offsets(&program),
+            offsets(&[(Some(0), Some(0)), (Some(0), None), (Some(0), None), (Some(0), None), (Some(0), None), (Some(1), None)])
+        );
+    }
+
+    #[test]
+    fn offsets_with_nested_drifting_loop() {
+        let program = BrainfuckProgram::from_string(&"virtual", &"[[<]>]+");
+        assert_eq!(pointer_offsets(&program).unwrap()[6], Offsets { min: None, max: None });
+        assert_eq!(pointer_offsets(&BrainfuckProgram::from_string(&"virtual", &"+]")), None);
+    }
+
+    #[test]
+    fn format_nested() {
+        assert_eq!(
+            format("+[  \n-[\n>\n]\n ]<\n", "  "),
+            "+[\n  -[\n    >\n  ]\n]<\n"
+        );
+    }
+
+    #[test]
+    fn format_unopened() {
+        assert_eq!(format("]\n  +", "\t"), "]\n+");
+    }
+}
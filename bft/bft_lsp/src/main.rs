@@ -0,0 +1,18 @@
+//! A language server for brainfuck, speaking JSON-RPC over stdio.
+//!
+//! Supports diagnostics for mismatched brackets and moves left of the
+//! first cell, hovering an instruction to see the pointer offsets it can
+//! run at, jumping to a matching loop bracket, and re-indenting a document
+//! by loop depth.
+
+use std::io;
+
+mod analysis;
+mod rpc;
+mod server;
+
+fn main() -> io::Result<()> {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    server::serve(&mut stdin.lock(), &mut stdout.lock())
+}
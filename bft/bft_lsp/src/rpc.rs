@@ -0,0 +1,72 @@
+//! Reading and writing of `Content-Length` framed JSON-RPC messages.
+
+use serde_json::Value;
+use std::io::{BufRead, Error, ErrorKind, Result, Write};
+
+/// Reads a single message from the stream, returning [None] once the
+/// stream has been closed.
+pub fn read_message(reader: &mut dyn BufRead) -> Result<Option<Value>> {
+    let mut length = None;
+
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = Some(value.trim().parse::<usize>()
+                    .map_err(|e| Error::new(ErrorKind::InvalidData, e))?);
+            }
+        }
+    }
+
+    let length = length.ok_or_else(|| Error::new(
+        ErrorKind::InvalidData,
+        "Message is missing a Content-Length header",
+    ))?;
+
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e))
+}
+
+/// Writes a single message to the stream.
+pub fn write_message(writer: &mut dyn Write, message: &Value) -> Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_message, write_message};
+    use serde_json::json;
+    use std::io::Cursor;
+
+    #[test]
+    fn round_trip() {
+        let mut buffer = vec![];
+        write_message(&mut buffer, &json!({"jsonrpc": "2.0", "id": 1})).unwrap();
+        write_message(&mut buffer, &json!({"jsonrpc": "2.0", "id": 2})).unwrap();
+
+        let mut reader = Cursor::new(buffer);
+        assert_eq!(read_message(&mut reader).unwrap().unwrap()["id"], 1);
+        assert_eq!(read_message(&mut reader).unwrap().unwrap()["id"], 2);
+        assert!(read_message(&mut reader).unwrap().is_none());
+    }
+
+    #[test]
+    fn missing_length() {
+        let mut reader = Cursor::new(b"Content-Type: foo\r\n\r\n{}".to_vec());
+        assert!(read_message(&mut reader).is_err());
+    }
+}
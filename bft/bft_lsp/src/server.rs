@@ -0,0 +1,445 @@
+//! The language server itself, dispatching JSON-RPC requests to handlers.
+
+use crate::analysis;
+use crate::rpc::{read_message, write_message};
+use bft_interp::bounds;
+use bft_interp::ir::IrProgram;
+use bft_types::{BrainfuckProgram, RawInstruction, Token, UnmatchedBracket};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{BufRead, Result, Write};
+
+/// JSON-RPC error code for a method the server does not support.
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// JSON-RPC error code for a request with malformed parameters.
+const INVALID_PARAMS: i64 = -32602;
+
+/// Runs the server until the client sends `exit` or closes the stream.
+pub fn serve(reader: &mut dyn BufRead, writer: &mut dyn Write) -> Result<()> {
+    let mut server = Server::default();
+    while let Some(message) = read_message(reader)? {
+        if !server.handle(&message, writer)? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Holds the text of every document the client has opened.
+#[derive(Default)]
+struct Server {
+    documents: HashMap<String, String>,
+}
+
+impl Server {
+    /// Handles a single incoming message, returning `false` once the
+    /// client has asked the server to exit.
+    fn handle(&mut self, message: &Value, writer: &mut dyn Write) -> Result<bool> {
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+
+        let result = match method {
+            "initialize" => Some(Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "documentFormattingProvider": true,
+                },
+                "serverInfo": { "name": "bft_lsp" },
+            }))),
+            "shutdown" => Some(Ok(Value::Null)),
+            "exit" => return Ok(false),
+            "textDocument/didOpen" => {
+                let document = &params["textDocument"];
+                self.update(document["uri"].as_str(), document["text"].as_str(), writer)?;
+                None
+            }
+            "textDocument/didChange" => {
+                // we only advertise full sync, so the last change holds the whole text
+                let text = params["contentChanges"].as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str());
+                self.update(params["textDocument"]["uri"].as_str(), text, writer)?;
+                None
+            }
+            "textDocument/didClose" => {
+                if let Some(uri) = params["textDocument"]["uri"].as_str() {
+                    self.documents.remove(uri);
+                    publish_diagnostics(uri, vec![], writer)?;
+                }
+                None
+            }
+            "textDocument/hover" => Some(self.hover(params)),
+            "textDocument/definition" => Some(self.definition(params)),
+            "textDocument/formatting" => Some(self.formatting(params)),
+            _ => Some(Err((METHOD_NOT_FOUND, format!("Unsupported method \"{}\"", method)))),
+        };
+
+        // notifications carry no id and must never be answered
+        let id = match message.get("id") {
+            Some(id) => id,
+            None => return Ok(true),
+        };
+
+        let response = match result {
+            Some(Ok(result)) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+            Some(Err((code, reason))) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": {"code": code, "message": reason},
+            }),
+            None => return Ok(true),
+        };
+
+        write_message(writer, &response)?;
+        Ok(true)
+    }
+
+    /// Stores the new text of a document and publishes its diagnostics.
+    fn update(&mut self, uri: Option<&str>, text: Option<&str>, writer: &mut dyn Write) -> Result<()> {
+        let (uri, text) = match (uri, text) {
+            (Some(uri), Some(text)) => (uri, text),
+            _ => return Ok(()),
+        };
+
+        let (program, warnings) = BrainfuckProgram::from_string_strict(&uri, &text);
+        let tokens = program.get_tokens();
+        let mut diagnostics: Vec<_> = program.bracket_pairs().1.into_iter()
+            .map(|bracket| {
+                let message = match bracket {
+                    UnmatchedBracket::Unclosed(_) => "Unmatched `[`: this loop is never closed",
+                    UnmatchedBracket::Unopened(_) => "Unmatched `]`: there is no loop to close",
+                };
+                json!({
+                    "range": range(text, &tokens[bracket.index()]),
+                    "severity": 1,
+                    "source": "bft",
+                    "message": message,
+                })
+            })
+            .collect();
+        diagnostics.extend(warnings.iter().map(|warning| json!({
+            "range": position_range(text, warning.line(), warning.column()),
+            "severity": 2,
+            "source": "bft",
+            "message": warning.to_string(),
+        })));
+        // moving left of the starting cell fails however long the tape is
+        if let Ok(ir) = IrProgram::compile(&program) {
+            diagnostics.extend(bounds::failures(&ir, isize::MAX as usize).iter().map(|failure| json!({
+                "range": range(text, &tokens[failure.token()]),
+                "severity": 2,
+                "source": "bft",
+                "message": format!("This will fail if it gets this far: {}", failure),
+            })));
+        }
+
+        self.documents.insert(uri.to_string(), text.to_string());
+        publish_diagnostics(uri, diagnostics, writer)
+    }
+
+    /// Parses the document referenced by the request parameters, giving
+    /// back its uri and text along with the program.
+    fn program(&self, params: &Value) -> std::result::Result<(String, &str, BrainfuckProgram), (i64, String)> {
+        let uri = params["textDocument"]["uri"].as_str()
+            .ok_or_else(|| (INVALID_PARAMS, "Missing document uri".to_string()))?;
+        let text = self.documents.get(uri)
+            .ok_or_else(|| (INVALID_PARAMS, format!("Unknown document \"{}\"", uri)))?;
+        Ok((uri.to_string(), text, BrainfuckProgram::from_string(&uri, text)))
+    }
+
+    /// Shows the static pointer offsets of the hovered instruction.
+    fn hover(&self, params: &Value) -> std::result::Result<Value, (i64, String)> {
+        let (_, text, program) = self.program(params)?;
+        let index = match token_at(text, &program, &params["position"]) {
+            Some(index) => index,
+            None => return Ok(Value::Null),
+        };
+
+        let offsets = analysis::pointer_offsets(&program).map(|offsets| offsets[index]);
+        let contents = match offsets.map(|offsets| (offsets.min, offsets.max)) {
+            Some((Some(min), Some(max))) if min == max => format!("Pointer offset: `{:+}`", min),
+            Some((Some(min), Some(max))) => format!("Pointer offset: between `{:+}` and `{:+}`", min, max),
+            Some((Some(min), None)) => format!("Pointer offset: `{:+}` or more", min),
+            Some((None, Some(max))) => format!("Pointer offset: `{:+}` or less", max),
+            _ => "Pointer offset cannot be determined statically".to_string(),
+        };
+
+        Ok(json!({
+            "contents": {"kind": "markdown", "value": contents},
+            "range": range(text, &program.get_tokens()[index]),
+        }))
+    }
+
+    /// Jumps from a loop bracket to its partner.
+    fn definition(&self, params: &Value) -> std::result::Result<Value, (i64, String)> {
+        let (uri, text, program) = self.program(params)?;
+        let index = match token_at(text, &program, &params["position"]) {
+            Some(index) => index,
+            None => return Ok(Value::Null),
+        };
+
+        let partner = program.bracket_pairs().0.into_iter()
+            .find_map(|(open, close)| match index {
+                i if i == open => Some(close),
+                i if i == close => Some(open),
+                _ => None,
+            });
+
+        Ok(match partner {
+            Some(partner) => json!({"uri": uri, "range": range(text, &program.get_tokens()[partner])}),
+            None => Value::Null,
+        })
+    }
+
+    /// Re-indents the whole document by loop depth.
+    fn formatting(&self, params: &Value) -> std::result::Result<Value, (i64, String)> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let text = self.documents.get(uri)
+            .ok_or_else(|| (INVALID_PARAMS, format!("Unknown document \"{}\"", uri)))?;
+
+        let options = &params["options"];
+        let tab_size = options["tabSize"].as_u64().unwrap_or(4) as usize;
+        let indent = match options["insertSpaces"].as_bool() {
+            Some(false) => "\t".to_string(),
+            _ => " ".repeat(tab_size),
+        };
+
+        let formatted = analysis::format(text, &indent);
+        if &formatted == text {
+            return Ok(json!([]));
+        }
+
+        // replace everything, ending the range past the last line
+        let lines = text.lines().count() + 1;
+        Ok(json!([{
+            "range": {
+                "start": {"line": 0, "character": 0},
+                "end": {"line": lines, "character": 0},
+            },
+            "newText": formatted,
+        }]))
+    }
+}
+
+/// Sends the diagnostics for a document to the client.
+fn publish_diagnostics(uri: &str, diagnostics: Vec<Value>, writer: &mut dyn Write) -> Result<()> {
+    write_message(writer, &json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": {"uri": uri, "diagnostics": diagnostics},
+    }))
+}
+
+/// Converts the one-based position of a [Token] in `text` to a zero-based
+/// LSP range.
+fn range(text: &str, token: &Token) -> Value {
+    position_range(text, token.line(), token.column())
+}
+
+/// Converts a one-based line and column in `text` to a zero-based LSP range
+/// covering a single character. Columns count characters, but LSP counts
+/// UTF-16 code units, so anything outside the Basic Multilingual Plane
+/// earlier on the line takes up two.
+fn position_range(text: &str, line: usize, column: usize) -> Value {
+    let chars: Vec<char> = text.lines().nth(line - 1).unwrap_or_default().chars().collect();
+    let start: usize = chars.iter().take(column - 1).map(|c| c.len_utf16()).sum();
+    let width = chars.get(column - 1).map_or(1, |c| c.len_utf16());
+    json!({
+        "start": {"line": line - 1, "character": start},
+        "end": {"line": line - 1, "character": start + width},
+    })
+}
+
+/// Finds the token under an LSP position in `text`, falling back to the
+/// token immediately before it so that a cursor placed after a bracket works.
+fn token_at(text: &str, program: &BrainfuckProgram, position: &Value) -> Option<usize> {
+    let line = position["line"].as_u64()? as usize + 1;
+    let offset = position["character"].as_u64()? as usize;
+    let tokens = program.get_tokens();
+
+    // count the characters that end at or before the UTF-16 offset
+    let mut units = 0;
+    let column = text.lines().nth(line - 1).unwrap_or_default().chars()
+        .take_while(|c| {
+            units += c.len_utf16();
+            units <= offset
+        })
+        .count() + 1;
+
+    let find = |column: usize| tokens.iter()
+        .position(|t| t.line() == line && t.column() == column);
+
+    find(column).or_else(|| match find(column - 1) {
+        Some(index) if tokens[index].instruction == RawInstruction::StartLoop
+            || tokens[index].instruction == RawInstruction::EndLoop => Some(index),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::serve;
+    use crate::rpc::{read_message, write_message};
+    use serde_json::{json, Value};
+    use std::io::Cursor;
+
+    /// Runs a session with the given messages, returning everything the server sent.
+    fn session(messages: &[Value]) -> Vec<Value> {
+        let mut input = vec![];
+        for message in messages {
+            write_message(&mut input, message).unwrap();
+        }
+
+        let mut output = vec![];
+        serve(&mut Cursor::new(input), &mut output).unwrap();
+
+        let mut output = Cursor::new(output);
+        let mut responses = vec![];
+        while let Some(response) = read_message(&mut output).unwrap() {
+            responses.push(response);
+        }
+        responses
+    }
+
+    fn open(text: &str) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": {"textDocument": {"uri": "file:///a.b", "languageId": "brainfuck", "version": 1, "text": text}},
+        })
+    }
+
+    fn request(id: u64, method: &str, line: u64, character: u64) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": {
+                "textDocument": {"uri": "file:///a.b"},
+                "position": {"line": line, "character": character},
+            },
+        })
+    }
+
+    #[test]
+    fn initialize_and_exit() {
+        let responses = session(&[
+            json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {}}),
+            json!({"jsonrpc": "2.0", "method": "initialized", "params": {}}),
+            json!({"jsonrpc": "2.0", "id": 2, "method": "shutdown"}),
+            json!({"jsonrpc": "2.0", "method": "exit"}),
+            json!({"jsonrpc": "2.0", "id": 3, "method": "shutdown"}),
+        ]);
+
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0]["result"]["capabilities"]["hoverProvider"], true);
+        assert_eq!(responses[1], json!({"jsonrpc": "2.0", "id": 2, "result": null}));
+    }
+
+    #[test]
+    fn bracket_diagnostics() {
+        let responses = session(&[open("+[\n]]")]);
+
+        let diagnostics = &responses[0]["params"]["diagnostics"];
+        assert_eq!(diagnostics.as_array().unwrap().len(), 1);
+        assert_eq!(diagnostics[0]["range"]["start"], json!({"line": 1, "character": 1}));
+    }
+
+    #[test]
+    fn underflow_diagnostics() {
+        let responses = session(&[open("+[>+<-]\n><<")]);
+
+        let diagnostics = responses[0]["params"]["diagnostics"].as_array().unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!((diagnostics[0]["severity"].as_u64(), &diagnostics[0]["range"]["start"]), (Some(2), &json!({"line": 1, "character": 2})));
+        assert_eq!(diagnostics[0]["message"], "This will fail if it gets this far: pointer moved left of the first cell at line 2, column 3");
+    }
+
+    #[test]
+    fn strict_warning_diagnostics() {
+        let responses = session(&[open("Add two numbers.\n+\u{2013}[")]);
+
+        let diagnostics = responses[0]["params"]["diagnostics"].as_array().unwrap();
+        let found: Vec<_> = diagnostics.iter()
+            .map(|d| (d["severity"].as_u64().unwrap(), d["range"]["start"].clone()))
+            .collect();
+        assert_eq!(found, vec![
+            (1, json!({"line": 1, "character": 2})),
+            (2, json!({"line": 0, "character": 15})),
+            (2, json!({"line": 1, "character": 1})),
+        ]);
+    }
+
+    #[test]
+    fn hover_offset() {
+        let responses = session(&[
+            open(">>[-]<\n[>]<[<]>"),
+            request(1, "textDocument/hover", 0, 5),
+            request(2, "textDocument/hover", 1, 3),
+            request(3, "textDocument/hover", 0, 6),
+            request(4, "textDocument/hover", 1, 7),
+        ]);
+
+        assert_eq!(responses[1]["result"]["contents"]["value"], "Pointer offset: `+2`");
+        assert_eq!(responses[2]["result"]["contents"]["value"], "Pointer offset: `+1` or more");
+        assert_eq!(responses[3]["result"], Value::Null);
+        assert_eq!(
+            responses[4]["result"]["contents"]["value"],
+            "Pointer offset cannot be determined statically"
+        );
+    }
+
+    #[test]
+    fn positions_count_utf16_units() {
+        // the emoji takes up two UTF-16 code units, so everything after it is one further on
+        let responses = session(&[
+            open("\u{1f600} [-]\n]"),
+            request(1, "textDocument/definition", 0, 3),
+            request(2, "textDocument/hover", 0, 4),
+        ]);
+
+        let diagnostics = &responses[0]["params"]["diagnostics"];
+        assert_eq!(diagnostics[0]["range"]["start"], json!({"line": 1, "character": 0}));
+        assert_eq!(responses[1]["result"]["range"]["start"], json!({"line": 0, "character": 5}));
+        assert_eq!(responses[2]["result"]["range"], json!({"start": {"line": 0, "character": 4}, "end": {"line": 0, "character": 5}}));
+    }
+
+    #[test]
+    fn goto_matching_bracket() {
+        let responses = session(&[
+            open("+[\n  [-]\n]"),
+            request(1, "textDocument/definition", 0, 1),
+            request(2, "textDocument/definition", 1, 5),
+        ]);
+
+        assert_eq!(responses[1]["result"]["range"]["start"], json!({"line": 2, "character": 0}));
+        assert_eq!(responses[2]["result"]["range"]["start"], json!({"line": 1, "character": 2}));
+    }
+
+    #[test]
+    fn formatting() {
+        let responses = session(&[
+            open("+[\n-\n]\n"),
+            json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "textDocument/formatting",
+                "params": {
+                    "textDocument": {"uri": "file:///a.b"},
+                    "options": {"tabSize": 2, "insertSpaces": true},
+                },
+            }),
+        ]);
+
+        assert_eq!(responses[1]["result"][0]["newText"], "+[\n  -\n]\n");
+    }
+
+    #[test]
+    fn unknown_method() {
+        let responses = session(&[json!({"jsonrpc": "2.0", "id": 1, "method": "foo/bar"})]);
+        assert_eq!(responses[0]["error"]["code"], -32601);
+    }
+}
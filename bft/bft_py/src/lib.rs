@@ -0,0 +1,268 @@
+//! Python bindings for parsing and running brainfuck programs.
+//!
+//! Build and install the `bft` module into the current Python environment
+//! with [maturin](https://www.maturin.rs):
+//!
+//! ```text
+//! maturin develop --release -m bft_py/Cargo.toml
+//! ```
+//!
+//! Programs and input are `bytes`, and so is the output:
+//!
+//! ```text
+//! >>> import bft
+//! >>> bft.run(b",[+.,]", input=b"HAL")
+//! b'IBM'
+//! >>> bft.run(b"+\n<")
+//! Traceback (most recent call last):
+//!   ...
+//! bft.PointerUnderflowError: pointer moved left of the first cell at line 2, column 1
+//! ```
+//!
+//! Every error raised is a `bft.BftError`, carrying the `line` and
+//! `column` of the offending instruction and any `output` written before
+//! it failed. Programs run without holding the GIL, so batches can be
+//! spread across threads.
+
+use bft_interp::{BrainfuckInterpreter, InterpError};
+use bft_types::{BrainfuckProgram, ParseError};
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use pyo3::PyTypeInfo;
+
+create_exception!(bft, BftError, PyException, "A brainfuck program could not be parsed or run.");
+create_exception!(bft, SourceError, BftError, "The program source is not valid UTF-8.");
+create_exception!(bft, UnmatchedBracketError, BftError, "The program contains a loop bracket with no partner.");
+create_exception!(bft, PointerUnderflowError, BftError, "The pointer was moved left of the first cell.");
+create_exception!(bft, PointerOverflowError, BftError, "The pointer was moved right of the last cell of a fixed tape.");
+create_exception!(bft, StepLimitError, BftError, "The program was about to exceed its step limit.");
+create_exception!(bft, CellOverflowError, BftError, "A cell went out of range under a policy that forbids it.");
+create_exception!(bft, InfiniteLoopError, BftError, "The program was found to be stuck in a loop forever.");
+
+/// Builds the Python exception for an error at a line and column,
+/// recording the output written before it.
+fn raise<T: PyTypeInfo>(py: Python, message: String, line: usize, column: usize, output: &[u8]) -> PyErr {
+    let error = PyErr::new::<T, _>(message);
+    let value = error.value(py);
+    let attached = value.setattr("line", line)
+        .and_then(|_| value.setattr("column", column))
+        .and_then(|_| value.setattr("output", PyBytes::new(py, output)));
+    match attached {
+        Ok(()) => error,
+        Err(e) => e,
+    }
+}
+
+fn parse_error(py: Python, error: ParseError) -> PyErr {
+    match error {
+        ParseError::InvalidUtf8 { line, column } => raise::<SourceError>(py, error.to_string(), line, column, &[]),
+        other => raise::<BftError>(py, other.to_string(), 0, 0, &[]),
+    }
+}
+
+fn interp_error(py: Python, error: InterpError, output: &[u8]) -> PyErr {
+    let (line, column) = error.location();
+    let message = error.to_string();
+    match error {
+        InterpError::UnmatchedBracket { .. } => raise::<UnmatchedBracketError>(py, message, line, column, output),
+        InterpError::PointerUnderflow { .. } => raise::<PointerUnderflowError>(py, message, line, column, output),
+        InterpError::PointerOverflow { .. } => raise::<PointerOverflowError>(py, message, line, column, output),
+        InterpError::StepLimit { .. } => raise::<StepLimitError>(py, message, line, column, output),
+        InterpError::CellOverflow { .. } => raise::<CellOverflowError>(py, message, line, column, output),
+        InterpError::InfiniteLoop { .. } => raise::<InfiniteLoopError>(py, message, line, column, output),
+        InterpError::Io { .. } => raise::<BftError>(py, message, line, column, output),
+    }
+}
+
+/// A parsed brainfuck program.
+///
+/// `Program(source, name="program")` parses UTF-8 `source` bytes, raising
+/// `SourceError` if they are not valid.
+#[pyclass(name = "Program", module = "bft", frozen)]
+pub struct Program(BrainfuckProgram);
+
+#[pymethods]
+impl Program {
+    #[new]
+    #[pyo3(signature = (source, name = "program"))]
+    fn new(py: Python, source: &[u8], name: &str) -> PyResult<Self> {
+        BrainfuckProgram::from_bytes(&name, source)
+            .map(Program)
+            .map_err(|e| parse_error(py, e))
+    }
+
+    /// The name given to the program.
+    #[getter]
+    fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    /// The number of instructions in the program.
+    fn __len__(&self) -> usize {
+        self.0.get_tokens().len()
+    }
+
+    /// The program's instructions, without comments.
+    fn __str__(&self) -> String {
+        self.0.to_string()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("<bft.Program {:?}, {} instructions>", self.0.name, self.0.get_tokens().len())
+    }
+}
+
+/// A brainfuck interpreter with a tape of byte cells.
+///
+/// `Interpreter(tape_size=30000, expandable=False, step_limit=None)`
+/// creates a tape of `tape_size` cells, which grows if `expandable`. With
+/// a `step_limit`, runs that execute more instructions than that raise
+/// `StepLimitError`.
+#[pyclass(name = "Interpreter", module = "bft")]
+pub struct Interpreter(BrainfuckInterpreter<u8>);
+
+#[pymethods]
+impl Interpreter {
+    #[new]
+    #[pyo3(signature = (tape_size = 30000, expandable = false, step_limit = None))]
+    fn new(tape_size: usize, expandable: bool, step_limit: Option<u64>) -> Self {
+        let mut interp = BrainfuckInterpreter::new(tape_size, expandable);
+        interp.set_step_limit(step_limit);
+        Interpreter(interp)
+    }
+
+    /// Runs a program from its first instruction on the tape as the last
+    /// run left it, feeding it `input` and returning everything it wrote.
+    #[pyo3(signature = (program, input = None))]
+    fn run<'py>(&mut self, py: Python<'py>, program: PyRef<'py, Program>, input: Option<&[u8]>) -> PyResult<Bound<'py, PyBytes>> {
+        let input = input.unwrap_or_default();
+        let program = &program.0;
+        let interp = &mut self.0;
+        let (result, output) = py.detach(|| {
+            let mut output = vec![];
+            let result = interp.interpret(program, &mut &input[..], &mut output);
+            (result, output)
+        });
+
+        match result {
+            Ok(()) => Ok(PyBytes::new(py, &output)),
+            Err(e) => Err(interp_error(py, e, &output)),
+        }
+    }
+
+    /// Clears the tape and moves the pointer back to the first cell.
+    fn reset(&mut self) {
+        self.0.reset();
+    }
+
+    /// The contents of the tape.
+    #[getter]
+    fn tape<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, self.0.tape())
+    }
+
+    /// The index of the cell the pointer is at.
+    #[getter]
+    fn tape_index(&self) -> usize {
+        self.0.tape_index()
+    }
+
+    /// The number of instructions executed by the last run.
+    #[getter]
+    fn steps(&self) -> u64 {
+        self.0.steps()
+    }
+}
+
+/// Parses and runs UTF-8 `source` on a fresh interpreter, feeding it
+/// `input` and returning everything it wrote.
+#[pyfunction]
+#[pyo3(signature = (source, input = None, tape_size = 30000, expandable = false, step_limit = None))]
+fn run<'py>(py: Python<'py>, source: &[u8], input: Option<&[u8]>, tape_size: usize, expandable: bool, step_limit: Option<u64>) -> PyResult<Bound<'py, PyBytes>> {
+    let program = Bound::new(py, Program::new(py, source, "program")?)?;
+    Interpreter::new(tape_size, expandable, step_limit).run(py, program.borrow(), input)
+}
+
+/// Parse and run brainfuck programs.
+#[pymodule]
+#[pyo3(name = "bft")]
+fn bft_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    let py = m.py();
+    m.add_class::<Program>()?;
+    m.add_class::<Interpreter>()?;
+    m.add_function(wrap_pyfunction!(run, m)?)?;
+    m.add("BftError", py.get_type::<BftError>())?;
+    m.add("SourceError", py.get_type::<SourceError>())?;
+    m.add("UnmatchedBracketError", py.get_type::<UnmatchedBracketError>())?;
+    m.add("PointerUnderflowError", py.get_type::<PointerUnderflowError>())?;
+    m.add("PointerOverflowError", py.get_type::<PointerOverflowError>())?;
+    m.add("StepLimitError", py.get_type::<StepLimitError>())?;
+    m.add("CellOverflowError", py.get_type::<CellOverflowError>())?;
+    m.add("InfiniteLoopError", py.get_type::<InfiniteLoopError>())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use pyo3::ffi::c_str;
+    use pyo3::prelude::*;
+    use pyo3::types::{PyDict, PyModule};
+    use std::ffi::CStr;
+
+    /// Runs Python code with the `bft` module imported.
+    fn python(code: &CStr) {
+        Python::initialize();
+        Python::attach(|py| {
+            let module = PyModule::new(py, "bft").unwrap();
+            super::bft_module(&module).unwrap();
+            let locals = PyDict::new(py);
+            locals.set_item("bft", module).unwrap();
+            if let Err(e) = py.run(code, None, Some(&locals)) {
+                panic!("{}", e);
+            }
+        });
+    }
+
+    #[test]
+    fn runs_programs() {
+        python(c_str!(r#"
+assert bft.run(b",[+.,]", input=b"HAL") == b"IBM"
+
+program = bft.Program(b"++ add two\n[>+<-]", name="move")
+assert (program.name, len(program), str(program)) == ("move", 8, "++[>+<-]")
+
+interp = bft.Interpreter(tape_size=4)
+assert interp.run(program) == b""
+assert (interp.tape, interp.tape_index, interp.steps) == (b"\0\x02\0\0", 0, 13)
+interp.reset()
+assert interp.tape == bytes(4)
+"#));
+    }
+
+    #[test]
+    fn raises_with_location() {
+        python(c_str!(r#"
+try:
+    bft.Interpreter().run(bft.Program(b"+.\n <"))
+    raise AssertionError("expected an error")
+except bft.PointerUnderflowError as e:
+    assert isinstance(e, bft.BftError)
+    assert (e.line, e.column, e.output) == (2, 2, b"\x01")
+    assert str(e) == "pointer moved left of the first cell at line 2, column 2"
+
+try:
+    bft.Program(b"+\n+\xff")
+    raise AssertionError("expected an error")
+except bft.SourceError as e:
+    assert (e.line, e.column) == (2, 2)
+
+try:
+    bft.run(b"+[]", step_limit=100)
+    raise AssertionError("expected an error")
+except bft.StepLimitError as e:
+    assert (e.line, e.column) == (1, 3)
+"#));
+    }
+}
@@ -1,70 +1,405 @@
 //! Contains supporting types and datastructures
 //! for the brainfuck interpreter.
-
-use std::io::{Result, Error, ErrorKind, BufReader, BufRead};
-use std::path::Path;
+//!
+//! Without the default `std` feature the crate only needs `alloc`, and
+//! programs can be loaded with [BrainfuckProgram::from_string] and
+//! [BrainfuckProgram::from_bytes].
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::str;
+#[cfg(feature = "std")]
+use std::io::{self, BufReader, BufRead};
+#[cfg(feature = "std")]
+use std::path::{Path, PathBuf};
+#[cfg(feature = "std")]
 use std::fs;
-use std::fmt;
-use std::string::ToString;
+
+#[cfg(feature = "std")]
+pub mod preprocess;
+pub mod strict;
+
+pub use strict::ParseWarning;
 
 /// A brainfuck program, ie. a list of valid brainfuck instructions.
 pub struct BrainfuckProgram {
     pub name: String,
-    tokens: Vec<Token>
+    tokens: Vec<Token>,
+    partners: Vec<Option<usize>>,
+    sources: Option<SourceMap>,
 }
 
 impl BrainfuckProgram {
 
     /// Creates a new [BrainfuckProgram] with the given file name and content.
     pub fn new(file_name: &dyn AsRef<str>, tokens: Vec<Token>) -> BrainfuckProgram {
-        BrainfuckProgram {
+        let mut program = BrainfuckProgram {
             name: file_name.as_ref().to_string(),
+            partners: vec![None; tokens.len()],
             tokens,
+            sources: None,
+        };
+
+        for (open, close) in program.bracket_pairs().0 {
+            program.partners[open] = Some(close);
+            program.partners[close] = Some(open);
         }
+
+        program
     }
 
     /// Creates a new [BrainfuckProgram] with the given file name and content.
+    ///
+    /// ```
+    /// # use bft_types::BrainfuckProgram;
+    /// let program = BrainfuckProgram::from_string(&"virtual", &"+[-]\n>.");
+    /// let last = &program.get_tokens()[5];
+    /// assert_eq!((last.line(), last.column()), (2, 2));
+    /// ```
     pub fn from_string(file_name: &dyn AsRef<str>, content: &dyn AsRef<str>) -> BrainfuckProgram {
-        BrainfuckProgram {
-            name: file_name.as_ref().to_string(),
-            tokens: content.as_ref().chars()
-                .filter_map(|c| Token::new(1, 1, c)) // todo this
-                .collect()
-        }
+        Self::new(
+            file_name,
+            content.as_ref().lines()
+                .enumerate()
+                .flat_map(|(row, line)| Self::parse_line(row, line))
+                .collect(),
+        )
     }
 
     /// Parses a new [BrainfuckProgram] at a given path relative to the current directory.
-    pub fn from_file(path: &dyn AsRef<Path>) -> Result<BrainfuckProgram> {
-
+    #[cfg(feature = "std")]
+    pub fn from_file(path: &dyn AsRef<Path>) -> Result<BrainfuckProgram, ParseError> {
         let name = path.as_ref().file_name()
-                .ok_or_else(|| Error::new(
-                    ErrorKind::Other, 
-                    format!("Couldn't discern file name from \"{}\"", path.as_ref().display())
-                ))?
+                .ok_or_else(|| ParseError::NoFileName(path.as_ref().to_path_buf()))?
                 .to_string_lossy().to_string();
 
-        let text = BufReader::new(fs::File::open(path)?);
-        let tokens: Result<_> = text.lines()
+        Self::from_reader(&name, &mut BufReader::new(fs::File::open(path)?))
+    }
+
+    /// Parses a new [BrainfuckProgram] from everything left in a reader,
+    /// such as a network stream.
+    #[cfg(feature = "std")]
+    pub fn from_reader(name: &dyn AsRef<str>, reader: &mut dyn BufRead) -> Result<BrainfuckProgram, ParseError> {
+        let mut tokens = vec![];
+        let mut line = vec![];
+        for row in 0.. {
+            line.clear();
+            if reader.read_until(b'\n', &mut line)? == 0 {
+                break;
+            }
+            if line.ends_with(b"\n") {
+                line.pop();
+            }
+            tokens.extend(Self::parse_bytes_line(row, &line)?);
+        }
+
+        Ok(Self::new(name, tokens))
+    }
+
+    /// Parses a new [BrainfuckProgram] from UTF-8 encoded source, such as
+    /// a program embedded with [include_bytes].
+    ///
+    /// ```
+    /// # use bft_types::{BrainfuckProgram, ParseError};
+    /// let program = BrainfuckProgram::from_bytes(&"virtual", b"+[-]\r\n>.").unwrap();
+    /// assert_eq!(program.to_string(), "+[-]>.");
+    ///
+    /// match BrainfuckProgram::from_bytes(&"virtual", b"+\n-\xff") {
+    ///     Err(ParseError::InvalidUtf8 { line: 2, column: 2 }) => (),
+    ///     other => panic!("unexpected {:?}", other.map(|p| p.to_string())),
+    /// }
+    /// ```
+    pub fn from_bytes(name: &dyn AsRef<str>, bytes: &[u8]) -> Result<BrainfuckProgram, ParseError> {
+        let mut tokens = vec![];
+        for (row, line) in bytes.split(|b| *b == b'\n').enumerate() {
+            tokens.extend(Self::parse_bytes_line(row, line)?);
+        }
+
+        Ok(Self::new(name, tokens))
+    }
+
+    /// Parses a new [BrainfuckProgram] like [BrainfuckProgram::from_string],
+    /// in strict mode, also giving a [ParseWarning] for anything that looks
+    /// like a mistake.
+    ///
+    /// ```
+    /// # use bft_types::{BrainfuckProgram, ParseWarning, RawInstruction};
+    /// let (program, warnings) = BrainfuckProgram::from_string_strict(&"virtual", &"+\u{2013}");
+    /// assert_eq!(program.to_string(), "+");
+    /// assert_eq!(warnings, vec![ParseWarning::Lookalike {
+    ///     line: 1, column: 2, found: '\u{2013}', instruction: RawInstruction::Decrement,
+    /// }]);
+    /// ```
+    pub fn from_string_strict(file_name: &dyn AsRef<str>, content: &dyn AsRef<str>) -> (BrainfuckProgram, Vec<ParseWarning>) {
+        let warnings = content.as_ref().lines()
             .enumerate()
-            .map(|(row, line)| Ok(
-                line?.chars().filter_map(|c| Token::new(1,1,c))
-            ))
+            .flat_map(|(row, line)| strict::check_line(row, line))
             .collect();
+        (Self::from_string(file_name, content), warnings)
+    }
+
+    /// Parses a new [BrainfuckProgram] like [BrainfuckProgram::from_bytes],
+    /// in strict mode, also giving a [ParseWarning] for anything that looks
+    /// like a mistake.
+    pub fn from_bytes_strict(name: &dyn AsRef<str>, bytes: &[u8]) -> Result<(BrainfuckProgram, Vec<ParseWarning>), ParseError> {
+        let mut tokens = vec![];
+        let mut warnings = vec![];
+        for (row, line) in bytes.split(|b| *b == b'\n').enumerate() {
+            let text = Self::decode_line(row, line)?;
+            tokens.extend(Self::parse_line(row, text));
+            warnings.extend(strict::check_line(row, text));
+        }
 
-        Ok(Self::new(
-            &name,
-            tokens?,
-        ))
+        Ok((Self::new(name, tokens), warnings))
     }
 
+    /// Parses a single (zero-indexed) line of UTF-8 source, without its `\n`.
+    fn parse_bytes_line(row: usize, line: &[u8]) -> Result<Vec<Token>, ParseError> {
+        Ok(Self::parse_line(row, Self::decode_line(row, line)?))
+    }
+
+    /// Decodes a single (zero-indexed) line of UTF-8 source, without its `\n`.
+    pub(crate) fn decode_line(row: usize, line: &[u8]) -> Result<&str, ParseError> {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        str::from_utf8(line).map_err(|e| ParseError::InvalidUtf8 {
+            line: row + 1,
+            column: String::from_utf8_lossy(&line[..e.valid_up_to()]).chars().count() + 1,
+        })
+    }
+
+    /// Parses the instructions on a single (zero-indexed) line of source.
+    fn parse_line(row: usize, line: &str) -> Vec<Token> {
+        line.chars()
+            .enumerate()
+            .filter_map(|(col, c)| Token::new(row + 1, col + 1, c))
+            .collect()
+    }
+
+    /// Gets the [Token]s that make up the program, in source order.
     pub fn get_tokens(&self) -> &[Token] {
         self.tokens.as_slice()
     }
+
+    /// Pairs up the loop brackets in the program.
+    ///
+    /// Returns the `(open, close)` token indices of every matched loop,
+    /// ordered by their opening bracket, alongside any brackets that
+    /// have no partner.
+    ///
+    /// ```
+    /// # use bft_types::{BrainfuckProgram, UnmatchedBracket};
+    /// let program = BrainfuckProgram::from_string(&"virtual", &"[[]]]");
+    /// let (pairs, unmatched) = program.bracket_pairs();
+    /// assert_eq!(pairs, vec![(0, 3), (1, 2)]);
+    /// assert_eq!(unmatched, vec![UnmatchedBracket::Unopened(4)]);
+    /// ```
+    pub fn bracket_pairs(&self) -> (Vec<(usize, usize)>, Vec<UnmatchedBracket>) {
+        let mut open = vec![];
+        let mut pairs = vec![];
+        let mut unmatched = vec![];
+
+        for (index, token) in self.tokens.iter().enumerate() {
+            match token.instruction {
+                RawInstruction::StartLoop => open.push(index),
+                RawInstruction::EndLoop => match open.pop() {
+                    Some(start) => pairs.push((start, index)),
+                    None => unmatched.push(UnmatchedBracket::Unopened(index)),
+                },
+                _ => (),
+            }
+        }
+
+        unmatched.extend(open.into_iter().map(UnmatchedBracket::Unclosed));
+        pairs.sort();
+        (pairs, unmatched)
+    }
+
+    /// Gets the index of the bracket matching the loop bracket at `index`,
+    /// or [None] if the token there is not a matched bracket.
+    ///
+    /// ```
+    /// # use bft_types::BrainfuckProgram;
+    /// let program = BrainfuckProgram::from_string(&"virtual", &"+[-]");
+    /// assert_eq!(program.matching_bracket(1), Some(3));
+    /// assert_eq!(program.matching_bracket(0), None);
+    /// ```
+    pub fn matching_bracket(&self, index: usize) -> Option<usize> {
+        self.partners.get(index).copied().flatten()
+    }
+
+    /// Attaches a map of the files the program's tokens came from.
+    #[cfg(feature = "std")]
+    pub(crate) fn with_source_map(mut self, sources: SourceMap) -> BrainfuckProgram {
+        self.sources = Some(sources);
+        self
+    }
+
+    /// Gets the map of the files the program's tokens came from, if it was
+    /// put together from several.
+    pub fn source_map(&self) -> Option<&SourceMap> {
+        self.sources.as_ref()
+    }
+
+    /// Gets where the token at `index` was written. Unless the program was
+    /// put together from several files, the file is the program's name.
+    ///
+    /// ```
+    /// # use bft_types::BrainfuckProgram;
+    /// let program = BrainfuckProgram::from_string(&"virtual", &"+\n [-]");
+    /// assert_eq!(program.location(2).to_string(), "virtual:2:3");
+    /// ```
+    pub fn location(&self, index: usize) -> Location<'_> {
+        let token = &self.tokens[index];
+        Location {
+            file: self.sources.as_ref().map_or(&self.name, |sources| sources.file(index)),
+            line: token.line(),
+            column: token.column(),
+        }
+    }
+}
+
+/// Maps the tokens of a program put together from several files, such as
+/// by [preprocess::preprocess], back to the files they were written in.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SourceMap {
+    files: Vec<String>,
+    token_files: Vec<usize>,
+}
+
+impl SourceMap {
+    /// Gets every file read, starting with the one the program was loaded from.
+    pub fn files(&self) -> &[String] {
+        &self.files
+    }
+
+    /// Gets the file the token at `index` came from.
+    pub fn file(&self, index: usize) -> &str {
+        &self.files[self.token_files[index]]
+    }
+}
+
+/// Where a [Token] was written in its original source.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Location<'a> {
+    pub file: &'a str,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Location<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+impl fmt::Display for BrainfuckProgram {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.tokens.iter().try_for_each(|t| t.fmt(f))
+    }
+}
+
+/// An error encountered while loading a [BrainfuckProgram].
+#[derive(Debug)]
+pub enum ParseError {
+    /// Reading the source failed.
+    #[cfg(feature = "std")]
+    Io(io::Error),
+
+    /// The source is not valid UTF-8, starting at the given character.
+    InvalidUtf8 { line: usize, column: usize },
+
+    /// A program was loaded from a path that does not name a file.
+    #[cfg(feature = "std")]
+    NoFileName(PathBuf),
+
+    /// Expanding the macros and includes in a program failed.
+    #[cfg(feature = "std")]
+    Preprocess(preprocess::PreprocessError),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            #[cfg(feature = "std")]
+            ParseError::Io(source) => write!(f, "couldn't read program: {}", source),
+            ParseError::InvalidUtf8 { line, column } =>
+                write!(f, "invalid UTF-8 at line {}, column {}", line, column),
+            #[cfg(feature = "std")]
+            ParseError::NoFileName(path) =>
+                write!(f, "couldn't discern file name from \"{}\"", path.display()),
+            #[cfg(feature = "std")]
+            ParseError::Preprocess(source) => source.fmt(f),
+        }
+    }
 }
 
-impl std::string::ToString for BrainfuckProgram {
-    fn to_string(&self) -> String {
-        self.tokens.iter().map(|t| format!("{}", t)).collect()
+impl ParseError {
+    /// Gets the line and column the error is about, if it is about a
+    /// particular place in the source.
+    pub fn location(&self) -> Option<(usize, usize)> {
+        match self {
+            #[cfg(feature = "std")]
+            ParseError::Io(_) => None,
+            ParseError::InvalidUtf8 { line, column } => Some((*line, *column)),
+            #[cfg(feature = "std")]
+            ParseError::NoFileName(_) => None,
+            #[cfg(feature = "std")]
+            ParseError::Preprocess(source) => source.location(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ParseError::Io(source) => Some(source),
+            ParseError::Preprocess(source) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for ParseError {
+    fn from(error: io::Error) -> Self {
+        ParseError::Io(error)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<ParseError> for io::Error {
+    fn from(error: ParseError) -> Self {
+        match error {
+            ParseError::Io(source) => source,
+            other => io::Error::new(io::ErrorKind::InvalidData, other),
+        }
+    }
+}
+
+/// A loop bracket without a partner, identified by its index
+/// into [BrainfuckProgram::get_tokens].
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum UnmatchedBracket {
+    /// A [RawInstruction::StartLoop] that is never closed.
+    Unclosed(usize),
+
+    /// A [RawInstruction::EndLoop] that closes nothing.
+    Unopened(usize),
+}
+
+impl UnmatchedBracket {
+    /// Gets the index of the offending [Token].
+    pub fn index(self) -> usize {
+        match self {
+            UnmatchedBracket::Unclosed(index) | UnmatchedBracket::Unopened(index) => index,
+        }
     }
 }
 
@@ -110,16 +445,19 @@ pub enum RawInstruction {
 }
 
 impl RawInstruction {
-    fn from_char(c1: char) -> Option<Self> {
+    /// Gets the instruction a character represents, or [None] if
+    /// the character is a comment.
+    pub fn from_char(c1: char) -> Option<Self> {
         INSTRUCTIONS.iter().find(|(c2, _)| c1.eq(c2)).map(|(_, ri)| *ri)
     }
 
-    fn to_char(self) -> char {
+    /// Gets the character that represents the instruction.
+    pub fn to_char(self) -> char {
         INSTRUCTIONS.iter().find(|(_, ri)| self.eq(ri)).map(|(c2, _)| *c2).unwrap()
     }
 }
 
-impl std::fmt::Display for RawInstruction {
+impl fmt::Display for RawInstruction {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}", self.to_char())
     }
@@ -127,7 +465,7 @@ impl std::fmt::Display for RawInstruction {
 
 /// Represents a token in a brainfuck program, which consists of
 /// a line number, column number, and [RawInstruction].
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub struct Token {
     line_number: u32,
     col_number: u32,
@@ -144,9 +482,19 @@ impl Token {
             instruction: RawInstruction::from_char(c)?,
         })
     }
+
+    /// The line the token appears on, counting from one.
+    pub fn line(&self) -> usize {
+        self.line_number as usize
+    }
+
+    /// The column the token appears in, counting from one.
+    pub fn column(&self) -> usize {
+        self.col_number as usize
+    }
 }
 
-impl std::fmt::Display for Token {
+impl fmt::Display for Token {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         self.instruction.fmt(f)
     }
@@ -154,7 +502,7 @@ impl std::fmt::Display for Token {
 
 #[cfg(test)]
 mod tests {
-    use crate::{Token, RawInstruction, BrainfuckProgram};
+    use crate::{Token, RawInstruction, BrainfuckProgram, ParseError, UnmatchedBracket};
 
     #[test]
     fn create_invalid_token() {
@@ -181,6 +529,48 @@ mod tests {
         )
     }
 
+    #[test]
+    fn track_line_and_column() {
+        let program = BrainfuckProgram::from_string(&"./virtual", &"a+\n\n  [-]");
+        let positions: Vec<_> = program.get_tokens().iter()
+            .map(|t| (t.line(), t.column()))
+            .collect();
+        assert_eq!(positions, vec![(1, 2), (3, 3), (3, 4), (3, 5)]);
+    }
+
+    #[test]
+    fn unmatched_brackets() {
+        let program = BrainfuckProgram::from_string(&"./virtual", &"][[]");
+        assert_eq!(
+            program.bracket_pairs(),
+            (vec![(2, 3)], vec![UnmatchedBracket::Unopened(0), UnmatchedBracket::Unclosed(1)])
+        );
+    }
+
+    #[test]
+    fn reader_matches_string() {
+        let source = "a+\r\n\n  [-]é.";
+        let from_reader = BrainfuckProgram::from_reader(&"./virtual", &mut source.as_bytes()).unwrap();
+        let from_string = BrainfuckProgram::from_string(&"./virtual", &source);
+        assert_eq!(from_reader.get_tokens(), from_string.get_tokens());
+    }
+
+    #[test]
+    fn invalid_utf8_location() {
+        match BrainfuckProgram::from_bytes(&"./virtual", b"+\n\xc3\xa9-\xc3") {
+            Err(error @ ParseError::InvalidUtf8 { line: 2, column: 3 }) => assert_eq!(error.location(), Some((2, 3))),
+            other => panic!("unexpected {:?}", other.map(|p| p.to_string())),
+        }
+    }
+
+    #[test]
+    fn missing_file_name() {
+        match BrainfuckProgram::from_file(&"..") {
+            Err(ParseError::NoFileName(path)) => assert_eq!(path.to_str(), Some("..")),
+            other => panic!("unexpected {:?}", other.map(|p| p.to_string())),
+        }
+    }
+
     macro_rules! parse_tests {
         ($($name:ident: $value:expr,)*) => {
         $(
@@ -188,7 +578,7 @@ mod tests {
             fn $name() {
                 let (file, content, expected) = $value;
                 assert_eq!(
-                    BrainfuckProgram::new(file, content).to_string(),
+                    BrainfuckProgram::from_string(file, content).to_string(),
                     expected
                 )
             }
@@ -201,4 +591,23 @@ mod tests {
         parse_all: (&"./virtual", &"<>.,+-[]", "<>.,+-[]"),
         parse_none: (&"./virtual", &"", ""),
     }
+
+    #[test]
+    fn strict_warnings() {
+        let source = "Hello, world.\n+++\u{ff0b}[\u{2013}]\n[-]>.< 3.5 e-mail>-\r\n";
+        let (program, warnings) = BrainfuckProgram::from_bytes_strict(&"./virtual", source.as_bytes()).unwrap();
+        assert_eq!(program.get_tokens(), BrainfuckProgram::from_string(&"./virtual", &source).get_tokens());
+
+        let found: Vec<_> = warnings.iter().map(|w| (w.line(), w.column(), w.to_string())).collect();
+        assert_eq!(found, vec![
+            (1, 6, "`,` at line 1, column 6 is run as an instruction, but looks like part of a comment".to_string()),
+            (1, 13, "`.` at line 1, column 13 is run as an instruction, but looks like part of a comment".to_string()),
+            (2, 4, "`\u{ff0b}` (U+FF0B) at line 2, column 4 looks like `+`, but is a comment".to_string()),
+            (2, 6, "`\u{2013}` (U+2013) at line 2, column 6 looks like `-`, but is a comment".to_string()),
+            (3, 13, "`-` at line 3, column 13 is run as an instruction, but looks like part of a comment".to_string()),
+        ]);
+
+        let (_, warnings) = BrainfuckProgram::from_string_strict(&"./virtual", &source);
+        assert_eq!(warnings.len(), 5);
+    }
 }
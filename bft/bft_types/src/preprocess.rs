@@ -0,0 +1,474 @@
+//! Expands macros, repetitions and includes in brainfuck source before it
+//! is parsed, keeping track of where every instruction came from.
+//!
+//! ```text
+//! #include "lib/print.bfm"
+//! #define CLEAR [-]
+//! #macro DOUBLE
+//!   [>++<-]>
+//! #end
+//!
+//! +*10 @DOUBLE @CLEAR*2
+//! ```
+//!
+//! - `#include "path"` pastes in another file, found relative to the file
+//!   including it.
+//! - `#define NAME code` defines a macro on a single line, and `#macro NAME`
+//!   defines one over the lines up to `#end`.
+//! - `@NAME` expands a macro defined further up.
+//! - `*N` straight after an instruction or a macro repeats it `N` times.
+//!
+//! Any other line starting with `#` is an error, so that a misspelt
+//! directive isn't quietly treated as a comment. Everything else is plain
+//! brainfuck. Each [Token] keeps the line and column it was written at, even
+//! inside a macro, and the program's [SourceMap] gives the file.
+
+use crate::{strict, BrainfuckProgram, ParseError, ParseWarning, SourceMap, Token};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+
+/// The most instructions and macro uses a program may expand to, which
+/// also limits each repetition count.
+pub const MAX_EXPANSION: usize = 1 << 24;
+
+/// How deeply includes may nest, which stops files that aren't on disk
+/// from including each other under ever longer paths.
+pub const MAX_INCLUDE_DEPTH: usize = 64;
+
+/// The output of [preprocess]: the expanded program, with a [SourceMap]
+/// of where each of its tokens came from, and any warnings about the
+/// source in strict mode.
+pub struct Preprocessed {
+    pub program: BrainfuckProgram,
+    /// Every [ParseWarning] in the files read, along with the file.
+    pub warnings: Vec<(String, ParseWarning)>,
+}
+
+/// An error encountered while preprocessing a program.
+#[derive(Debug)]
+pub enum PreprocessError {
+    /// A file couldn't be read.
+    Io { file: String, source: io::Error },
+
+    /// A file is not valid UTF-8, starting at the given character.
+    InvalidUtf8 { file: String, line: usize, column: usize },
+
+    /// A line starts with `#`, but not with a known directive.
+    UnknownDirective { file: String, line: usize, column: usize, directive: String },
+
+    /// A directive is missing its macro name or path.
+    InvalidDirective { file: String, line: usize, column: usize },
+
+    /// A `#macro` is never closed by an `#end`.
+    UnclosedMacro { file: String, line: usize, column: usize, name: String },
+
+    /// A macro is used before it is defined.
+    UnknownMacro { file: String, line: usize, column: usize, name: String },
+
+    /// A macro is used inside itself.
+    RecursiveMacro { file: String, line: usize, column: usize, name: String },
+
+    /// A file is included inside itself, or includes nest more than
+    /// [MAX_INCLUDE_DEPTH] deep.
+    RecursiveInclude { file: String, line: usize, column: usize },
+
+    /// A repetition count is too large, or the program expands to more
+    /// than [MAX_EXPANSION] instructions and macro uses.
+    InvalidCount { file: String, line: usize, column: usize },
+}
+
+impl PreprocessError {
+    /// Gets the line and column the error is about, in the file it names,
+    /// unless the file couldn't be read at all.
+    pub fn location(&self) -> Option<(usize, usize)> {
+        match self {
+            PreprocessError::Io { .. } => None,
+            PreprocessError::InvalidUtf8 { line, column, .. }
+            | PreprocessError::UnknownDirective { line, column, .. }
+            | PreprocessError::InvalidDirective { line, column, .. }
+            | PreprocessError::UnclosedMacro { line, column, .. }
+            | PreprocessError::UnknownMacro { line, column, .. }
+            | PreprocessError::RecursiveMacro { line, column, .. }
+            | PreprocessError::RecursiveInclude { line, column, .. }
+            | PreprocessError::InvalidCount { line, column, .. } => Some((*line, *column)),
+        }
+    }
+}
+
+impl fmt::Display for PreprocessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PreprocessError::Io { file, source } => write!(f, "couldn't read {}: {}", file, source),
+            PreprocessError::InvalidUtf8 { file, line, column } =>
+                write!(f, "invalid UTF-8 in {} at line {}, column {}", file, line, column),
+            PreprocessError::UnknownDirective { file, line, column, directive } =>
+                write!(f, "unknown directive `#{}` in {} at line {}, column {}", directive, file, line, column),
+            PreprocessError::InvalidDirective { file, line, column } =>
+                write!(f, "directive without a name in {} at line {}, column {}", file, line, column),
+            PreprocessError::UnclosedMacro { file, line, column, name } =>
+                write!(f, "macro `{}` is never closed with `#end` in {} at line {}, column {}", name, file, line, column),
+            PreprocessError::UnknownMacro { file, line, column, name } =>
+                write!(f, "unknown macro `@{}` in {} at line {}, column {}", name, file, line, column),
+            PreprocessError::RecursiveMacro { file, line, column, name } =>
+                write!(f, "macro `@{}` used inside itself in {} at line {}, column {}", name, file, line, column),
+            PreprocessError::RecursiveInclude { file, line, column } =>
+                write!(f, "file included inside itself in {} at line {}, column {}", file, line, column),
+            PreprocessError::InvalidCount { file, line, column } =>
+                write!(f, "repetition count too large in {} at line {}, column {}", file, line, column),
+        }
+    }
+}
+
+impl std::error::Error for PreprocessError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PreprocessError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Preprocesses the program at a given path relative to the current directory.
+pub fn preprocess(path: &dyn AsRef<Path>) -> Result<Preprocessed, PreprocessError> {
+    preprocess_with(path, &mut |path| fs::read(path))
+}
+
+/// Preprocesses a program, reading it and everything it includes with `read`.
+///
+/// ```
+/// # use bft_types::preprocess::preprocess_with;
+/// # use std::path::Path;
+/// let mut read = |path: &Path| Ok(match path.to_str() {
+///     Some("main.bfm") => b"#include \"lib.bfm\"\n+*3 @CLEAR".to_vec(),
+///     _ => b"#define CLEAR [-]".to_vec(),
+/// });
+/// let preprocessed = preprocess_with(&"main.bfm", &mut read).unwrap();
+/// assert_eq!(preprocessed.program.to_string(), "+++[-]");
+/// assert_eq!(preprocessed.program.location(3).to_string(), "lib.bfm:1:15");
+/// ```
+pub fn preprocess_with(path: &dyn AsRef<Path>, read: &mut dyn FnMut(&Path) -> io::Result<Vec<u8>>) -> Result<Preprocessed, PreprocessError> {
+    let path = path.as_ref();
+    let mut preprocessor = Preprocessor {
+        read,
+        macros: HashMap::new(),
+        including: vec![],
+        expanding: vec![],
+        expanded: 0,
+        tokens: vec![],
+        map: SourceMap::default(),
+        warnings: vec![],
+    };
+    preprocessor.include(path)?;
+
+    let name = path.file_name().unwrap_or(path.as_os_str()).to_string_lossy();
+    let files = &preprocessor.map.files;
+    let warnings = preprocessor.warnings.iter().map(|(file, warning)| (files[*file].clone(), *warning)).collect();
+    Ok(Preprocessed {
+        program: BrainfuckProgram::new(&name, preprocessor.tokens).with_source_map(preprocessor.map),
+        warnings,
+    })
+}
+
+/// A macro's code, as lines along with where they start.
+struct Macro {
+    file: usize,
+    lines: Vec<(usize, usize, String)>,
+}
+
+struct Preprocessor<'a> {
+    read: &'a mut dyn FnMut(&Path) -> io::Result<Vec<u8>>,
+    macros: HashMap<String, Rc<Macro>>,
+    /// The files, as given by [identify], and macros currently being
+    /// expanded, innermost last.
+    including: Vec<PathBuf>,
+    expanding: Vec<String>,
+    /// How many instructions and macro uses have been expanded so far.
+    expanded: usize,
+    tokens: Vec<Token>,
+    map: SourceMap,
+    warnings: Vec<(usize, ParseWarning)>,
+}
+
+impl Preprocessor<'_> {
+    /// Expands a file in place.
+    fn include(&mut self, path: &Path) -> Result<(), PreprocessError> {
+        let name = path.display().to_string();
+        let bytes = (self.read)(path).map_err(|source| PreprocessError::Io { file: name.clone(), source })?;
+        let file = self.map.files.len();
+        self.map.files.push(name);
+        self.including.push(identify(path));
+
+        let mut lines = bytes.split(|b| *b == b'\n').enumerate();
+        while let Some((row, line)) = lines.next() {
+            let text = self.decode(file, row, line)?;
+            let trimmed = text.trim_start();
+            let (line, column) = (row + 1, text.chars().count() - trimmed.chars().count() + 1);
+
+            let directive = match trimmed.strip_prefix('#') {
+                Some(directive) => directive,
+                None => {
+                    self.check(file, row, text);
+                    self.expand_line(file, line, 1, text)?;
+                    continue;
+                },
+            };
+
+            let keyword = directive.split(char::is_whitespace).next().unwrap_or("");
+            let argument = directive[keyword.len()..].trim_start();
+            match keyword {
+                "include" => {
+                    let target = argument.trim_end().trim_matches('"');
+                    if target.is_empty() {
+                        return Err(self.error(file, line, column, |file, line, column| PreprocessError::InvalidDirective { file, line, column }));
+                    }
+                    let target = path.parent().unwrap_or_else(|| Path::new("")).join(target);
+                    if self.including.contains(&identify(&target)) || self.including.len() >= MAX_INCLUDE_DEPTH {
+                        return Err(self.error(file, line, column, |file, line, column| PreprocessError::RecursiveInclude { file, line, column }));
+                    }
+                    self.include(&target)?;
+                },
+                "define" => {
+                    self.check(file, row, text);
+                    let name = argument.split_whitespace().next().unwrap_or("");
+                    let code = argument[name.len()..].trim_start();
+                    let code_column = text.chars().count() - code.chars().count() + 1;
+                    self.define(file, line, column, name, vec![(line, code_column, code.to_string())])?;
+                },
+                "macro" => {
+                    self.check(file, row, text);
+                    let mut body = vec![];
+                    loop {
+                        let (row, code) = match lines.next() {
+                            Some((row, code)) => (row, self.decode(file, row, code)?),
+                            None => return Err(self.error(file, line, column, |file, line, column|
+                                PreprocessError::UnclosedMacro { file, line, column, name: argument.trim_end().to_string() })),
+                        };
+                        if code.trim() == "#end" {
+                            break;
+                        }
+                        self.check(file, row, code);
+                        body.push((row + 1, 1, code.to_string()));
+                    }
+                    self.define(file, line, column, argument.trim_end(), body)?;
+                },
+                _ => return Err(self.error(file, line, column, |file, line, column|
+                    PreprocessError::UnknownDirective { file, line, column, directive: keyword.to_string() })),
+            }
+        }
+
+        self.including.pop();
+        Ok(())
+    }
+
+    /// Expands the instructions and macros on a single line, which starts
+    /// at `first_column`.
+    fn expand_line(&mut self, file: usize, line: usize, first_column: usize, text: &str) -> Result<(), PreprocessError> {
+        let chars: Vec<char> = text.chars().collect();
+        let mut col = 0;
+        while col < chars.len() {
+            let column = first_column + col;
+            let c = chars[col];
+            col += 1;
+
+            if let Some(token) = Token::new(line, column, c) {
+                let count = self.count(&chars, &mut col, file, line, first_column)?;
+                self.charge(count, file, line, column)?;
+                for _ in 0..count {
+                    self.tokens.push(token);
+                    self.map.token_files.push(file);
+                }
+                continue;
+            }
+
+            let length = chars[col..].iter().take_while(|&&c| is_name(c)).count();
+            if c != '@' || length == 0 {
+                continue;
+            }
+            let name: String = chars[col..col + length].iter().collect();
+            col += length;
+            let count = self.count(&chars, &mut col, file, line, first_column)?;
+
+            if self.expanding.contains(&name) {
+                return Err(self.error(file, line, column, |file, line, column| PreprocessError::RecursiveMacro { file, line, column, name }));
+            }
+            let expansion = match self.macros.get(&name) {
+                Some(expansion) => expansion.clone(),
+                None => return Err(self.error(file, line, column, |file, line, column| PreprocessError::UnknownMacro { file, line, column, name })),
+            };
+
+            self.expanding.push(name);
+            for _ in 0..count {
+                self.charge(1, file, line, column)?;
+                for (line, first_column, code) in &expansion.lines {
+                    self.expand_line(expansion.file, *line, *first_column, code)?;
+                }
+            }
+            self.expanding.pop();
+        }
+        Ok(())
+    }
+
+    /// Reads the `*N` repetition count at `col`, if there is one, moving past it.
+    fn count(&self, chars: &[char], col: &mut usize, file: usize, line: usize, first_column: usize) -> Result<usize, PreprocessError> {
+        let digits = chars[*col..].iter().skip(1).take_while(|c| c.is_ascii_digit()).count();
+        if chars.get(*col) != Some(&'*') || digits == 0 {
+            return Ok(1);
+        }
+
+        let column = first_column + *col;
+        let count: String = chars[*col + 1..*col + 1 + digits].iter().collect();
+        *col += 1 + digits;
+        match count.parse() {
+            Ok(count) if count <= MAX_EXPANSION => Ok(count),
+            _ => Err(self.error(file, line, column, |file, line, column| PreprocessError::InvalidCount { file, line, column })),
+        }
+    }
+
+    /// Counts `amount` more instructions or macro uses towards [MAX_EXPANSION].
+    fn charge(&mut self, amount: usize, file: usize, line: usize, column: usize) -> Result<(), PreprocessError> {
+        self.expanded += amount;
+        if self.expanded > MAX_EXPANSION {
+            return Err(self.error(file, line, column, |file, line, column| PreprocessError::InvalidCount { file, line, column }));
+        }
+        Ok(())
+    }
+
+    fn define(&mut self, file: usize, line: usize, column: usize, name: &str, lines: Vec<(usize, usize, String)>) -> Result<(), PreprocessError> {
+        if name.is_empty() || !name.chars().all(is_name) {
+            return Err(self.error(file, line, column, |file, line, column| PreprocessError::InvalidDirective { file, line, column }));
+        }
+        self.macros.insert(name.to_string(), Rc::new(Macro { file, lines }));
+        Ok(())
+    }
+
+    fn decode<'b>(&self, file: usize, row: usize, line: &'b [u8]) -> Result<&'b str, PreprocessError> {
+        BrainfuckProgram::decode_line(row, line).map_err(|error| {
+            let (line, column) = match error {
+                ParseError::InvalidUtf8 { line, column } => (line, column),
+                _ => (row + 1, 1),
+            };
+            self.error(file, line, column, |file, line, column| PreprocessError::InvalidUtf8 { file, line, column })
+        })
+    }
+
+    /// Records any warnings about a line of a file.
+    fn check(&mut self, file: usize, row: usize, text: &str) {
+        self.warnings.extend(strict::check_line(row, text).into_iter().map(|warning| (file, warning)));
+    }
+
+    /// Builds an error about a position in one of the files.
+    fn error<F>(&self, file: usize, line: usize, column: usize, error: F) -> PreprocessError
+    where F: FnOnce(String, usize, usize) -> PreprocessError {
+        error(self.map.files[file].clone(), line, column)
+    }
+}
+
+/// Gets a path that is the same for every way of naming a file: where it
+/// really is, following symlinks, or if it isn't on disk, its [normalise]d
+/// path.
+fn identify(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| normalise(path))
+}
+
+/// Drops the `.` components of a path, along with each `..` and the
+/// directory before it, so that the same file is always the same path.
+fn normalise(path: &Path) -> PathBuf {
+    let mut normal = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir if matches!(normal.components().next_back(), Some(Component::Normal(_))) => {
+                normal.pop();
+            },
+            component => normal.push(component),
+        }
+    }
+    normal
+}
+
+/// Whether a character can be part of a macro name.
+fn is_name(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{preprocess_with, PreprocessError, Preprocessed};
+    use std::io;
+    use std::path::Path;
+
+    /// Preprocesses `main.bfm` from a set of in-memory files.
+    fn run(files: &[(&str, &str)]) -> Result<Preprocessed, PreprocessError> {
+        preprocess_with(&"main.bfm", &mut |path: &Path| {
+            files.iter()
+                .find(|(name, _)| Path::new(name) == path)
+                .map(|(_, contents)| contents.as_bytes().to_vec())
+                .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+        })
+    }
+
+    #[test]
+    fn expands_macros_and_includes() {
+        let preprocessed = run(&[
+            ("main.bfm", "#include \"lib/moves.bfm\"\n#define TWICE @RIGHT*2\nadd ten: +*10 @TWICE -*0 x*3 a@ b"),
+            ("lib/moves.bfm", "#macro RIGHT\n  >+\n#end\n#define LEFT <"),
+        ]).unwrap();
+        assert_eq!(preprocessed.program.to_string(), "++++++++++>+>+");
+        assert_eq!(preprocessed.program.source_map().unwrap().files(), &["main.bfm", "lib/moves.bfm"]);
+
+        // each token points at where it was written, even inside a macro
+        let origins: Vec<_> = (9..12).map(|i| preprocessed.program.location(i).to_string()).collect();
+        assert_eq!(origins, vec!["main.bfm:3:10", "lib/moves.bfm:2:3", "lib/moves.bfm:2:4"]);
+    }
+
+    #[test]
+    fn reports_where_it_went_wrong() {
+        let error = |files: &[(&str, &str)]| run(files).err().map(|e| e.to_string());
+        assert_eq!(error(&[("main.bfm", "+\n  @MISSING")]), Some("unknown macro `@MISSING` in main.bfm at line 2, column 3".to_string()));
+        assert_eq!(error(&[("main.bfm", "#define LOOP [@LOOP]\n@LOOP")]), Some("macro `@LOOP` used inside itself in main.bfm at line 1, column 15".to_string()));
+        assert_eq!(error(&[("main.bfm", "#include \"main.bfm\"")]), Some("file included inside itself in main.bfm at line 1, column 1".to_string()));
+        assert_eq!(error(&[("main.bfm", "#include \"./main.bfm\"")]), Some("file included inside itself in main.bfm at line 1, column 1".to_string()));
+        assert_eq!(error(&[("main.bfm", "+\n#include \"sub/../main.bfm\""), ("sub/../main.bfm", "")]),
+            Some("file included inside itself in main.bfm at line 2, column 1".to_string()));
+        assert_eq!(error(&[("main.bfm", "#inlcude \"lib.bfm\"")]), Some("unknown directive `#inlcude` in main.bfm at line 1, column 1".to_string()));
+        assert_eq!(error(&[("main.bfm", "#macro OPEN\n[")]), Some("macro `OPEN` is never closed with `#end` in main.bfm at line 1, column 1".to_string()));
+        assert_eq!(error(&[("main.bfm", "+*99999999999999999999999")]), Some("repetition count too large in main.bfm at line 1, column 2".to_string()));
+        assert!(error(&[("main.bfm", "#include \"gone.bfm\"")]).unwrap().starts_with("couldn't read gone.bfm"));
+    }
+
+    #[test]
+    fn limits_include_depth() {
+        // `link` acts like a symlink to the directory it is in
+        let result = preprocess_with(&"main.bfm", &mut |_: &Path| Ok(b"#include \"link/main.bfm\"".to_vec()));
+        match result {
+            Err(PreprocessError::RecursiveInclude { file, line: 1, column: 1 }) => assert_eq!(file.matches("link").count(), super::MAX_INCLUDE_DEPTH - 1),
+            other => panic!("unexpected {:?}", other.err()),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn catches_includes_through_symlinks() {
+        let dir = std::env::temp_dir().join(format!("bft-preprocess-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("main.bfm"), "+\n#include \"link/main.bfm\"").unwrap();
+        std::os::unix::fs::symlink(".", dir.join("link")).unwrap();
+
+        let error = super::preprocess(&dir.join("main.bfm")).err().map(|e| e.to_string());
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(error, Some(format!("file included inside itself in {} at line 2, column 1", dir.join("main.bfm").display())));
+    }
+
+    #[test]
+    fn limits_expansion() {
+        let error = |source| run(&[("main.bfm", source)]).err().map(|e| e.to_string());
+        assert_eq!(error("+*100000000000"), Some("repetition count too large in main.bfm at line 1, column 2".to_string()));
+        assert_eq!(error("#define A +*65536\n@A*65536"), Some("repetition count too large in main.bfm at line 1, column 11".to_string()));
+        assert_eq!(error("#define E\n#define F @E*10000000\n@F*10000000"), Some("repetition count too large in main.bfm at line 2, column 11".to_string()));
+    }
+}
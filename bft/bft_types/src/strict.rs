@@ -0,0 +1,131 @@
+//! Warnings about source that probably doesn't do what its author meant,
+//! for parsing in strict mode.
+//!
+//! Every character that isn't one of the eight instructions is a comment,
+//! so a program pasted through a word processor can quietly lose an
+//! instruction to a lookalike, like an en dash standing in for `-`, and
+//! a comment written as ordinary prose can quietly gain the commas, full
+//! stops and hyphens of its punctuation.
+
+use crate::RawInstruction;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+static LOOKALIKES: &[(char, RawInstruction)] = &[
+    ('\u{203A}', RawInstruction::IncrementPointer), // ›
+    ('\u{232A}', RawInstruction::IncrementPointer), // 〉
+    ('\u{27E9}', RawInstruction::IncrementPointer), // ⟩
+    ('\u{FE65}', RawInstruction::IncrementPointer), // ﹥
+    ('\u{FF1E}', RawInstruction::IncrementPointer), // ＞
+    ('\u{2039}', RawInstruction::DecrementPointer), // ‹
+    ('\u{2329}', RawInstruction::DecrementPointer), // 〈
+    ('\u{27E8}', RawInstruction::DecrementPointer), // ⟨
+    ('\u{FE64}', RawInstruction::DecrementPointer), // ﹤
+    ('\u{FF1C}', RawInstruction::DecrementPointer), // ＜
+    ('\u{207A}', RawInstruction::Increment), // ⁺
+    ('\u{208A}', RawInstruction::Increment), // ₊
+    ('\u{2795}', RawInstruction::Increment), // ➕
+    ('\u{FE62}', RawInstruction::Increment), // ﹢
+    ('\u{FF0B}', RawInstruction::Increment), // ＋
+    ('\u{2010}', RawInstruction::Decrement), // ‐
+    ('\u{2011}', RawInstruction::Decrement), // ‑
+    ('\u{2012}', RawInstruction::Decrement), // ‒
+    ('\u{2013}', RawInstruction::Decrement), // –
+    ('\u{2014}', RawInstruction::Decrement), // —
+    ('\u{2015}', RawInstruction::Decrement), // ―
+    ('\u{207B}', RawInstruction::Decrement), // ⁻
+    ('\u{208B}', RawInstruction::Decrement), // ₋
+    ('\u{2212}', RawInstruction::Decrement), // −
+    ('\u{2796}', RawInstruction::Decrement), // ➖
+    ('\u{FE63}', RawInstruction::Decrement), // ﹣
+    ('\u{FF0D}', RawInstruction::Decrement), // －
+    ('\u{2024}', RawInstruction::Output), // ․
+    ('\u{3002}', RawInstruction::Output), // 。
+    ('\u{FE52}', RawInstruction::Output), // ﹒
+    ('\u{FF0E}', RawInstruction::Output), // ．
+    ('\u{201A}', RawInstruction::Input), // ‚
+    ('\u{3001}', RawInstruction::Input), // 、
+    ('\u{FE50}', RawInstruction::Input), // ﹐
+    ('\u{FF0C}', RawInstruction::Input), // ，
+    ('\u{27E6}', RawInstruction::StartLoop), // ⟦
+    ('\u{3014}', RawInstruction::StartLoop), // 〔
+    ('\u{FF3B}', RawInstruction::StartLoop), // ［
+    ('\u{27E7}', RawInstruction::EndLoop), // ⟧
+    ('\u{3015}', RawInstruction::EndLoop), // 〕
+    ('\u{FF3D}', RawInstruction::EndLoop), // ］
+];
+
+/// The instructions that double as punctuation in prose.
+static PUNCTUATION: &[char] = &[',', '.', '-'];
+
+/// Something suspicious found while parsing in strict mode, which doesn't
+/// stop the program from loading.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ParseWarning {
+    /// A comment character that looks like an instruction.
+    Lookalike { line: usize, column: usize, found: char, instruction: RawInstruction },
+
+    /// An instruction that looks like punctuation in a sentence, such as
+    /// the comma in "Hello, world".
+    InProse { line: usize, column: usize, instruction: RawInstruction },
+}
+
+impl ParseWarning {
+    /// The line the warning is about, counting from one.
+    pub fn line(&self) -> usize {
+        match self {
+            ParseWarning::Lookalike { line, .. } | ParseWarning::InProse { line, .. } => *line,
+        }
+    }
+
+    /// The column the warning is about, counting from one.
+    pub fn column(&self) -> usize {
+        match self {
+            ParseWarning::Lookalike { column, .. } | ParseWarning::InProse { column, .. } => *column,
+        }
+    }
+}
+
+impl fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseWarning::Lookalike { line, column, found, instruction } => write!(
+                f, "`{}` (U+{:04X}) at line {}, column {} looks like `{}`, but is a comment",
+                found, *found as u32, line, column, instruction,
+            ),
+            ParseWarning::InProse { line, column, instruction } => write!(
+                f, "`{}` at line {}, column {} is run as an instruction, but looks like part of a comment",
+                instruction, line, column,
+            ),
+        }
+    }
+}
+
+/// Finds anything suspicious on a single (zero-indexed) line of source.
+///
+/// Punctuation counts as prose when it comes straight after a letter and
+/// isn't followed by a digit or another instruction, so `well-known` and
+/// `world.` are caught but `[-]` and `3.5` are not.
+pub(crate) fn check_line(row: usize, line: &str) -> Vec<ParseWarning> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut warnings = vec![];
+
+    for (col, &c) in chars.iter().enumerate() {
+        let (line, column) = (row + 1, col + 1);
+        if let Some((_, instruction)) = LOOKALIKES.iter().find(|(lookalike, _)| *lookalike == c) {
+            warnings.push(ParseWarning::Lookalike { line, column, found: c, instruction: *instruction });
+            continue;
+        }
+
+        let after_letter = col > 0 && chars[col - 1].is_alphabetic();
+        let before_code = chars.get(col + 1)
+            .is_some_and(|&next| next.is_ascii_digit() || RawInstruction::from_char(next).is_some());
+        if PUNCTUATION.contains(&c) && after_letter && !before_code {
+            let instruction = RawInstruction::from_char(c).expect("punctuation is made of instructions");
+            warnings.push(ParseWarning::InProse { line, column, instruction });
+        }
+    }
+
+    warnings
+}
@@ -0,0 +1,245 @@
+//! A thin wrapper for running brainfuck somewhere without a filesystem or
+//! a terminal, such as a browser.
+//!
+//! Programs go in and output comes out as plain bytes, with [run] for Rust
+//! callers and a handful of `extern "C"` functions for everyone else. The
+//! C-ABI only passes integers and pointers, so it can be called directly
+//! from JavaScript once built for `wasm32-unknown-unknown`:
+//!
+//! ```text
+//! cargo build -p bft_wasm --release --target wasm32-unknown-unknown
+//! ```
+//!
+//! The caller copies the source and input into memory from [bft_alloc],
+//! calls [bft_run], reads the results through the `bft_run_*` accessors,
+//! and finally hands everything back with [bft_free] and [bft_run_free].
+
+use bft_interp::{BrainfuckInterpreter, InterpError};
+use bft_types::{BrainfuckProgram, ParseError};
+use std::fmt;
+use std::ptr;
+use std::slice;
+
+/// Why a program could not be run to completion.
+#[derive(Debug, PartialEq)]
+pub struct RunError {
+    pub message: String,
+
+    /// The line of the offending character or instruction, counting from one.
+    pub line: usize,
+
+    /// The column of the offending character or instruction, counting from one.
+    pub column: usize,
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.message.fmt(f)
+    }
+}
+
+impl From<InterpError> for RunError {
+    fn from(error: InterpError) -> Self {
+        let (line, column) = error.location();
+        RunError { message: error.to_string(), line, column }
+    }
+}
+
+impl From<ParseError> for RunError {
+    fn from(error: ParseError) -> Self {
+        let (line, column) = error.location().unwrap_or((0, 0));
+        RunError { message: error.to_string(), line, column }
+    }
+}
+
+/// The outcome of [run]: everything the program wrote, and the error
+/// that stopped it, if any.
+pub struct Run {
+    pub output: Vec<u8>,
+    pub error: Option<RunError>,
+}
+
+/// Runs UTF-8 brainfuck source on a growable tape, feeding it `input`.
+/// With a `step_limit`, programs that execute more instructions than
+/// that are stopped, so that an endless loop cannot hang the caller.
+///
+/// ```
+/// let run = bft_wasm::run(b",[.,]", b"echo", None);
+/// assert_eq!(run.output, b"echo");
+/// assert!(run.error.is_none());
+/// ```
+pub fn run(source: &[u8], mut input: &[u8], step_limit: Option<u64>) -> Run {
+    let mut output = vec![];
+    let result = BrainfuckProgram::from_bytes(&"playground", source)
+        .map_err(RunError::from)
+        .and_then(|program| {
+            let mut interp: BrainfuckInterpreter<u8> = BrainfuckInterpreter::new(0, true);
+            interp.set_step_limit(step_limit);
+            interp.interpret(&program, &mut input, &mut output).map_err(RunError::from)
+        });
+
+    Run { output, error: result.err() }
+}
+
+/// Allocates `len` bytes for the caller to fill, such as with the source
+/// and input for [bft_run].
+#[no_mangle]
+pub extern "C" fn bft_alloc(len: usize) -> *mut u8 {
+    let mut buffer = Vec::<u8>::with_capacity(len);
+    let ptr = buffer.as_mut_ptr();
+    std::mem::forget(buffer);
+    ptr
+}
+
+/// Frees memory from [bft_alloc].
+///
+/// # Safety
+///
+/// `ptr` must have come from [bft_alloc] with the same `len`, and must not
+/// be used again.
+#[no_mangle]
+pub unsafe extern "C" fn bft_free(ptr: *mut u8, len: usize) {
+    if !ptr.is_null() {
+        drop(Vec::from_raw_parts(ptr, 0, len));
+    }
+}
+
+/// Runs the source in `source[..source_len]` on the input in
+/// `input[..input_len]`, as [run] does. A `step_limit` of zero means
+/// no limit. The result must be freed with [bft_run_free].
+///
+/// # Safety
+///
+/// Both pointers must be valid for reads of their lengths, or may be
+/// null if their length is zero.
+#[no_mangle]
+pub unsafe extern "C" fn bft_run(source: *const u8, source_len: usize, input: *const u8, input_len: usize, step_limit: u64) -> *mut Run {
+    let source = bytes(source, source_len);
+    let input = bytes(input, input_len);
+    let step_limit = if step_limit == 0 { None } else { Some(step_limit) };
+    Box::into_raw(Box::new(run(source, input, step_limit)))
+}
+
+unsafe fn bytes<'a>(ptr: *const u8, len: usize) -> &'a [u8] {
+    if len == 0 { &[] } else { slice::from_raw_parts(ptr, len) }
+}
+
+/// Gets a pointer to the output of a [Run], valid until it is freed.
+///
+/// # Safety
+///
+/// `run` must have come from [bft_run] and not yet been freed.
+#[no_mangle]
+pub unsafe extern "C" fn bft_run_output(run: *const Run) -> *const u8 {
+    (*run).output.as_ptr()
+}
+
+/// Gets the number of bytes of output in a [Run].
+///
+/// # Safety
+///
+/// `run` must have come from [bft_run] and not yet been freed.
+#[no_mangle]
+pub unsafe extern "C" fn bft_run_output_len(run: *const Run) -> usize {
+    (*run).output.len()
+}
+
+/// Gets a pointer to the UTF-8 error message of a failed [Run], or null
+/// if it succeeded. The message is valid until the run is freed.
+///
+/// # Safety
+///
+/// `run` must have come from [bft_run] and not yet been freed.
+#[no_mangle]
+pub unsafe extern "C" fn bft_run_error(run: *const Run) -> *const u8 {
+    (*run).error.as_ref().map_or(ptr::null(), |e| e.message.as_ptr())
+}
+
+/// Gets the length of the error message of a [Run], or zero if it succeeded.
+///
+/// # Safety
+///
+/// `run` must have come from [bft_run] and not yet been freed.
+#[no_mangle]
+pub unsafe extern "C" fn bft_run_error_len(run: *const Run) -> usize {
+    (*run).error.as_ref().map_or(0, |e| e.message.len())
+}
+
+/// Gets the line of the error in a [Run], or zero if it succeeded.
+///
+/// # Safety
+///
+/// `run` must have come from [bft_run] and not yet been freed.
+#[no_mangle]
+pub unsafe extern "C" fn bft_run_error_line(run: *const Run) -> usize {
+    (*run).error.as_ref().map_or(0, |e| e.line)
+}
+
+/// Gets the column of the error in a [Run], or zero if it succeeded.
+///
+/// # Safety
+///
+/// `run` must have come from [bft_run] and not yet been freed.
+#[no_mangle]
+pub unsafe extern "C" fn bft_run_error_column(run: *const Run) -> usize {
+    (*run).error.as_ref().map_or(0, |e| e.column)
+}
+
+/// Frees a [Run], along with its output and error message.
+///
+/// # Safety
+///
+/// `run` must have come from [bft_run], or be null, and must not be used again.
+#[no_mangle]
+pub unsafe extern "C" fn bft_run_free(run: *mut Run) {
+    if !run.is_null() {
+        drop(Box::from_raw(run));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_location() {
+        let run = run(b"+.\n<", b"", None);
+        assert_eq!(run.output, [1]);
+        assert_eq!(run.error, Some(RunError {
+            message: "pointer moved left of the first cell at line 2, column 1".to_string(),
+            line: 2,
+            column: 1,
+        }));
+    }
+
+    #[test]
+    fn stops_endless_loops() {
+        let run = run(b"+[]", b"", Some(1000));
+        assert_eq!(run.error.map(|e| (e.line, e.column)), Some((1, 3)));
+    }
+
+    #[test]
+    fn round_trip_through_c_abi() {
+        unsafe {
+            let source = b",[.,]";
+            let buffer = bft_alloc(source.len());
+            ptr::copy_nonoverlapping(source.as_ptr(), buffer, source.len());
+
+            let run = bft_run(buffer, source.len(), b"hi".as_ptr(), 2, 0);
+            bft_free(buffer, source.len());
+            assert_eq!(bytes(bft_run_output(run), bft_run_output_len(run)), b"hi");
+            assert!(bft_run_error(run).is_null());
+            assert_eq!(bft_run_error_len(run), 0);
+            bft_run_free(run);
+
+            let run = bft_run(b"]".as_ptr(), 1, ptr::null(), 0, 0);
+            assert_eq!(bft_run_output_len(run), 0);
+            assert_eq!(
+                bytes(bft_run_error(run), bft_run_error_len(run)),
+                b"unmatched loop bracket at line 1, column 1"
+            );
+            assert_eq!((bft_run_error_line(run), bft_run_error_column(run)), (1, 1));
+            bft_run_free(run);
+        }
+    }
+}
@@ -0,0 +1,263 @@
+//! Runs many programs at once across a pool of threads, and reports on
+//! how each one went as JSON or JUnit XML.
+
+use crate::config::{CellType, Settings};
+use bft_interp::{ByteCell, IncDec};
+use serde::Serialize;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Instant;
+
+/// A program to run, and the file to feed it as input.
+#[derive(Debug, PartialEq)]
+pub struct Job {
+    pub program: PathBuf,
+    pub input: Option<PathBuf>,
+}
+
+/// Finds every `.b` or `.bf` program in a directory, each fed the `.in`
+/// file with the same name if there is one.
+pub fn jobs_in_dir(dir: &Path) -> io::Result<Vec<Job>> {
+    let mut programs = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if matches!(path.extension().and_then(|e| e.to_str()), Some("b" | "bf")) {
+            programs.push(path);
+        }
+    }
+    programs.sort();
+
+    Ok(programs.into_iter().map(|program| {
+        let input = program.with_extension("in");
+        Job { input: if input.is_file() { Some(input) } else { None }, program }
+    }).collect())
+}
+
+/// Reads a manifest listing a program, optionally followed by an input
+/// file, on each line. Paths are relative to the manifest, and blank lines
+/// and lines starting with `#` are skipped.
+pub fn jobs_in_manifest(manifest: &Path) -> io::Result<Vec<Job>> {
+    let base = manifest.parent().unwrap_or_else(|| Path::new(""));
+    let mut jobs = vec![];
+    for line in BufReader::new(fs::File::open(manifest)?).lines() {
+        let line = line?;
+        let mut paths = line.split_whitespace();
+        match paths.next() {
+            Some(program) if !program.starts_with('#') => jobs.push(Job {
+                program: base.join(program),
+                input: paths.next().map(|input| base.join(input)),
+            }),
+            _ => (),
+        }
+    }
+    Ok(jobs)
+}
+
+/// The limits every program in a batch runs under.
+pub struct Limits {
+    pub settings: Settings,
+    pub output: usize,
+}
+
+/// How a single program went.
+#[derive(Debug, Serialize)]
+pub struct Outcome {
+    pub program: String,
+    pub input: Option<String>,
+    pub passed: bool,
+    /// Everything the program wrote, with invalid UTF-8 replaced.
+    pub output: String,
+    pub error: Option<String>,
+    pub steps: u64,
+    pub seconds: f64,
+}
+
+/// Collects output, failing once there is more than a limit.
+struct Capped {
+    output: Vec<u8>,
+    limit: usize,
+}
+
+impl Write for Capped {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.output.len() + buf.len() > self.limit {
+            return Err(io::Error::other(format!("output limit of {} bytes exceeded", self.limit)));
+        }
+        self.output.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn run_job(job: &Job, limits: &Limits) -> Outcome {
+    match limits.settings.cell {
+        CellType::U8 => run_job_with::<u8>(job, limits),
+        CellType::U16 => run_job_with::<u16>(job, limits),
+        CellType::U32 => run_job_with::<u32>(job, limits),
+    }
+}
+
+fn run_job_with<T>(job: &Job, limits: &Limits) -> Outcome
+where T: IncDec, T: ByteCell, T: Default, T: Clone, T: PartialEq {
+    let start = Instant::now();
+    let mut interpreter = limits.settings.interpreter::<T>();
+    let mut output = Capped { output: vec![], limit: limits.output };
+
+    let result: Result<(), Box<dyn std::error::Error>> = (|| {
+        let (program, mut input) = limits.settings.read_program(&job.program)?;
+        if let Some(path) = &job.input {
+            input.extend(fs::read(path)?);
+        }
+        Ok(interpreter.interpret(&program, &mut &input[..], &mut output).map_err(|e| e.describe(&program))?)
+    })();
+
+    Outcome {
+        program: job.program.display().to_string(),
+        input: job.input.as_ref().map(|p| p.display().to_string()),
+        passed: result.is_ok(),
+        output: String::from_utf8_lossy(&output.output).into_owned(),
+        error: result.err().map(|e| e.to_string()),
+        steps: interpreter.steps(),
+        seconds: start.elapsed().as_secs_f64(),
+    }
+}
+
+/// Runs every job on up to `threads` threads at once, giving their
+/// outcomes in the same order.
+pub fn run(jobs: &[Job], limits: &Limits, threads: usize) -> Vec<Outcome> {
+    let next = AtomicUsize::new(0);
+    let outcomes = Mutex::new((0..jobs.len()).map(|_| None).collect::<Vec<_>>());
+
+    thread::scope(|scope| {
+        for _ in 0..threads.clamp(1, jobs.len().max(1)) {
+            scope.spawn(|| {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let job = match jobs.get(index) {
+                        Some(job) => job,
+                        None => return,
+                    };
+                    let outcome = run_job(job, limits);
+                    outcomes.lock().expect("no worker panics holding the lock")[index] = Some(outcome);
+                }
+            });
+        }
+    });
+
+    outcomes.into_inner().expect("no worker panics holding the lock")
+        .into_iter()
+        .map(|outcome| outcome.expect("every job is run"))
+        .collect()
+}
+
+#[derive(Serialize)]
+struct Report<'a> {
+    programs: usize,
+    failed: usize,
+    seconds: f64,
+    results: &'a [Outcome],
+}
+
+/// Writes the outcomes as a single JSON object, with totals alongside.
+/// `seconds` is how long the whole batch took, which is less than the time
+/// taken by each program added up when they ran at once.
+pub fn write_json(outcomes: &[Outcome], seconds: f64, out: &mut dyn Write) -> io::Result<()> {
+    let report = Report {
+        programs: outcomes.len(),
+        failed: outcomes.iter().filter(|o| !o.passed).count(),
+        seconds,
+        results: outcomes,
+    };
+    serde_json::to_writer_pretty(&mut *out, &report)?;
+    writeln!(out)
+}
+
+/// Escapes text for XML, replacing the control characters it can't hold.
+fn escape(text: &str) -> String {
+    text.chars().map(|c| match c {
+        '&' => "&amp;".to_string(),
+        '<' => "&lt;".to_string(),
+        '>' => "&gt;".to_string(),
+        '"' => "&quot;".to_string(),
+        '\t' | '\n' | '\r' => c.to_string(),
+        c if c.is_control() => char::REPLACEMENT_CHARACTER.to_string(),
+        c => c.to_string(),
+    }).collect()
+}
+
+/// Writes the outcomes as a JUnit XML test suite, with a test case for
+/// each program. `seconds` is how long the whole batch took.
+pub fn write_junit(outcomes: &[Outcome], seconds: f64, out: &mut dyn Write) -> io::Result<()> {
+    let failed = outcomes.iter().filter(|o| !o.passed).count();
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(out, r#"<testsuite name="bft batch" tests="{}" failures="{}" time="{:.6}">"#, outcomes.len(), failed, seconds)?;
+    for outcome in outcomes {
+        writeln!(out, r#"  <testcase name="{}" classname="bft" time="{:.6}">"#, escape(&outcome.program), outcome.seconds)?;
+        if let Some(error) = &outcome.error {
+            writeln!(out, r#"    <failure message="{}"/>"#, escape(error))?;
+        }
+        writeln!(out, "    <system-out>{}</system-out>", escape(&outcome.output))?;
+        writeln!(out, "  </testcase>")?;
+    }
+    writeln!(out, "</testsuite>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{jobs_in_dir, jobs_in_manifest, run, write_junit, Job, Limits};
+    use crate::config::{Config, Settings};
+    use std::fs;
+
+    fn limits() -> Limits {
+        let settings = Settings { tape_size: 16, fixed_tape: true, step_limit: Some(1000), ..Settings::from(&Config::default()) };
+        Limits { settings, output: 8 }
+    }
+
+    #[test]
+    fn runs_in_order_with_limits() {
+        let dir = std::env::temp_dir().join(format!("bft_batch_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (name, contents) in [("a.b", ",[.,]"), ("a.in", "hi"), ("b.bf", "+[]"), ("c.b", "+[.]"), ("notes.txt", "")] {
+            fs::write(dir.join(name), contents).unwrap();
+        }
+        fs::write(dir.join("manifest"), "# echo it twice\na.b a.in\n\nb.bf\nmissing.b").unwrap();
+
+        let jobs = jobs_in_dir(&dir).unwrap();
+        assert_eq!(jobs[0], Job { program: dir.join("a.b"), input: Some(dir.join("a.in")) });
+        assert_eq!(jobs.iter().map(|j| j.input.is_some()).collect::<Vec<_>>(), vec![true, false, false]);
+
+        let outcomes = run(&jobs, &limits(), 2);
+        assert_eq!(outcomes[0].output, "hi");
+        assert!(outcomes[0].passed);
+        assert_eq!(outcomes[1].error.as_deref(), Some("step limit exceeded at line 1, column 3"));
+        assert!(outcomes[2].error.as_ref().unwrap().contains("output limit of 8 bytes exceeded"));
+        assert_eq!(outcomes[2].output.len(), 8);
+
+        let manifest = jobs_in_manifest(&dir.join("manifest")).unwrap();
+        assert_eq!(manifest[0], jobs[0]);
+        let outcomes = run(&manifest, &limits(), 8);
+        assert_eq!(outcomes.iter().map(|o| o.passed).collect::<Vec<_>>(), vec![true, false, false]);
+        assert!(outcomes[2].program.ends_with("missing.b"));
+        assert!(outcomes[2].error.as_ref().unwrap().starts_with("couldn't read program"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn escapes_junit() {
+        let outcomes = run(&[Job { program: "<none>".into(), input: None }], &limits(), 1);
+        let mut xml = vec![];
+        write_junit(&outcomes, 0.5, &mut xml).unwrap();
+        let xml = String::from_utf8(xml).unwrap();
+        assert!(xml.contains(r#"<testsuite name="bft batch" tests="1" failures="1" time="0.500000">"#));
+        assert!(xml.contains(r#"<testcase name="&lt;none&gt;""#));
+        assert!(xml.contains(r#"<failure message="couldn't read program"#));
+    }
+}
@@ -0,0 +1,291 @@
+//! Defaults for the command line, read from a `.bftconfig` TOML file in
+//! the current directory or, failing that, the home directory. Flags
+//! given on the command line take precedence over the file.
+//!
+//! ```toml
+//! dialect = "standard"
+//! strict = false
+//!
+//! [tape]
+//! size = 30000
+//! fixed = false
+//!
+//! [cells]
+//! type = "u8"
+//! overflow = "wrap"
+//! eof = "zero"
+//!
+//! [limits]
+//! steps = 10000000
+//! output = 1048576
+//! detect_cycles = true
+//! ```
+
+use bft_interp::{BrainfuckInterpreter, ByteCell, Eof, IncDec, Overflow};
+use bft_types::preprocess::preprocess;
+use bft_types::{BrainfuckProgram, ParseError, ParseWarning};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::default::Default;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The name of the config file.
+pub const FILE_NAME: &str = ".bftconfig";
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub dialect: Dialect,
+    /// Warns about comments that look like they might hide a mistake.
+    pub strict: bool,
+    pub tape: Tape,
+    pub cells: Cells,
+    pub limits: Limits,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Tape {
+    pub size: usize,
+    pub fixed: bool,
+}
+
+impl Default for Tape {
+    fn default() -> Self {
+        Tape { size: 30000, fixed: false }
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Cells {
+    #[serde(rename = "type")]
+    pub cell: CellType,
+    pub overflow: Overflow,
+    pub eof: Eof,
+}
+
+/// Limits on each run, which are unlimited when left out. The output limit
+/// only applies to `batch`, which also picks its own limits if there are none.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Limits {
+    pub steps: Option<u64>,
+    pub output: Option<usize>,
+    /// Stops programs that are certain to loop forever.
+    pub detect_cycles: bool,
+}
+
+/// The type of each cell on the tape.
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CellType {
+    #[default]
+    U8,
+    U16,
+    U32,
+}
+
+/// A [ParseWarning], along with the name of the file it is about.
+pub type FileWarning = (String, ParseWarning);
+
+/// The flavour of brainfuck the source is written in.
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Dialect {
+    /// Every character other than the eight instructions is a comment.
+    #[default]
+    Standard,
+
+    /// As standard, except that the first `!` ends the program and
+    /// everything after it is fed to the program before its other input.
+    Bang,
+
+    /// Source with macros, repetitions and includes, which is expanded
+    /// before it is parsed.
+    Macro,
+}
+
+impl Dialect {
+    /// Reads a program, along with any input embedded in it.
+    pub fn read_program(&self, path: &Path) -> Result<(BrainfuckProgram, Vec<u8>), ParseError> {
+        match self {
+            Dialect::Standard => return Ok((BrainfuckProgram::from_file(&path)?, vec![])),
+            Dialect::Macro => return Ok((preprocess(&path).map_err(ParseError::Preprocess)?.program, vec![])),
+            Dialect::Bang => (),
+        }
+
+        let (name, source) = read_source(path)?;
+        let (code, input) = self.split(&source);
+        Ok((BrainfuckProgram::from_bytes(&name, code)?, input))
+    }
+
+    /// Reads a program in strict mode, along with any input embedded in it
+    /// and warnings about anything in its code that looks like a mistake,
+    /// each with the name of the file it is about.
+    pub fn read_program_strict(&self, path: &Path) -> Result<(BrainfuckProgram, Vec<u8>, Vec<FileWarning>), ParseError> {
+        if *self == Dialect::Macro {
+            let preprocessed = preprocess(&path).map_err(ParseError::Preprocess)?;
+            return Ok((preprocessed.program, vec![], preprocessed.warnings));
+        }
+
+        let (name, source) = read_source(path)?;
+        let (code, input) = self.split(&source);
+        let (program, warnings) = BrainfuckProgram::from_bytes_strict(&name, code)?;
+        Ok((program, input, warnings.into_iter().map(|warning| (name.clone(), warning)).collect()))
+    }
+
+    /// Splits source into its code and the input embedded after it.
+    fn split<'a>(&self, source: &'a [u8]) -> (&'a [u8], Vec<u8>) {
+        match source.iter().position(|&b| b == b'!') {
+            Some(bang) if *self == Dialect::Bang => (&source[..bang], source[bang + 1..].to_vec()),
+            _ => (source, vec![]),
+        }
+    }
+}
+
+/// Reads the whole of a program's file, along with its name.
+fn read_source(path: &Path) -> Result<(String, Vec<u8>), ParseError> {
+    let name = path.file_name()
+        .ok_or_else(|| ParseError::NoFileName(path.to_path_buf()))?
+        .to_string_lossy().to_string();
+    Ok((name, fs::read(path)?))
+}
+
+/// The settings a program runs under, once the flags and the config file
+/// have been combined.
+#[derive(Clone, Copy)]
+pub struct Settings {
+    pub tape_size: usize,
+    pub fixed_tape: bool,
+    pub cell: CellType,
+    pub overflow: Overflow,
+    pub eof: Eof,
+    pub dialect: Dialect,
+    pub strict: bool,
+    pub step_limit: Option<u64>,
+    pub detect_cycles: bool,
+}
+
+impl From<&Config> for Settings {
+    fn from(config: &Config) -> Self {
+        Settings {
+            tape_size: config.tape.size,
+            fixed_tape: config.tape.fixed,
+            cell: config.cells.cell,
+            overflow: config.cells.overflow,
+            eof: config.cells.eof,
+            dialect: config.dialect,
+            strict: config.strict,
+            step_limit: config.limits.steps,
+            detect_cycles: config.limits.detect_cycles,
+        }
+    }
+}
+
+impl Settings {
+    /// Creates an interpreter with these settings, whatever the type of its
+    /// cells.
+    pub fn interpreter<T>(&self) -> BrainfuckInterpreter<T>
+    where T: IncDec, T: ByteCell, T: Default, T: Clone, T: PartialEq {
+        let mut interpreter = BrainfuckInterpreter::new(self.tape_size, !self.fixed_tape);
+        interpreter.set_overflow(self.overflow);
+        interpreter.set_eof(self.eof);
+        interpreter.set_step_limit(self.step_limit);
+        interpreter.set_cycle_detection(self.detect_cycles);
+        interpreter
+    }
+
+    /// Reads a program in the configured dialect, printing any warnings
+    /// about it to stderr in strict mode.
+    pub fn read_program(&self, path: &Path) -> Result<(BrainfuckProgram, Vec<u8>), ParseError> {
+        if !self.strict {
+            return self.dialect.read_program(path);
+        }
+
+        let (program, input, warnings) = self.dialect.read_program_strict(path)?;
+        for (file, warning) in warnings {
+            eprintln!("warning: {}: {}", file, warning);
+        }
+        Ok((program, input))
+    }
+}
+
+/// Parses a value by the name it has in the config file, so that flags
+/// take the same values.
+pub fn from_name<T: DeserializeOwned>(name: &str) -> Result<T, String> {
+    serde_json::from_value(serde_json::Value::String(name.to_string())).map_err(|e| e.to_string())
+}
+
+/// Finds the config file to use, if there is one.
+pub fn path() -> Option<PathBuf> {
+    let home = std::env::var_os("HOME").map(|home| Path::new(&home).join(FILE_NAME));
+    Some(PathBuf::from(FILE_NAME)).into_iter().chain(home).find(|path| path.is_file())
+}
+
+/// Loads the config file, or the defaults if there is none.
+pub fn load() -> Result<Config, String> {
+    match path() {
+        Some(path) => fs::read(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|buf| toml::from_slice(&buf).map_err(|e| e.to_string()))
+            .map_err(|e| format!("couldn't read {}: {}", path.display(), e)),
+        None => Ok(Config::default()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_name, CellType, Config, Dialect};
+    use bft_interp::{Eof, Overflow};
+
+    #[test]
+    fn fills_in_defaults() {
+        let config: Config = toml::from_str("dialect = \"bang\"\n[cells]\ntype = \"u16\"\neof = \"minus-one\"\n").unwrap();
+        assert_eq!((config.dialect, config.cells.cell, config.cells.eof), (Dialect::Bang, CellType::U16, Eof::MinusOne));
+        assert_eq!((config.tape.size, config.cells.overflow, config.limits.steps), (30000, Overflow::Wrap, None));
+
+        // the file written by `init` reads back in
+        let written = toml::to_string(&Config::default()).unwrap();
+        assert!(toml::from_str::<Config>(&written).is_ok(), "{}", written);
+        assert!(toml::from_str::<Config>("[cells]\ntype = \"u64\"").is_err());
+    }
+
+    #[test]
+    fn parses_flags_by_name() {
+        assert_eq!(from_name::<Overflow>("error"), Ok(Overflow::Error));
+        assert_eq!(from_name::<CellType>("u32"), Ok(CellType::U32));
+        assert!(from_name::<Eof>("never").is_err());
+    }
+
+    #[test]
+    fn splits_bang_input() {
+        let path = std::env::temp_dir().join(format!("bft_bang_{}.b", std::process::id()));
+        std::fs::write(&path, ",[.,]!hi!").unwrap();
+        let (program, input) = Dialect::Bang.read_program(&path).unwrap();
+        assert_eq!((program.to_string(), input), (",[.,]".to_string(), b"hi!".to_vec()));
+        let (program, input) = Dialect::Standard.read_program(&path).unwrap();
+        assert_eq!((program.get_tokens().len(), input), (5, vec![]));
+
+        std::fs::write(&path, "Echo, \u{ff3b}.,]!hi").unwrap();
+        let (program, input, warnings) = Dialect::Bang.read_program_strict(&path).unwrap();
+        assert_eq!((program.to_string(), input), (",.,]".to_string(), b"hi".to_vec()));
+        assert_eq!(warnings.iter().map(|(_, w)| (w.line(), w.column())).collect::<Vec<_>>(), vec![(1, 5), (1, 7)]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn expands_macros() {
+        let dir = std::env::temp_dir().join(format!("bft_macro_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("lib.bfm"), "#define PRINT .\n#define NEXT >,\n").unwrap();
+        std::fs::write(dir.join("main.bfm"), "#include \"lib.bfm\"\n+*3 @PRINT Then, @NEXT").unwrap();
+
+        let (program, _) = Dialect::Macro.read_program(&dir.join("main.bfm")).unwrap();
+        assert_eq!(program.to_string(), "+++.,>,");
+        let (_, _, warnings) = Dialect::Macro.read_program_strict(&dir.join("main.bfm")).unwrap();
+        assert_eq!(warnings.iter().map(|(file, w)| (file.ends_with("main.bfm"), w.column())).collect::<Vec<_>>(), vec![(true, 16)]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
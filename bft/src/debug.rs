@@ -0,0 +1,197 @@
+//! An interactive command loop around the [Debugger].
+
+use bft_interp::debugger::{Debugger, Stop};
+use bft_interp::InterpError;
+use std::io::{self, BufRead, Write};
+
+const HELP: &str = "\
+commands:
+  s, step [n]             execute the next n instructions (default 1)
+  rs, reverse-step [n]    undo the last n instructions (default 1)
+  c, continue             run until a breakpoint or the end of the program
+  rc, reverse-continue    run backwards until a breakpoint or the start
+  b, break [FILE:]LINE[:COLUMN]
+                          toggle a breakpoint on an instruction
+  t, tape                 show the cells around the pointer
+  o, output               show everything the program has written
+  q, quit                 leave the debugger";
+
+/// Reads debugger commands from `commands` until it is closed or the
+/// user quits, reporting back to `out`.
+pub fn repl(debugger: &mut Debugger<u8>, commands: &mut dyn BufRead, out: &mut dyn Write, dump_radius: usize) -> io::Result<()> {
+    location(debugger, out)?;
+
+    loop {
+        write!(out, "(bft) ")?;
+        out.flush()?;
+
+        let mut line = String::new();
+        if commands.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+
+        let mut words = line.split_whitespace();
+        let command = match words.next() {
+            Some(command) => command,
+            None => continue,
+        };
+        let argument = words.next();
+        let count = argument.and_then(|n| n.parse().ok()).unwrap_or(1);
+
+        match command {
+            "s" | "step" => {
+                for _ in 0..count {
+                    match debugger.step() {
+                        Ok(true) => (),
+                        Ok(false) => break,
+                        Err(e) => { report(debugger, e, out)?; break },
+                    }
+                }
+                location(debugger, out)?;
+            },
+            "rs" | "reverse-step" => {
+                for _ in 0..count {
+                    if !debugger.reverse_step() {
+                        break;
+                    }
+                }
+                location(debugger, out)?;
+            },
+            "c" | "continue" => {
+                match debugger.run() {
+                    Ok(Stop::Breakpoint(_)) => writeln!(out, "hit breakpoint")?,
+                    Ok(_) => (),
+                    Err(e) => report(debugger, e, out)?,
+                }
+                location(debugger, out)?;
+            },
+            "rc" | "reverse-continue" => {
+                if let Stop::Breakpoint(_) = debugger.reverse_run() {
+                    writeln!(out, "hit breakpoint")?;
+                }
+                location(debugger, out)?;
+            },
+            "b" | "break" => match argument.and_then(|p| find_token(debugger, p)) {
+                Some(index) => {
+                    let state = if debugger.toggle_breakpoint(index) { "set" } else { "removed" };
+                    writeln!(out, "breakpoint {} at {}", state, debugger.program().location(index))?;
+                },
+                None => writeln!(out, "no instruction there, expected LINE, LINE:COLUMN or FILE:LINE[:COLUMN]")?,
+            },
+            "t" | "tape" => debugger.interpreter().dump(out, dump_radius)?,
+            "o" | "output" => writeln!(out, "{}", String::from_utf8_lossy(debugger.output()))?,
+            "q" | "quit" => return Ok(()),
+            "h" | "help" => writeln!(out, "{}", HELP)?,
+            other => writeln!(out, "unknown command \"{}\", try \"help\"", other)?,
+        }
+    }
+}
+
+/// Describes the instruction the debugger will execute next, and where it
+/// was written.
+fn location(debugger: &Debugger<u8>, out: &mut dyn Write) -> io::Result<()> {
+    let interp = debugger.interpreter();
+    let program = debugger.program();
+    match program.get_tokens().get(interp.program_counter()) {
+        Some(token) => writeln!(
+            out,
+            "step {}: next `{}` at {}, pointer at cell {} = {}",
+            debugger.steps(), token.instruction, program.location(interp.program_counter()),
+            interp.tape_index(), interp.tape()[interp.tape_index()],
+        ),
+        None => writeln!(out, "step {}: program finished", debugger.steps()),
+    }
+}
+
+fn report(debugger: &Debugger<u8>, error: InterpError, out: &mut dyn Write) -> io::Result<()> {
+    writeln!(out, "error: {}", error.describe(debugger.program()))
+}
+
+/// Finds the first instruction at `LINE` or `LINE:COLUMN`, in any file, or
+/// at `FILE:LINE[:COLUMN]` in a file whose path ends with `FILE`.
+fn find_token(debugger: &Debugger<u8>, position: &str) -> Option<usize> {
+    let mut parts: Vec<&str> = position.split(':').collect();
+    let file = match parts[0].parse::<usize>() {
+        Ok(_) => None,
+        Err(_) => Some(parts.remove(0)),
+    };
+    let line: usize = parts.first()?.parse().ok()?;
+    let column: Option<usize> = match parts.get(1) {
+        Some(column) => Some(column.parse().ok()?),
+        None => None,
+    };
+    if parts.len() > 2 {
+        return None;
+    }
+
+    let program = debugger.program();
+    (0..program.get_tokens().len()).find(|&index| {
+        let location = program.location(index);
+        location.line == line
+            && column.is_none_or(|c| location.column == c)
+            && file.is_none_or(|f| location.file.ends_with(f))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::repl;
+    use bft_interp::debugger::Debugger;
+    use bft_interp::BrainfuckInterpreter;
+    use bft_types::preprocess::preprocess_with;
+    use bft_types::BrainfuckProgram;
+
+    #[test]
+    fn reverse_continue_to_breakpoint() {
+        let program = BrainfuckProgram::from_string(&"./virtual", &"++\n[>+<-]");
+        let mut input = std::io::empty();
+        let mut debugger = Debugger::new(BrainfuckInterpreter::new(0, false), &program, &mut input);
+        let mut out = vec![];
+
+        let commands = "b 2:2\nc\nc\nc\nrc\nrs 2\nq\n";
+        repl(&mut debugger, &mut commands.as_bytes(), &mut out, 1).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = out.lines().map(|l| l.trim_start_matches("(bft) ")).collect();
+
+        assert_eq!(lines, vec![
+            "step 0: next `+` at ./virtual:1:1, pointer at cell 0 = 0",
+            "breakpoint set at ./virtual:2:2",
+            "hit breakpoint",
+            "step 3: next `>` at ./virtual:2:2, pointer at cell 0 = 2",
+            "hit breakpoint",
+            "step 8: next `>` at ./virtual:2:2, pointer at cell 0 = 1",
+            "step 13: program finished",
+            "hit breakpoint",
+            "step 8: next `>` at ./virtual:2:2, pointer at cell 0 = 1",
+            "step 6: next `-` at ./virtual:2:5, pointer at cell 0 = 2",
+            "",
+        ]);
+    }
+
+    #[test]
+    fn shows_the_file_each_instruction_came_from() {
+        let mut read = |path: &std::path::Path| Ok(match path.to_str() {
+            Some("main.bfm") => b"#include \"lib.bfm\"\n+ @LEFT".to_vec(),
+            _ => b"#define LEFT <".to_vec(),
+        });
+        let program = preprocess_with(&"main.bfm", &mut read).unwrap().program;
+        let mut input = std::io::empty();
+        let mut debugger = Debugger::new(BrainfuckInterpreter::new(1, false), &program, &mut input);
+        let mut out = vec![];
+
+        repl(&mut debugger, &mut "b lib.bfm:1\nb main.bfm:9\nc\nc\n".as_bytes(), &mut out, 1).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = out.lines().map(|l| l.trim_start_matches("(bft) ")).collect();
+
+        assert_eq!(lines, vec![
+            "step 0: next `+` at main.bfm:2:1, pointer at cell 0 = 0",
+            "breakpoint set at lib.bfm:1:14",
+            "no instruction there, expected LINE, LINE:COLUMN or FILE:LINE[:COLUMN]",
+            "hit breakpoint",
+            "step 1: next `<` at lib.bfm:1:14, pointer at cell 0 = 1",
+            "error: pointer moved left of the first cell at line 1, column 14 in lib.bfm",
+            "step 1: next `<` at lib.bfm:1:14, pointer at cell 0 = 1",
+            "",
+        ]);
+    }
+}
@@ -1,10 +1,627 @@
+//! Runs brainfuck programs from the command line.
+
 use bft_types::BrainfuckProgram;
-use bft_interp::BrainfuckInterpreter;
-use std::env;
+use bft_types::preprocess::preprocess;
+use bft_interp::bounds;
+use bft_interp::debugger::Debugger;
+use bft_interp::ir::IrProgram;
+use bft_interp::pipeline::{self, Pipeline, Stage, StageError};
+use bft_interp::trace::{self, TraceEntry};
+use bft_interp::minify::minify;
+use bft_interp::{BrainfuckInterpreter, ByteCell, Eof, IncDec, InterpError, Overflow};
+use bft_compiler::text::print_text;
+use config::{CellType, Config, Dialect, Settings};
+use serde::Serialize;
+use std::fmt::{Display, UpperHex};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+use std::time::Instant;
+use structopt::StructOpt;
+
+mod batch;
+mod config;
+mod debug;
+mod repl;
+mod tui;
+
+/// The number of cells either side of the pointer shown by `--dump`
+/// and the debugger.
+const DUMP_RADIUS: usize = 8;
+
+/// The number of bytes that may be waiting between two programs in `--pipe` mode.
+const PIPE_CAPACITY: usize = 4096;
+
+/// The number of cells the programs from `gen-print` are checked against,
+/// which is more than they ever use.
+const GEN_PRINT_CELLS: usize = 16;
+
+/// The number of instructions each program in a batch may run, unless
+/// another limit is given.
+const BATCH_STEP_LIMIT: u64 = 10_000_000;
+
+/// The number of bytes each program in a batch may write, unless another
+/// limit is given.
+const BATCH_OUTPUT_LIMIT: usize = 1 << 20;
+
+#[derive(StructOpt)]
+enum Command {
+    /// Runs brainfuck programs one after another on the same tape, reading
+    /// from stdin and writing to stdout.
+    #[structopt(name="run")]
+    Run {
+        /// The brainfuck programs to run, in order
+        #[structopt(parse(from_os_str), required = true)]
+        programs: Vec<PathBuf>,
+
+        /// Resets the tape before each program after the first
+        #[structopt(long = "reset", conflicts_with = "pipe")]
+        reset: bool,
+
+        /// Runs the programs at once, each on its own tape, feeding the output
+        /// of each into the input of the next like a Unix pipe
+        #[structopt(long = "pipe")]
+        pipe: bool,
+
+        /// Prints the cells around the pointer to stderr when the program exits or fails
+        #[structopt(long = "dump")]
+        dump: bool,
+
+        /// Records every executed instruction to the given file, one JSON object per line.
+        /// Only supported when running a single program
+        #[structopt(long = "trace", parse(from_os_str))]
+        trace: Option<PathBuf>,
+
+        #[structopt(flatten)]
+        options: InterpOptions,
+    },
+
+    /// Replays a trace against a program, reporting where their behaviour diverges.
+    #[structopt(name="verify")]
+    Verify {
+        /// The brainfuck program the trace was recorded from
+        #[structopt(parse(from_os_str))]
+        program: PathBuf,
+
+        /// The trace to verify
+        #[structopt(parse(from_os_str))]
+        trace: PathBuf,
+
+        #[structopt(flatten)]
+        options: InterpOptions,
+    },
+
+    /// Steps through a program interactively, forwards and backwards.
+    #[structopt(name="debug")]
+    Debug {
+        /// The brainfuck program to debug
+        #[structopt(parse(from_os_str))]
+        program: PathBuf,
+
+        /// A file to feed to the program as input, since stdin is used for commands
+        #[structopt(long = "input", parse(from_os_str))]
+        input: Option<PathBuf>,
+
+        #[structopt(flatten)]
+        options: InterpOptions,
+    },
+
+    /// Shows a program running full screen, with controls to play, pause and step it.
+    #[structopt(name="visualise")]
+    Visualise {
+        /// The brainfuck program to show
+        #[structopt(parse(from_os_str))]
+        program: PathBuf,
+
+        /// A file to feed to the program as input
+        #[structopt(long = "input", parse(from_os_str))]
+        input: Option<PathBuf>,
+
+        /// The number of instructions to run each second when playing
+        #[structopt(long = "speed", default_value = "10")]
+        speed: u32,
+
+        #[structopt(flatten)]
+        options: InterpOptions,
+    },
+
+    /// Runs brainfuck typed in line by line, keeping the tape between lines.
+    #[structopt(name="repl")]
+    Repl {
+        #[structopt(flatten)]
+        options: InterpOptions,
+    },
+
+    /// Compares two traces, reporting the first step at which they differ.
+    #[structopt(name="diff")]
+    Diff {
+        /// The trace to compare against
+        #[structopt(parse(from_os_str))]
+        expected: PathBuf,
+
+        /// The trace to compare
+        #[structopt(parse(from_os_str))]
+        actual: PathBuf,
+    },
+
+    /// Runs many programs in parallel, each with its own input, and reports on them all.
+    /// Prints a JSON report unless asked to write reports to files
+    #[structopt(name="batch")]
+    Batch {
+        /// A directory of `.b` or `.bf` programs, each fed the `.in` file with the same name if there is one,
+        /// or a manifest with a program and an optional input file on each line
+        #[structopt(parse(from_os_str))]
+        source: PathBuf,
+
+        /// Writes a JSON report to the given file
+        #[structopt(long = "json", parse(from_os_str))]
+        json: Option<PathBuf>,
+
+        /// Writes a JUnit XML report to the given file
+        #[structopt(long = "junit", parse(from_os_str))]
+        junit: Option<PathBuf>,
+
+        /// The number of programs to run at once, which defaults to the number of CPUs
+        #[structopt(long = "jobs")]
+        jobs: Option<usize>,
+
+        /// Stops each program once it writes more than this many bytes [default: 1048576]
+        #[structopt(long = "output-limit")]
+        output_limit: Option<usize>,
+
+        /// Exits successfully even if some programs fail
+        #[structopt(long = "allow-failures")]
+        allow_failures: bool,
+
+        #[structopt(flatten)]
+        options: InterpOptions,
+    },
+
+    /// Prints a program without its comments and dead code, after checking
+    /// that it still behaves the same.
+    #[structopt(name="minify")]
+    Minify {
+        /// The brainfuck program to minify
+        #[structopt(parse(from_os_str))]
+        program: PathBuf,
+
+        /// A file to feed to both programs when checking they behave the same
+        #[structopt(long = "input", parse(from_os_str))]
+        input: Option<PathBuf>,
+
+        /// Gives up checking after the original program runs this many instructions
+        #[structopt(long = "check-steps", default_value = "10000000")]
+        check_steps: u64,
+
+        #[structopt(flatten)]
+        options: InterpOptions,
+    },
+
+    /// Prints a program with its macros, repetitions and includes expanded.
+    #[structopt(name="expand")]
+    Expand {
+        /// The program to expand, written in the macro dialect
+        #[structopt(parse(from_os_str))]
+        program: PathBuf,
+
+        /// Prints each instruction on its own line, alongside the file, line
+        /// and column it was written at
+        #[structopt(long = "map")]
+        map: bool,
+    },
+
+    /// Prints a short brainfuck program that prints the given text.
+    #[structopt(name="gen-print")]
+    GenPrint {
+        /// The text for the program to print
+        text: String,
+    },
+
+    /// Generates a config file with the default settings in the current directory.
+    #[structopt(name="init")]
+    Init {
+        /// Overwrites the previous config, if one exists
+        #[structopt(long="force", short="f")]
+        force: bool,
+    },
+}
+
+/// Settings for the virtual machine the program runs on. Any left out are
+/// taken from the config file.
+#[derive(StructOpt)]
+struct InterpOptions {
+    /// The number of cells on the tape [default: 30000]
+    #[structopt(long = "tape-size")]
+    tape_size: Option<usize>,
+
+    /// Stops the tape from growing when the pointer moves past its end
+    #[structopt(long = "fixed-tape", overrides_with = "no-fixed-tape")]
+    fixed_tape: bool,
+
+    /// Lets the tape grow, even if the config file fixes it
+    #[structopt(long = "no-fixed-tape", overrides_with = "fixed-tape")]
+    no_fixed_tape: bool,
+
+    /// The type of each cell: u8, u16 or u32 [default: u8]
+    #[structopt(long = "cell", parse(try_from_str = config::from_name))]
+    cell: Option<CellType>,
+
+    /// What happens when a cell goes out of range: wrap or error [default: wrap]
+    #[structopt(long = "overflow", parse(try_from_str = config::from_name))]
+    overflow: Option<Overflow>,
+
+    /// What reading past the end of the input leaves in the cell: zero, unchanged
+    /// or minus-one [default: zero]
+    #[structopt(long = "eof", parse(try_from_str = config::from_name))]
+    eof: Option<Eof>,
+
+    /// The flavour of brainfuck: standard, bang, where everything after the
+    /// first `!` is input for the program, or macro, which is expanded as by
+    /// `expand` first [default: standard]
+    #[structopt(long = "dialect", parse(try_from_str = config::from_name))]
+    dialect: Option<Dialect>,
+
+    /// Warns about characters that look like instructions but aren't, and
+    /// instructions that look like punctuation in a comment
+    #[structopt(long = "strict", overrides_with = "no-strict")]
+    strict: bool,
+
+    /// Turns off strict mode, even if the config file turns it on
+    #[structopt(long = "no-strict", overrides_with = "strict")]
+    no_strict: bool,
+
+    /// Stops a program after it runs this many instructions
+    #[structopt(long = "step-limit")]
+    step_limit: Option<u64>,
+
+    /// Stops a program that comes back round a loop in exactly the same state,
+    /// since it will never finish
+    #[structopt(long = "detect-cycles", overrides_with = "no-detect-cycles")]
+    detect_cycles: bool,
+
+    /// Turns off cycle detection, even if the config file turns it on
+    #[structopt(long = "no-detect-cycles", overrides_with = "detect-cycles")]
+    no_detect_cycles: bool,
+}
+
+impl InterpOptions {
+    /// Combines the options with the config file, preferring the options.
+    fn settings(&self, config: &Config) -> Settings {
+        let defaults = Settings::from(config);
+        Settings {
+            tape_size: self.tape_size.unwrap_or(defaults.tape_size),
+            fixed_tape: flag(self.fixed_tape, self.no_fixed_tape).unwrap_or(defaults.fixed_tape),
+            cell: self.cell.unwrap_or(defaults.cell),
+            overflow: self.overflow.unwrap_or(defaults.overflow),
+            eof: self.eof.unwrap_or(defaults.eof),
+            dialect: self.dialect.unwrap_or(defaults.dialect),
+            strict: flag(self.strict, self.no_strict).unwrap_or(defaults.strict),
+            step_limit: self.step_limit.or(defaults.step_limit),
+            detect_cycles: flag(self.detect_cycles, self.no_detect_cycles).unwrap_or(defaults.detect_cycles),
+        }
+    }
+}
+
+/// Reads a pair of `--x` and `--no-x` flags, of which at most one is set.
+fn flag(on: bool, off: bool) -> Option<bool> {
+    match (on, off) {
+        (true, _) => Some(true),
+        (_, true) => Some(false),
+        _ => None,
+    }
+}
+
+/// A brainfuck toolkit.
+#[derive(StructOpt)]
+#[structopt(name="bft")]
+struct Cli {
+    #[structopt(subcommand)]
+    command: Command,
+}
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let file_name = env::args().nth(1).ok_or("No file name provided!")?;
-    let program = BrainfuckProgram::from_file(&file_name)?;
-    let mut interpreter: BrainfuckInterpreter<u8> = BrainfuckInterpreter::new(30_000, true);
-    Ok(interpreter.interpret(&program))
+    let args = Cli::from_args();
+    // `init` has to work even when the config it replaces is broken
+    let config = match args.command {
+        Command::Init{..} => Config::default(),
+        _ => config::load().unwrap_or_else(|error| fail(error)),
+    };
+
+    match args.command {
+        Command::Run{programs, reset, pipe, dump, trace, options} => {
+            if trace.is_some() && programs.len() > 1 {
+                fail("--trace can only record a single program");
+            }
+
+            let settings = options.settings(&config);
+            let mut embedded = vec![];
+            let mut loaded = vec![];
+            for path in &programs {
+                let (program, input) = read_program(&settings, path);
+                if loaded.is_empty() {
+                    embedded = input;
+                }
+                loaded.push(program);
+            }
+
+            if settings.fixed_tape {
+                // only programs starting on an empty tape begin at the first cell
+                let fresh = loaded.iter().enumerate().filter(|(index, _)| *index == 0 || pipe || reset);
+                for (_, program) in fresh {
+                    if let Ok(ir) = IrProgram::compile(program) {
+                        for failure in bounds::failures(&ir, settings.tape_size) {
+                            eprintln!("warning: {} will fail if it gets this far: {}", program.name, failure.describe(program));
+                        }
+                    }
+                }
+            }
+
+            let mut input = (&embedded[..]).chain(io::stdin());
+            let run = Run { programs: &loaded, reset, pipe, dump, trace, settings };
+            match settings.cell {
+                CellType::U8 => run.start::<u8>(&mut input),
+                CellType::U16 => run.start::<u16>(&mut input),
+                CellType::U32 => run.start::<u32>(&mut input),
+            }?;
+        },
+        Command::Verify{program, trace, options} => {
+            let settings = options.settings(&config);
+            let (program, _) = read_program(&settings, &program);
+            let trace = read_trace(&trace)?;
+            match trace::verify(&mut byte_interpreter("verify", &settings), &program, &trace) {
+                Some(divergence) => fail(divergence),
+                None => println!("Trace verified: {} steps match", trace.len()),
+            }
+        },
+        Command::Debug{program, input, options} => {
+            let settings = options.settings(&config);
+            let (program, embedded) = read_program(&settings, &program);
+            let mut input: Box<dyn io::Read> = match input {
+                Some(path) => Box::new(io::Cursor::new(embedded).chain(BufReader::new(File::open(path)?))),
+                None => Box::new(io::Cursor::new(embedded)),
+            };
+            let mut debugger = Debugger::new(byte_interpreter("debug", &settings), &program, &mut input);
+            debug::repl(&mut debugger, &mut io::stdin().lock(), &mut io::stdout(), DUMP_RADIUS)?;
+        },
+        Command::Visualise{program: path, input, speed, options} => {
+            let settings = options.settings(&config);
+            let (program, mut embedded) = read_program(&settings, &path);
+            if let Some(path) = input {
+                embedded.extend(std::fs::read(path)?);
+            }
+            // name each file the way the program's locations do
+            let files = match program.source_map() {
+                Some(sources) => sources.files().iter().map(|file| (file.clone(), PathBuf::from(file))).collect(),
+                None => vec![(program.name.clone(), path.clone())],
+            };
+            let mut sources = vec![];
+            for (name, file) in files {
+                sources.push((name, String::from_utf8_lossy(&std::fs::read(&file)?).into_owned()));
+            }
+            let mut reader = &embedded[..];
+            let mut debugger = Debugger::new(byte_interpreter("visualise", &settings), &program, &mut reader);
+            tui::show(&mut debugger, &sources, &embedded, speed)?;
+        },
+        Command::Repl{options} => {
+            let settings = options.settings(&config);
+            let (commands, out) = (&mut io::stdin().lock(), &mut io::stdout());
+            match settings.cell {
+                CellType::U8 => repl::repl(&mut settings.interpreter::<u8>(), settings.dialect, settings.strict, commands, out, DUMP_RADIUS),
+                CellType::U16 => repl::repl(&mut settings.interpreter::<u16>(), settings.dialect, settings.strict, commands, out, DUMP_RADIUS),
+                CellType::U32 => repl::repl(&mut settings.interpreter::<u32>(), settings.dialect, settings.strict, commands, out, DUMP_RADIUS),
+            }?;
+        },
+        Command::Diff{expected, actual} => {
+            let (expected, actual) = (read_trace(&expected)?, read_trace(&actual)?);
+            match trace::diff(&expected, &actual) {
+                Some(divergence) => fail(divergence),
+                None => println!("Traces are identical: {} steps", expected.len()),
+            }
+        },
+        Command::Batch{source, json, junit, jobs, output_limit, allow_failures, options} => {
+            let jobs_list = if source.is_dir() { batch::jobs_in_dir(&source)? } else { batch::jobs_in_manifest(&source)? };
+            let threads = jobs.unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()));
+            let mut settings = options.settings(&config);
+            settings.step_limit = settings.step_limit.or(Some(BATCH_STEP_LIMIT));
+            let limits = batch::Limits {
+                settings,
+                output: output_limit.or(config.limits.output).unwrap_or(BATCH_OUTPUT_LIMIT),
+            };
+            let start = Instant::now();
+            let outcomes = batch::run(&jobs_list, &limits, threads);
+            let seconds = start.elapsed().as_secs_f64();
+
+            if let Some(path) = &json {
+                batch::write_json(&outcomes, seconds, &mut BufWriter::new(File::create(path)?))?;
+            }
+            if let Some(path) = &junit {
+                batch::write_junit(&outcomes, seconds, &mut BufWriter::new(File::create(path)?))?;
+            }
+            if json.is_none() && junit.is_none() {
+                batch::write_json(&outcomes, seconds, &mut io::stdout())?;
+            }
+
+            let failed = outcomes.iter().filter(|o| !o.passed).count();
+            eprintln!("Ran {} programs: {} passed, {} failed", outcomes.len(), outcomes.len() - failed, failed);
+            if failed > 0 && !allow_failures {
+                process::exit(1);
+            }
+        },
+        Command::Minify{program, input, check_steps, options} => {
+            let settings = options.settings(&config);
+            let (program, embedded) = read_program(&settings, &program);
+            let minified = minify(&program).unwrap_or_else(|error| fail(located(&program, &error)));
+            let mut check_input = embedded.clone();
+            if let Some(path) = input {
+                check_input.extend(std::fs::read(path)?);
+            }
+
+            let run = |program: &BrainfuckProgram| {
+                let mut interpreter = byte_interpreter("minify", &settings);
+                interpreter.set_step_limit(Some(check_steps));
+                let mut output = vec![];
+                let result = interpreter.interpret(program, &mut &check_input[..], &mut output);
+                let used = interpreter.tape().iter().rposition(|&c| c != 0).map_or(0, |i| i + 1);
+                (result, output, interpreter.tape()[..used].to_vec(), interpreter.tape_index())
+            };
+
+            let (result, output, tape, index) = run(&program);
+            if let Err(InterpError::StepLimit { .. }) = result {
+                eprintln!("Couldn't check the minified program, since the original ran for more than {} steps", check_steps);
+            } else {
+                let (minified_result, minified_output, minified_tape, minified_index) = run(&minified);
+                if result.is_ok() != minified_result.is_ok() || (output, tape, index) != (minified_output, minified_tape, minified_index) {
+                    fail("The minified program behaves differently from the original");
+                }
+            }
+
+            if embedded.is_empty() {
+                println!("{}", minified);
+            } else {
+                // keep the input embedded in the program
+                print!("{}!", minified);
+                io::stdout().write_all(&embedded)?;
+            }
+        },
+        Command::Expand{program, map} => {
+            let preprocessed = preprocess(&program).unwrap_or_else(|error| fail(error));
+            if !map {
+                println!("{}", preprocessed.program);
+                return Ok(());
+            }
+
+            for (index, token) in preprocessed.program.get_tokens().iter().enumerate() {
+                println!("{}\t{}", token, preprocessed.program.location(index));
+            }
+        },
+        Command::GenPrint{text} => {
+            let code = print_text(text.as_bytes());
+
+            // make sure the program really does print the text before handing it out
+            let program = BrainfuckProgram::from_string(&"gen-print", &code);
+            let mut output = vec![];
+            if let Err(error) = BrainfuckInterpreter::<u8>::new(GEN_PRINT_CELLS, false).interpret(&program, &mut io::empty(), &mut output) {
+                fail(located(&program, &error));
+            }
+            if output != text.as_bytes() {
+                fail(format!("The generated program printed {:?} instead", String::from_utf8_lossy(&output)));
+            }
+
+            println!("{}", code);
+        },
+        Command::Init{force} => {
+            let path = Path::new(config::FILE_NAME);
+            if path.exists() && !force {
+                println!("Warning: there is already a config file in this folder.");
+                println!("If you wish to overwrite it, please supply the -f flag.");
+                return Ok(())
+            }
+
+            let serialized = toml::to_vec(&Config::default())?;
+            let mut out = File::create(path)?;
+            out.write_all(serialized.as_slice())?;
+        },
+    };
+
+    Ok(())
+}
+
+/// The programs for `run`, and how to run them.
+struct Run<'a> {
+    programs: &'a [BrainfuckProgram],
+    reset: bool,
+    pipe: bool,
+    dump: bool,
+    trace: Option<PathBuf>,
+    settings: Settings,
+}
+
+impl Run<'_> {
+    fn start<T>(self, input: &mut (dyn Read + Send)) -> Result<(), Box<dyn std::error::Error>>
+    where T: IncDec, T: ByteCell, T: Default, T: Clone, T: PartialEq, T: Send, T: Display, T: UpperHex, T: Serialize {
+        if self.pipe {
+            let mut stages: Vec<_> = self.programs.iter().map(|p| (p, self.settings.interpreter::<T>())).collect();
+            let result = pipeline::pipe(&mut stages, input, &mut io::stdout(), PIPE_CAPACITY);
+
+            if self.dump {
+                for (program, interpreter) in &stages {
+                    eprintln!("{}:", program.name);
+                    interpreter.dump(&mut io::stderr(), DUMP_RADIUS)?;
+                }
+            }
+
+            if let Err(error) = result {
+                fail(self.describe(&error));
+            }
+            return Ok(());
+        }
+
+        let mut interpreter = self.settings.interpreter::<T>();
+        let result = match &self.trace {
+            Some(path) => {
+                let mut trace = BufWriter::new(File::create(path)?);
+                let result = interpreter.trace(&self.programs[0], input, &mut io::stdout(), &mut trace);
+                trace.flush()?;
+                result.map_err(|error| located(&self.programs[0], &error))
+            },
+            None => {
+                let mut pipeline = Pipeline::new();
+                for (index, program) in self.programs.iter().enumerate() {
+                    let stage = Stage::new(program);
+                    pipeline = pipeline.stage(if self.reset && index > 0 { stage.reset() } else { stage });
+                }
+                pipeline.run(&mut interpreter, input, &mut io::stdout()).map_err(|error| self.describe(&error))
+            },
+        };
+
+        if self.dump {
+            interpreter.dump(&mut io::stderr(), DUMP_RADIUS)?;
+        }
+
+        if let Err(reason) = result {
+            fail(reason);
+        }
+        Ok(())
+    }
+
+    /// Describes a failed stage where it went wrong, naming the stage only
+    /// when there is more than one.
+    fn describe(&self, error: &StageError) -> String {
+        let description = located(&self.programs[error.stage], &error.source);
+        match self.programs.len() {
+            1 => description,
+            _ => format!("{} (stage {})", description, error.stage + 1),
+        }
+    }
+}
+
+/// Describes a runtime error, starting with the file, line and column of
+/// the instruction that failed.
+fn located(program: &BrainfuckProgram, error: &InterpError) -> String {
+    format!("{}: {}", program.location(error.token()), error)
+}
+
+/// Creates an interpreter for a command that only works on byte cells.
+fn byte_interpreter(command: &str, settings: &Settings) -> BrainfuckInterpreter<u8> {
+    if settings.cell != CellType::U8 {
+        fail(format!("{} only supports u8 cells", command));
+    }
+    settings.interpreter()
+}
+
+/// Reads a program, or reports why it couldn't be read and exits.
+fn read_program(settings: &Settings, path: &Path) -> (BrainfuckProgram, Vec<u8>) {
+    settings.read_program(path).unwrap_or_else(|error| fail(error))
+}
+
+fn read_trace(path: &Path) -> io::Result<Vec<TraceEntry<u8>>> {
+    trace::read_trace(&mut BufReader::new(File::open(path)?))
+}
+
+/// Reports a failed check and exits with a non-zero status.
+fn fail(reason: impl std::fmt::Display) -> ! {
+    eprintln!("{}", reason);
+    process::exit(1)
 }
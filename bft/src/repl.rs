@@ -0,0 +1,157 @@
+//! An interactive prompt that runs each line typed against the same tape.
+
+use crate::config::{Dialect, FileWarning};
+use bft_interp::{BrainfuckInterpreter, ByteCell, IncDec};
+use bft_types::{BrainfuckProgram, ParseError, RawInstruction};
+use std::collections::VecDeque;
+use std::fmt::{Display, UpperHex};
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+const HELP: &str = "\
+Lines are run as brainfuck on a tape that is kept between them. A line with
+an unclosed `[` carries on over the lines after it.
+
+commands:
+  :tape          show the cells around the pointer
+  :reset         empty the tape and move the pointer back to the start
+  :load FILE     run a program from a file on the tape
+  :input TEXT    add a line of text for `,` to read
+  :help          show this message
+  :quit          leave the prompt";
+
+/// Reads lines from `commands` until it is closed or the user quits, running
+/// each one on `interp` and reporting back to `out`. In `strict` mode, files
+/// loaded are checked for instructions that look like mistakes.
+pub fn repl<T>(interp: &mut BrainfuckInterpreter<T>, dialect: Dialect, strict: bool, commands: &mut dyn BufRead, out: &mut dyn Write, dump_radius: usize) -> io::Result<()>
+where T: IncDec, T: ByteCell, T: Default, T: Clone, T: PartialEq, T: Display, T: UpperHex {
+    let mut input = VecDeque::new();
+    let mut source = String::new();
+
+    loop {
+        write!(out, "{}", if source.is_empty() { "bf> " } else { "... " })?;
+        out.flush()?;
+
+        let mut line = String::new();
+        if commands.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+
+        if source.is_empty() && line.starts_with(':') {
+            let mut words = line[1..].trim().splitn(2, ' ');
+            match (words.next().unwrap_or(""), words.next()) {
+                ("tape", _) => interp.dump(out, dump_radius)?,
+                ("reset", _) => interp.reset(),
+                ("load", Some(path)) => match load(dialect, strict, Path::new(path.trim())) {
+                    Ok((program, embedded, warnings)) => {
+                        for (file, warning) in warnings {
+                            writeln!(out, "warning: {}: {}", file, warning)?;
+                        }
+                        input.extend(embedded);
+                        run(interp, &program, &mut input, out)?;
+                    },
+                    Err(e) => writeln!(out, "error: {}", e)?,
+                },
+                ("input", text) => {
+                    input.extend(text.unwrap_or("").bytes());
+                    input.push_back(b'\n');
+                },
+                ("help", _) => writeln!(out, "{}", HELP)?,
+                ("quit", _) => return Ok(()),
+                (other, _) => writeln!(out, "unknown command \":{}\", try \":help\"", other)?,
+            }
+            continue;
+        }
+
+        source.push_str(&line);
+        let program = BrainfuckProgram::from_string(&"<repl>", &source);
+        let depth = program.get_tokens().iter().fold(0, |depth: isize, token| match token.instruction {
+            RawInstruction::StartLoop => depth + 1,
+            RawInstruction::EndLoop => depth - 1,
+            _ => depth,
+        });
+        if depth > 0 {
+            continue;
+        }
+
+        source.clear();
+        run(interp, &program, &mut input, out)?;
+    }
+}
+
+/// Reads a program for `:load`, along with any warnings about it in strict mode.
+fn load(dialect: Dialect, strict: bool, path: &Path) -> Result<(BrainfuckProgram, Vec<u8>, Vec<FileWarning>), ParseError> {
+    if !strict {
+        let (program, embedded) = dialect.read_program(path)?;
+        return Ok((program, embedded, vec![]));
+    }
+    dialect.read_program_strict(path)
+}
+
+/// Runs a program on the tape, then shows what it wrote and where it left
+/// the pointer.
+fn run<T>(interp: &mut BrainfuckInterpreter<T>, program: &BrainfuckProgram, input: &mut VecDeque<u8>, out: &mut dyn Write) -> io::Result<()>
+where T: IncDec, T: ByteCell, T: Default, T: Clone, T: PartialEq, T: Display {
+    let mut output = vec![];
+    let result = interp.interpret(program, input, &mut output);
+    out.write_all(&output)?;
+    if !output.is_empty() && !output.ends_with(b"\n") {
+        writeln!(out)?;
+    }
+
+    if let Err(e) = result {
+        writeln!(out, "error: {}", e)?;
+    }
+    writeln!(out, "cell {} = {}", interp.tape_index(), interp.tape()[interp.tape_index()])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::repl;
+    use crate::config::Dialect;
+    use bft_interp::BrainfuckInterpreter;
+
+    #[test]
+    fn keeps_the_tape() {
+        let mut interp = BrainfuckInterpreter::<u8>::new(4, false);
+        let mut out = vec![];
+
+        let commands = "+++ the line is run\n>++\n[<\n+>-]<.\n:input AB\n,.>,.\n<<<\n:reset\n:nothing\n";
+        repl(&mut interp, Dialect::Standard, false, &mut commands.as_bytes(), &mut out, 1).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = out.lines().map(|l| l.trim_start_matches("bf> ").trim_start_matches("... ")).collect();
+
+        assert_eq!(lines, vec![
+            "cell 0 = 3",
+            "cell 1 = 2",
+            "\u{5}",
+            "cell 0 = 5",
+            "AB",
+            "cell 1 = 66",
+            "error: pointer moved left of the first cell at line 1, column 2",
+            "cell 0 = 65",
+            "unknown command \":nothing\", try \":help\"",
+            "",
+        ]);
+        assert_eq!(interp.tape(), &[0, 0, 0, 0]);
+    }
+    #[test]
+    fn loads_strictly() {
+        let path = std::env::temp_dir().join(format!("bft-repl-{}.b", std::process::id()));
+        std::fs::write(&path, "Hi, there\n+").unwrap();
+        let commands = format!(":load {}\n", path.display());
+
+        let loaded = |strict| {
+            let mut out = vec![];
+            repl(&mut BrainfuckInterpreter::<u8>::new(4, false), Dialect::Standard, strict, &mut commands.as_bytes(), &mut out, 1).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let (lax, strict) = (loaded(false), loaded(true));
+        std::fs::remove_file(&path).unwrap();
+
+        let name = path.file_name().unwrap().to_string_lossy();
+        assert!(!lax.contains("warning"));
+        assert!(strict.contains(&format!("warning: {}: `,` at line 1, column 3 is run as an instruction", name)), "{}", strict);
+        assert!(strict.contains("cell 0 = 1"));
+    }
+}
@@ -0,0 +1,350 @@
+//! A full-screen view of a program as it runs, showing the source, the
+//! tape, and the input and output, with controls to play, pause and step
+//! it either way.
+//!
+//! Each frame is first laid out as plain text by [render], and only then
+//! drawn to the terminal, so the layout can be checked without one.
+
+use bft_interp::debugger::Debugger;
+use crossterm::cursor::{self, MoveTo};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Print, SetAttribute};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+const HELP: &str = "space play/pause  \u{2192} step  \u{2190} back  +/- speed  r restart  q quit";
+
+/// The fastest playback can go, in steps per second.
+const MAX_SPEED: u32 = 1 << 20;
+
+/// How often the screen is redrawn while the program plays.
+const FRAME: Duration = Duration::from_millis(33);
+
+/// The width given to each cell in the tape view.
+const CELL_WIDTH: usize = 6;
+
+/// The rows left for the source once every other part of the view has its share.
+const FIXED_ROWS: usize = 13;
+
+/// The number of rows of output shown.
+const OUTPUT_ROWS: usize = 3;
+
+/// Whether the program is playing, and how quickly.
+pub struct Playback {
+    pub playing: bool,
+    /// In steps per second.
+    pub speed: u32,
+    /// An error to show in place of the help line.
+    pub message: Option<String>,
+}
+
+/// How a run of characters in a [Frame] is drawn.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Style {
+    /// Swaps the foreground and background, to pick out where the program is.
+    Reverse,
+    /// Fades text that has been dealt with.
+    Dim,
+}
+
+/// A screen's worth of text, where every character takes up one column.
+pub struct Frame {
+    pub rows: Vec<String>,
+    /// Runs of characters drawn in a style, as their row, first column and length.
+    pub styled: Vec<(usize, usize, usize, Style)>,
+}
+
+impl Frame {
+    fn push(&mut self, row: String, width: usize) {
+        self.rows.push(row.chars().take(width).collect());
+    }
+
+    fn title(&mut self, title: &str, width: usize) {
+        let line = format!("\u{2500}\u{2500} {} ", title);
+        let fill = width.saturating_sub(line.chars().count());
+        self.push(line + &"\u{2500}".repeat(fill), width);
+    }
+
+    fn style(&mut self, column: usize, length: usize, style: Style) {
+        self.styled.push((self.rows.len() - 1, column, length, style));
+    }
+}
+
+/// Replaces anything that wouldn't take up exactly one column.
+fn printable(c: char) -> char {
+    match c {
+        c if c.is_ascii_graphic() || c == ' ' => c,
+        c if c.is_whitespace() || c.is_control() => ' ',
+        _ => '\u{b7}',
+    }
+}
+
+/// Lays out a frame of `width` by `height` characters, for the program as
+/// the debugger has it. `sources` holds the text of each file the program
+/// was written in, by name, starting with the one it was loaded from, and
+/// `input` all the input it will be given.
+pub fn render(debugger: &Debugger<u8>, sources: &[(String, String)], input: &[u8], playback: &Playback, width: usize, height: usize) -> Frame {
+    let mut frame = Frame { rows: vec![], styled: vec![] };
+    let interp = debugger.interpreter();
+    let program = debugger.program();
+    let token = program.get_tokens().get(interp.program_counter());
+
+    let state = match token {
+        None => "finished",
+        Some(_) if playback.playing => "playing",
+        Some(_) => "paused",
+    };
+    let header = format!(
+        "{}  step {}  {}  {} steps/s",
+        debugger.program().name, debugger.steps(), state, playback.speed,
+    );
+    frame.push(header, width);
+
+    // show whichever file the current instruction was written in
+    let file = token.map(|_| program.location(interp.program_counter()).file);
+    let (name, source) = sources.iter()
+        .find(|(name, _)| Some(name.as_str()) == file)
+        .or_else(|| sources.first())
+        .map_or(("", ""), |(name, source)| (name.as_str(), source.as_str()));
+    frame.title(if sources.len() > 1 { name } else { "source" }, width);
+
+    // keep the current instruction in the middle, scrolling sideways for long lines
+    let lines: Vec<&str> = source.lines().collect();
+    let rows = height.saturating_sub(FIXED_ROWS).max(1);
+    let (line, column) = token.map_or((lines.len(), 0), |t| (t.line() - 1, t.column() - 1));
+    let first = line.saturating_sub(rows / 2).min(lines.len().saturating_sub(rows));
+    let shift = column.saturating_sub(width * 3 / 4);
+    for index in first..first + rows {
+        let text = lines.get(index).map_or(String::new(), |l| l.chars().skip(shift).map(printable).collect());
+        frame.push(text, width);
+        if index == line && token.is_some() {
+            frame.style(column - shift, 1, Style::Reverse);
+        }
+    }
+
+    // centre the pointer where the tape allows
+    frame.title("tape", width);
+    let tape = interp.tape();
+    let visible = (width / CELL_WIDTH).max(1);
+    let start = interp.tape_index().saturating_sub(visible / 2).min(tape.len().saturating_sub(visible));
+    let cells = start..(start + visible).min(tape.len());
+    let pointer = (interp.tape_index() - start) * CELL_WIDTH;
+    frame.push(cells.clone().map(|i| format!("{:>w$} ", i, w = CELL_WIDTH - 1)).collect(), width);
+    frame.push(cells.clone().map(|i| format!("{:>w$} ", tape[i], w = CELL_WIDTH - 1)).collect(), width);
+    frame.style(pointer, CELL_WIDTH - 1, Style::Reverse);
+    frame.push(cells.map(|i| format!("{:>w$} ", printable(tape[i] as char), w = CELL_WIDTH - 1)).collect(), width);
+    frame.style(pointer, CELL_WIDTH - 1, Style::Reverse);
+
+    frame.title("output", width);
+    let output = String::from_utf8_lossy(debugger.output());
+    let output: Vec<&str> = output.split('\n').collect();
+    for index in output.len().saturating_sub(OUTPUT_ROWS)..output.len().max(OUTPUT_ROWS) {
+        frame.push(output.get(index).map_or(String::new(), |l| l.chars().map(printable).collect()), width);
+    }
+
+    // what has been read already is faded, keeping some of it in view
+    frame.title("input", width);
+    let escaped: Vec<String> = input.iter().map(|b| b.escape_ascii().to_string()).collect();
+    let read = debugger.input_read().min(escaped.len());
+    let mut kept = 0;
+    let mut skipped = read;
+    while skipped > 0 && kept + escaped[skipped - 1].len() <= width / 3 {
+        skipped -= 1;
+        kept += escaped[skipped].len();
+    }
+    frame.push(escaped[skipped..].concat(), width);
+    frame.style(0, kept, Style::Dim);
+
+    frame.push(playback.message.clone().unwrap_or_else(|| HELP.to_string()), width);
+    frame
+}
+
+/// Acts on a key, returning `false` if it was to quit.
+pub fn key(key: KeyEvent, debugger: &mut Debugger<u8>, playback: &mut Playback) -> bool {
+    match key.code {
+        KeyCode::Char('q') | KeyCode::Esc => return false,
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return false,
+        KeyCode::Char(' ') => playback.playing = !playback.playing,
+        KeyCode::Right | KeyCode::Char('s') => {
+            playback.playing = false;
+            advance(debugger, playback, 1);
+        },
+        KeyCode::Left | KeyCode::Char('b') => {
+            playback.playing = false;
+            playback.message = None;
+            debugger.reverse_step();
+        },
+        KeyCode::Char('r') => {
+            playback.playing = false;
+            playback.message = None;
+            debugger.restart();
+        },
+        KeyCode::Char('+') | KeyCode::Char('=') => playback.speed = (playback.speed * 2).min(MAX_SPEED),
+        KeyCode::Char('-') => playback.speed = (playback.speed / 2).max(1),
+        _ => (),
+    }
+    true
+}
+
+/// Runs up to `steps` instructions, pausing at the end of the program or
+/// on an error.
+fn advance(debugger: &mut Debugger<u8>, playback: &mut Playback, steps: u64) {
+    for _ in 0..steps {
+        match debugger.step() {
+            Ok(true) => (),
+            Ok(false) => {
+                playback.playing = false;
+                return;
+            },
+            Err(e) => {
+                playback.message = Some(format!("error: {}", e.describe(debugger.program())));
+                playback.playing = false;
+                return;
+            },
+        }
+    }
+}
+
+/// Writes a frame over the whole screen.
+fn draw<W: Write>(out: &mut W, frame: &Frame) -> io::Result<()> {
+    for (row, text) in frame.rows.iter().enumerate() {
+        queue!(out, MoveTo(0, row as u16), Print(text), Clear(ClearType::UntilNewLine))?;
+    }
+    queue!(out, Clear(ClearType::FromCursorDown))?;
+
+    for &(row, column, length, style) in &frame.styled {
+        let text: String = frame.rows[row].chars().skip(column).take(length).collect();
+        let attribute = match style {
+            Style::Reverse => Attribute::Reverse,
+            Style::Dim => Attribute::Dim,
+        };
+        queue!(out, MoveTo(column as u16, row as u16), SetAttribute(attribute), Print(text), SetAttribute(Attribute::Reset))?;
+    }
+    out.flush()
+}
+
+/// Puts the terminal back the way it was when dropped, however the view ends.
+struct Screen;
+
+impl Screen {
+    fn enter() -> io::Result<Screen> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen, cursor::Hide)?;
+        Ok(Screen)
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), cursor::Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Takes over the terminal to show the program running until the user
+/// quits, starting paused at `speed` steps per second.
+pub fn show(debugger: &mut Debugger<u8>, sources: &[(String, String)], input: &[u8], speed: u32) -> io::Result<()> {
+    let _screen = Screen::enter()?;
+    let mut out = io::stdout();
+    let mut playback = Playback { playing: false, speed: speed.clamp(1, MAX_SPEED), message: None };
+    let mut owed = 0.0;
+    let mut last = Instant::now();
+
+    loop {
+        let (width, height) = terminal::size()?;
+        draw(&mut out, &render(debugger, sources, input, &playback, width as usize, height as usize))?;
+
+        // while paused, only a key press or a resize needs a redraw
+        let timeout = if playback.playing { FRAME } else { Duration::from_secs(60) };
+        if event::poll(timeout)? {
+            if let Event::Key(event) = event::read()? {
+                if event.kind == KeyEventKind::Press && !key(event, debugger, &mut playback) {
+                    return Ok(());
+                }
+            }
+        }
+
+        let now = Instant::now();
+        if playback.playing {
+            owed += playback.speed as f64 * (now - last).as_secs_f64();
+            let steps = owed as u64;
+            owed -= steps as f64;
+            advance(debugger, &mut playback, steps);
+        } else {
+            owed = 0.0;
+        }
+        last = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{key, render, Playback, Style};
+    use bft_interp::debugger::Debugger;
+    use bft_interp::BrainfuckInterpreter;
+    use bft_types::BrainfuckProgram;
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    fn press(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn lays_out_the_view() {
+        let source = "read two: ,>,\nthen print them .<.";
+        let program = BrainfuckProgram::from_string(&"two", &source);
+        let mut input = &b"hi\n"[..];
+        let mut debugger = Debugger::new(BrainfuckInterpreter::new(3, false), &program, &mut input);
+        let mut playback = Playback { playing: false, speed: 10, message: None };
+
+        for _ in 0..4 {
+            assert!(key(press(KeyCode::Right), &mut debugger, &mut playback));
+        }
+        let frame = render(&debugger, &[("two".to_string(), source.to_string())], b"hi\n", &playback, 24, 16);
+        assert_eq!(frame.rows, vec![
+            "two  step 4  paused  10 ",
+            "\u{2500}\u{2500} source \u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}",
+            "read two: ,>,",
+            "then print them .<.",
+            "",
+            "\u{2500}\u{2500} tape \u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}",
+            "    0     1     2 ",
+            "  104   105     0 ",
+            "    h     i       ",
+            "\u{2500}\u{2500} output \u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}",
+            "i",
+            "",
+            "",
+            "\u{2500}\u{2500} input \u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}\u{2500}",
+            "hi\\n",
+            "space play/pause  \u{2192} step",
+        ]);
+        assert_eq!(frame.styled, vec![(3, 17, 1, Style::Reverse), (7, 6, 5, Style::Reverse), (8, 6, 5, Style::Reverse), (14, 0, 2, Style::Dim)]);
+    }
+
+    #[test]
+    fn steps_both_ways() {
+        let program = BrainfuckProgram::from_string(&"loop", &"+[>+<-]<");
+        let mut input = std::io::empty();
+        let mut debugger = Debugger::new(BrainfuckInterpreter::<u8>::new(2, false), &program, &mut input);
+        let mut playback = Playback { playing: true, speed: 1, message: None };
+
+        assert!(key(press(KeyCode::Char('-')), &mut debugger, &mut playback));
+        assert!(key(press(KeyCode::Char('+')), &mut debugger, &mut playback));
+        assert_eq!(playback.speed, 2);
+
+        for _ in 0..8 {
+            key(press(KeyCode::Right), &mut debugger, &mut playback);
+        }
+        assert!(!playback.playing);
+        assert_eq!(playback.message.as_deref(), Some("error: pointer moved left of the first cell at line 1, column 8"));
+
+        key(press(KeyCode::Left), &mut debugger, &mut playback);
+        assert_eq!((debugger.steps(), playback.message.as_deref()), (6, None));
+        key(press(KeyCode::Char('r')), &mut debugger, &mut playback);
+        assert_eq!(debugger.steps(), 0);
+        assert!(!key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL), &mut debugger, &mut playback));
+    }
+}
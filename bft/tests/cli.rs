@@ -0,0 +1,63 @@
+//! Runs the `bft` binary the way a user would, checking what it prints.
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+/// Creates an empty directory holding the given files, to run `bft` in.
+fn workspace(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("bft-cli-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    for (file, contents) in files {
+        let path = dir.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+    dir
+}
+
+/// Runs `bft` in a directory, without picking up anyone's config file.
+fn bft(dir: &PathBuf, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_bft")).args(args).current_dir(dir).env("HOME", dir).output().unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn runtime_errors_show_where_they_happened() {
+    let dir = workspace("runtime", &[
+        ("a.b", "+\n<"),
+        ("main.bfm", "+\n#include \"lib/left.bfm\""),
+        ("lib/left.bfm", "\n  <"),
+    ]);
+    let underflow = "pointer moved left of the first cell at line 2, column 1";
+
+    let output = bft(&dir, &["run", "a.b"]);
+    assert!(!output.status.success());
+    assert_eq!(stderr(&output), format!("a.b:2:1: {}\n", underflow));
+
+    let output = bft(&dir, &["run", "--trace", "a.trace", "a.b"]);
+    assert_eq!(stderr(&output), format!("a.b:2:1: {}\n", underflow));
+
+    let output = bft(&dir, &["run", "--pipe", "a.b", "a.b"]);
+    assert_eq!(stderr(&output), format!("a.b:2:1: {} (stage 1)\n", underflow));
+
+    // the file and position come from where the instruction was written
+    let output = bft(&dir, &["run", "--dialect", "macro", "main.bfm"]);
+    assert_eq!(stderr(&output), "lib/left.bfm:2:3: pointer moved left of the first cell at line 2, column 3\n");
+    let output = bft(&dir, &["run", "--dialect", "macro", "--trace", "main.trace", "main.bfm"]);
+    assert_eq!(stderr(&output), "lib/left.bfm:2:3: pointer moved left of the first cell at line 2, column 3\n");
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn minify_reports_unmatched_brackets() {
+    let dir = workspace("minify", &[("open.b", "+[")]);
+    let output = bft(&dir, &["minify", "open.b"]);
+    assert!(!output.status.success());
+    assert_eq!(stderr(&output), "open.b:1:2: unmatched loop bracket at line 1, column 2\n");
+    fs::remove_dir_all(dir).unwrap();
+}